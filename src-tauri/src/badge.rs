@@ -0,0 +1,80 @@
+use tauri::AppHandle;
+
+/// Show the total unread mention count on the app icon: the dock badge on
+/// macOS, a taskbar overlay on Windows and the launcher count on Linux
+/// desktops that support it (libunity). 0 clears it.
+/// A no-op where there is nothing to badge, mobile included, so the
+/// frontend can call it unconditionally
+#[tauri::command]
+pub fn set_badge_count(app: AppHandle, count: u32) -> Result<(), String> {
+    #[cfg(desktop)]
+    {
+        use tauri::Manager;
+
+        let Some(window) = app.get_webview_window("main") else {
+            return Ok(());
+        };
+        platform::set_badge(&window, count)?;
+    }
+    #[cfg(mobile)]
+    let _ = (app, count);
+    Ok(())
+}
+
+/// `NSApp.dockTile.badgeLabel`
+#[cfg(target_os = "macos")]
+mod platform {
+    pub fn set_badge(window: &tauri::WebviewWindow, count: u32) -> Result<(), String> {
+        window
+            .set_badge_label((count > 0).then(|| count.to_string()))
+            .map_err(|e| format!("Failed to set dock badge: {}", e))
+    }
+}
+
+/// Taskbar overlay icon; Windows has no numeric badge, so a dot marks
+/// that there is something unread
+#[cfg(windows)]
+mod platform {
+    use tauri::image::Image;
+
+    /// Size of the overlay icon in pixels
+    const DOT_SIZE: u32 = 16;
+    /// Fill of the overlay dot, RGBA
+    const DOT_COLOR: [u8; 4] = [0xe0, 0x24, 0x24, 0xff];
+
+    pub fn set_badge(window: &tauri::WebviewWindow, count: u32) -> Result<(), String> {
+        let icon = (count > 0).then(|| Image::new_owned(dot(), DOT_SIZE, DOT_SIZE));
+        window
+            .set_overlay_icon(icon)
+            .map_err(|e| format!("Failed to set taskbar overlay: {}", e))
+    }
+
+    /// RGBA pixels of a filled circle on a transparent background
+    fn dot() -> Vec<u8> {
+        let radius = DOT_SIZE as f32 / 2.0;
+        (0..DOT_SIZE * DOT_SIZE)
+            .flat_map(|i| {
+                let dx = (i % DOT_SIZE) as f32 + 0.5 - radius;
+                let dy = (i / DOT_SIZE) as f32 + 0.5 - radius;
+                if dx * dx + dy * dy <= radius * radius {
+                    DOT_COLOR
+                } else {
+                    [0; 4]
+                }
+            })
+            .collect()
+    }
+}
+
+/// Unity launcher count, shown by docks that implement the LauncherEntry
+/// API; other desktops silently ignore it
+#[cfg(all(desktop, not(any(target_os = "macos", windows))))]
+mod platform {
+    pub fn set_badge(window: &tauri::WebviewWindow, count: u32) -> Result<(), String> {
+        if let Err(e) = window.set_badge_count((count > 0).then_some(i64::from(count))) {
+            // Support depends on the desktop, so this isn't worth an error
+            log::debug!("Launcher badge unavailable: {}", e);
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,7 @@
 pub mod update;
 
-pub use update::{check_for_updates, get_app_version};
+pub use update::{
+    check_for_updates, clear_skipped_update_version, download_update, get_app_version,
+    open_update_url, skip_update_version, start_update_polling, stop_update_polling,
+    UpdateClientState, UpdatePollingState,
+};
@@ -0,0 +1,185 @@
+mod irc_url;
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use irc_url::{parse_irc_url, IrcUrl};
+
+/// Identical links arriving this close together are one link delivered
+/// twice; Windows hands them to both the single-instance and the deep link
+/// handler
+const DUPLICATE_WINDOW: Duration = Duration::from_secs(2);
+
+/// IRC URLs received before the frontend asked for them, and the ones
+/// handled recently
+pub struct PendingDeepLinks {
+    /// `None` once the frontend has drained the buffer; later links go
+    /// straight out as events
+    pending: Mutex<Option<Vec<String>>>,
+    recent: Mutex<Vec<(String, Instant)>>,
+}
+
+impl Default for PendingDeepLinks {
+    fn default() -> Self {
+        Self {
+            pending: Mutex::new(Some(Vec::new())),
+            recent: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl PendingDeepLinks {
+    /// Buffer `url` if the frontend is not ready yet; returns false if it is
+    fn push(&self, url: &str) -> bool {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        match pending.as_mut() {
+            Some(urls) => {
+                urls.push(url.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drain the buffer and stop buffering from now on
+    fn take(&self) -> Vec<String> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.take().unwrap_or_default()
+    }
+
+    /// Whether `url` was already handled within `DUPLICATE_WINDOW` of
+    /// `now`; records it otherwise
+    fn seen_recently(&self, url: &str, now: Instant) -> bool {
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        recent.retain(|(_, at)| now.duration_since(*at) < DUPLICATE_WINDOW);
+        if recent.iter().any(|(seen, _)| seen == url) {
+            return true;
+        }
+        recent.push((url.to_string(), now));
+        false
+    }
+}
+
+/// Whether a launch argument or opened URL is an IRC link
+fn is_irc_url(arg: &str) -> bool {
+    let lower = arg.to_ascii_lowercase();
+    lower.starts_with("irc://") || lower.starts_with("ircs://")
+}
+
+/// The IRC URLs among launch arguments, in order, each only once
+/// Besides bare URLs this picks up flag values (`--url=irc://...`); the
+/// program path and other flags are skipped
+fn irc_urls_in_args<I, S>(args: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut urls: Vec<String> = Vec::new();
+    for arg in args {
+        let arg = arg.as_ref().trim();
+        let url = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with('-') => value.trim_matches('"'),
+            _ => arg,
+        };
+        if is_irc_url(url) && !urls.iter().any(|seen| seen == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
+/// Parse any IRC URLs among `urls` and hand them to the frontend as
+/// `open-irc-url` events, in order; anything else (program path, flags) is
+/// ignored, as are links already opened a moment ago.
+/// Until the frontend calls `take_pending_deep_links` they are buffered
+pub fn open_urls<I, S>(app: &AppHandle, urls: I)
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let state = app.state::<PendingDeepLinks>();
+    for url in irc_urls_in_args(urls) {
+        let url = url.as_str();
+        if state.seen_recently(url, Instant::now()) {
+            log::info!("Ignoring repeated IRC URL");
+            continue;
+        }
+        if state.push(url) {
+            log::info!("Buffering IRC URL until the frontend is ready");
+            continue;
+        }
+        if let Some(parsed) = parse_or_warn(url) {
+            log::info!("Opening IRC URL {}", parsed.address);
+            if let Err(e) = app.emit("open-irc-url", parsed) {
+                log::error!("Failed to emit open-irc-url: {}", e);
+            }
+        }
+    }
+}
+
+/// Parse `url`, logging and dropping it if it is invalid
+fn parse_or_warn(url: &str) -> Option<IrcUrl> {
+    parse_irc_url(url)
+        .map_err(|e| log::warn!("Ignoring invalid IRC URL {}: {}", url, e))
+        .ok()
+}
+
+/// Bring the existing main window to the front
+pub fn focus_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Hand over IRC URLs that arrived before the frontend was listening,
+/// parsed like the ones sent as `open-irc-url` events
+/// Call once the `open-irc-url` listener is registered
+#[tauri::command]
+pub fn take_pending_deep_links(state: State<'_, PendingDeepLinks>) -> Vec<IrcUrl> {
+    state
+        .take()
+        .iter()
+        .filter_map(|url| parse_or_warn(url))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_irc_urls_in_args() {
+        let args = [
+            "/usr/bin/obsidianirc",
+            "--minimized",
+            "irc://irc.libera.chat/#rust",
+            "--url=ircs://irc.oftc.net:6697/",
+            "--flag=value",
+            "IRC://irc.libera.chat/#rust",
+            "irc://irc.libera.chat/#rust",
+        ];
+        assert_eq!(
+            irc_urls_in_args(args),
+            vec![
+                "irc://irc.libera.chat/#rust",
+                "ircs://irc.oftc.net:6697/",
+                "IRC://irc.libera.chat/#rust",
+            ]
+        );
+        assert!(irc_urls_in_args(["obsidianirc"]).is_empty());
+    }
+
+    #[test]
+    fn test_seen_recently() {
+        let links = PendingDeepLinks::default();
+        let start = Instant::now();
+        assert!(!links.seen_recently("irc://a", start));
+        assert!(!links.seen_recently("irc://b", start));
+        assert!(links.seen_recently("irc://a", start + Duration::from_millis(500)));
+        // Opening the same link again later is deliberate
+        assert!(!links.seen_recently("irc://a", start + DUPLICATE_WINDOW * 2));
+    }
+}
@@ -0,0 +1,191 @@
+use serde::Serialize;
+
+use crate::socket::{join_host_port, parse_address, SocketError, Transport};
+
+/// Flags the IRC URL draft allows after the target list; not channels
+const URL_FLAGS: &[&str] = &["isnick", "isserver", "ischannel", "needkey", "needpass"];
+
+/// A channel to join from an IRC URL, with its key if one was given
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IrcUrlChannel {
+    pub name: String,
+    pub key: Option<String>,
+}
+
+/// An `irc://`/`ircs://` URL decoded into connect parameters
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IrcUrl {
+    /// `scheme://host:port`, ready to hand to `connect`
+    pub address: String,
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub channels: Vec<IrcUrlChannel>,
+}
+
+/// Parse an IRC URL (draft-butcher-irc-url)
+/// `irc[s]://host[:port]/[target[,target...]][,flags][?key=k1,k2]`.
+/// Targets may be percent-encoded and get a `#` prefix when they have no
+/// channel prefix; keys are matched to channels by position
+pub fn parse_irc_url(url: &str) -> Result<IrcUrl, SocketError> {
+    let (scheme, rest) = url
+        .split_once("://")
+        .ok_or_else(|| SocketError::InvalidAddress(format!("Not an IRC URL: {}", url)))?;
+    let scheme = scheme.to_ascii_lowercase();
+    if scheme != "irc" && scheme != "ircs" {
+        return Err(SocketError::InvalidAddress(format!("Not an IRC URL: {}", url)));
+    }
+
+    let (transport, host, port) = parse_address(&format!("{}://{}", scheme, rest))?;
+    if host.is_empty() {
+        return Err(SocketError::InvalidAddress(format!("Missing host in {}", url)));
+    }
+    let tls = transport == Transport::Tls;
+
+    // Everything after the authority: "/targets?query"
+    let after_authority = rest.find('/').map(|index| &rest[index + 1..]).unwrap_or("");
+    let (path, query) = after_authority.split_once('?').unwrap_or((after_authority, ""));
+
+    let mut flags = Vec::new();
+    let mut targets = Vec::new();
+    for item in path.split(',').map(percent_decode) {
+        if item.is_empty() {
+            continue;
+        }
+        if URL_FLAGS.contains(&item.to_ascii_lowercase().as_str()) {
+            flags.push(item.to_ascii_lowercase());
+        } else {
+            targets.push(item);
+        }
+    }
+
+    // A nick or server target is not something we join
+    let channels = if flags.iter().any(|flag| flag == "isnick" || flag == "isserver") {
+        Vec::new()
+    } else {
+        let keys = query_keys(query);
+        targets
+            .into_iter()
+            .enumerate()
+            .map(|(index, target)| IrcUrlChannel {
+                name: channel_name(target),
+                key: keys.get(index).cloned().filter(|key| !key.is_empty()),
+            })
+            .collect()
+    };
+
+    Ok(IrcUrl {
+        address: format!("{}://{}", scheme, join_host_port(&host, port)),
+        host,
+        port,
+        tls,
+        channels,
+    })
+}
+
+/// Add the default `#` prefix to targets that lack a channel prefix
+fn channel_name(target: String) -> String {
+    if target.starts_with(['#', '&', '+', '!']) {
+        target
+    } else {
+        format!("#{}", target)
+    }
+}
+
+/// Collect channel keys from `key=` query parameters, in order
+fn query_keys(query: &str) -> Vec<String> {
+    query
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .filter(|(name, _)| name.eq_ignore_ascii_case("key"))
+        .flat_map(|(_, value)| value.split(',').map(percent_decode).collect::<Vec<_>>())
+        .collect()
+}
+
+/// Decode `%XX` escapes; invalid escapes are kept as-is
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' && index + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(name: &str, key: Option<&str>) -> IrcUrlChannel {
+        IrcUrlChannel {
+            name: name.to_string(),
+            key: key.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_irc_url_basic() {
+        let url = parse_irc_url("ircs://irc.libera.chat/rust").unwrap();
+        assert_eq!(url.address, "ircs://irc.libera.chat:6697");
+        assert_eq!(url.host, "irc.libera.chat");
+        assert_eq!(url.port, 6697);
+        assert!(url.tls);
+        assert_eq!(url.channels, vec![channel("#rust", None)]);
+
+        let url = parse_irc_url("irc://irc.example.com:6668").unwrap();
+        assert_eq!(url.port, 6668);
+        assert!(!url.tls);
+        assert!(url.channels.is_empty());
+
+        let url = parse_irc_url("ircs://[2001:db8::1]:6697/rust").unwrap();
+        assert_eq!(url.address, "ircs://[2001:db8::1]:6697");
+        assert_eq!(url.host, "2001:db8::1");
+        assert_eq!(url.channels, vec![channel("#rust", None)]);
+    }
+
+    #[test]
+    fn test_parse_irc_url_channels_and_keys() {
+        let url = parse_irc_url("irc://irc.example.com/%23r%C3%BCst,%26local,#plain?key=s%2Fecret,").unwrap();
+        assert_eq!(
+            url.channels,
+            vec![
+                channel("#rüst", Some("s/ecret")),
+                channel("&local", None),
+                channel("#plain", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_irc_url_flags() {
+        let url = parse_irc_url("irc://irc.example.com/secret,needkey?key=hunter2").unwrap();
+        assert_eq!(url.channels, vec![channel("#secret", Some("hunter2"))]);
+
+        let url = parse_irc_url("irc://irc.example.com/somenick,isnick").unwrap();
+        assert!(url.channels.is_empty());
+    }
+
+    #[test]
+    fn test_parse_irc_url_rejects_other_schemes() {
+        assert!(parse_irc_url("https://example.com/").is_err());
+        assert!(parse_irc_url("irc.example.com").is_err());
+        assert!(parse_irc_url("irc:///chan").is_err());
+    }
+
+    #[test]
+    fn test_percent_decode_invalid() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%41"), "%zzA");
+    }
+}
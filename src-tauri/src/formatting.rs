@@ -0,0 +1,296 @@
+use serde::Serialize;
+
+/// mIRC color: `\x03` followed by `fg[,bg]` of up to two digits each
+const COLOR: char = '\x03';
+/// Hex color: `\x04` followed by `RRGGBB[,RRGGBB]`
+const HEX_COLOR: char = '\x04';
+const BOLD: char = '\x02';
+const ITALIC: char = '\x1d';
+const UNDERLINE: char = '\x1f';
+const STRIKETHROUGH: char = '\x1e';
+const MONOSPACE: char = '\x11';
+const REVERSE: char = '\x16';
+const RESET: char = '\x0f';
+
+/// mIRC color 99, which means the client's default color
+const DEFAULT_COLOR: u8 = 99;
+
+/// A text color: an mIRC palette index (0-98) or `#rrggbb`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Color {
+    Index(u8),
+    Hex(String),
+}
+
+/// Formatting in effect for a run of text
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub monospace: bool,
+    /// Swap foreground and background; left to the renderer, so `fg` and
+    /// `bg` stay as sent
+    pub reverse: bool,
+    /// `None` for the default color
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+}
+
+/// A run of text with one style, from `parse_formatting`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Segment {
+    pub text: String,
+    #[serde(flatten)]
+    pub style: Style,
+}
+
+/// `text` without IRC formatting codes, for notification previews and
+/// screen readers
+///
+/// Colors lose their parameters too, so `\x0304,12red` becomes `red`; a
+/// comma not followed by a background color stays in the text.
+#[tauri::command]
+pub fn strip_formatting(text: String) -> String {
+    strip(&text)
+}
+
+/// Split `text` into runs of equally formatted text, so the UI can render
+/// colors and styles without parsing control codes itself
+///
+/// Follows the mIRC/IRCv3 formatting conventions: toggles flip their
+/// style and may overlap freely, `\x03` alone clears both colors, a color
+/// without a background keeps the current one, 99 is the default color
+/// and `\x0f` resets everything. Empty runs are left out.
+#[tauri::command]
+pub fn parse_formatting(text: String) -> Vec<Segment> {
+    parse(&text)
+}
+
+/// The work behind `strip_formatting`, for backend callers
+pub fn strip(text: &str) -> String {
+    parse(text).into_iter().map(|segment| segment.text).collect()
+}
+
+/// The work behind `parse_formatting`
+pub fn parse(text: &str) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut style = Style::default();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            BOLD => style.bold = !style.bold,
+            ITALIC => style.italic = !style.italic,
+            UNDERLINE => style.underline = !style.underline,
+            STRIKETHROUGH => style.strikethrough = !style.strikethrough,
+            MONOSPACE => style.monospace = !style.monospace,
+            REVERSE => style.reverse = !style.reverse,
+            RESET => style = Style::default(),
+            COLOR | HEX_COLOR => {
+                let (colors, after) = color_params(rest, c == HEX_COLOR);
+                rest = after;
+                match colors {
+                    Some(change) => {
+                        style.fg = change.fg;
+                        if let Some(bg) = change.bg {
+                            style.bg = bg;
+                        }
+                    }
+                    None => {
+                        style.fg = None;
+                        style.bg = None;
+                    }
+                }
+            }
+            c => match segments.last_mut() {
+                Some(last) if last.style == style => last.text.push(c),
+                _ => segments.push(Segment {
+                    text: c.to_string(),
+                    style: style.clone(),
+                }),
+            },
+        }
+    }
+    segments
+}
+
+/// Colors set by the parameters of a color code
+struct ColorChange {
+    fg: Option<Color>,
+    /// `None` when the code has no background, which keeps the current one
+    bg: Option<Option<Color>>,
+}
+
+/// The `fg[,bg]` after a color code and the text after them; `None` when
+/// there is no foreground, which clears the colors
+fn color_params(text: &str, hex: bool) -> (Option<ColorChange>, &str) {
+    let Some((fg, after_fg)) = color(text, hex) else {
+        return (None, text);
+    };
+    match after_fg.strip_prefix(',').and_then(|bg| color(bg, hex)) {
+        Some((bg, after_bg)) => (Some(ColorChange { fg, bg: Some(bg) }), after_bg),
+        None => (Some(ColorChange { fg, bg: None }), after_fg),
+    }
+}
+
+/// A color at the start of `text` and the text after it: one or two
+/// digits, or exactly six hex digits with `hex`. 99 is the default color.
+fn color(text: &str, hex: bool) -> Option<(Option<Color>, &str)> {
+    let (max_len, is_digit): (usize, fn(&char) -> bool) = if hex {
+        (6, char::is_ascii_hexdigit)
+    } else {
+        (2, char::is_ascii_digit)
+    };
+    // Digits are ASCII, so counting chars counts bytes
+    let len = text.chars().take(max_len).take_while(is_digit).count();
+    if len == 0 || (hex && len < max_len) {
+        return None;
+    }
+    let (digits, rest) = text.split_at(len);
+    let color = if hex {
+        Some(Color::Hex(format!("#{}", digits.to_ascii_lowercase())))
+    } else {
+        digits
+            .parse()
+            .ok()
+            .filter(|&index| index != DEFAULT_COLOR)
+            .map(Color::Index)
+    };
+    Some((color, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str, style: Style) -> Segment {
+        Segment {
+            text: text.to_string(),
+            style,
+        }
+    }
+
+    fn colored(fg: Option<u8>, bg: Option<u8>) -> Style {
+        Style {
+            fg: fg.map(Color::Index),
+            bg: bg.map(Color::Index),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_strip_colors() {
+        assert_eq!(strip("\x034red\x03 plain"), "red plain");
+        assert_eq!(strip("\x0304,12red on blue\x03"), "red on blue");
+        assert_eq!(strip("\x034,2x"), "x");
+        // Only two digits belong to the color
+        assert_eq!(strip("\x031234"), "34");
+        assert_eq!(strip("\x0304,123"), "3");
+        // A comma without a background is text
+        assert_eq!(strip("\x034,hi"), ",hi");
+        assert_eq!(strip("\x03,12hi"), ",12hi");
+        assert_eq!(strip("\x04ff0000,00ff00hex\x04"), "hex");
+        assert_eq!(strip("\x04ff00short"), "ff00short");
+    }
+
+    #[test]
+    fn test_strip_toggles() {
+        assert_eq!(strip("\x02bold\x02 \x1ditalic\x1d \x1funder\x1f"), "bold italic under");
+        assert_eq!(strip("half\x0fway \x16rev\x16 \x1estrike\x1e \x11mono"), "halfway rev strike mono");
+        assert_eq!(strip("w\x02o\x0304r\x0fd"), "word");
+        assert_eq!(strip("plain ünïcödé"), "plain ünïcödé");
+        assert_eq!(strip("\x03"), "");
+    }
+
+    #[test]
+    fn test_parse_overlapping_toggles() {
+        let bold = Style { bold: true, ..Default::default() };
+        let bold_italic = Style { bold: true, italic: true, ..Default::default() };
+        let italic = Style { italic: true, ..Default::default() };
+        assert_eq!(
+            parse("a\x02b\x1dc\x02d\x1de"),
+            vec![
+                segment("a", Style::default()),
+                segment("b", bold),
+                segment("c", bold_italic),
+                segment("d", italic),
+                segment("e", Style::default()),
+            ]
+        );
+        // Toggling twice with nothing in between leaves no empty run
+        assert_eq!(parse("\x02\x02x\x1f\x1f"), vec![segment("x", Style::default())]);
+        let everything = Style {
+            underline: true,
+            strikethrough: true,
+            monospace: true,
+            reverse: true,
+            ..colored(Some(4), None)
+        };
+        assert_eq!(
+            parse("\x1f\x1e\x11\x16\x034all\x0fnone"),
+            vec![segment("all", everything), segment("none", Style::default())]
+        );
+    }
+
+    #[test]
+    fn test_parse_colors() {
+        assert_eq!(
+            parse("\x034,12a\x037b\x03c"),
+            vec![
+                segment("a", colored(Some(4), Some(12))),
+                // A new foreground keeps the background
+                segment("b", colored(Some(7), Some(12))),
+                // A bare color code clears both
+                segment("c", Style::default()),
+            ]
+        );
+        // 99 is the default color; 98 is still in the palette
+        assert_eq!(
+            parse("\x0304,02a\x0399,98b"),
+            vec![segment("a", colored(Some(4), Some(2))), segment("b", colored(None, Some(98)))]
+        );
+        assert_eq!(parse("\x035,x"), vec![segment(",x", colored(Some(5), None))]);
+        assert_eq!(parse("\x03123"), vec![segment("3", colored(Some(12), None))]);
+    }
+
+    #[test]
+    fn test_parse_hex_colors() {
+        let hex = |fg: &str, bg: Option<&str>| Style {
+            fg: Some(Color::Hex(fg.to_string())),
+            bg: bg.map(|bg| Color::Hex(bg.to_string())),
+            ..Default::default()
+        };
+        assert_eq!(
+            parse("\x04FF0000,00ff00a\x040000ffb\x04c"),
+            vec![
+                segment("a", hex("#ff0000", Some("#00ff00"))),
+                segment("b", hex("#0000ff", Some("#00ff00"))),
+                segment("c", Style::default()),
+            ]
+        );
+        // Fewer than six digits aren't a color
+        assert_eq!(parse("\x04abcx"), vec![segment("abcx", Style::default())]);
+    }
+
+    #[test]
+    fn test_segment_shape() {
+        let segments = parse("\x02\x034,12hi");
+        assert_eq!(
+            serde_json::to_value(&segments).unwrap(),
+            serde_json::json!([{
+                "text": "hi",
+                "bold": true,
+                "italic": false,
+                "underline": false,
+                "strikethrough": false,
+                "monospace": false,
+                "reverse": false,
+                "fg": 4,
+                "bg": 12,
+            }])
+        );
+    }
+}
@@ -0,0 +1,89 @@
+/// Seconds since the last keyboard or mouse input, for auto-away
+/// Errors on platforms (and Linux desktops) without an idle API so the
+/// frontend can turn the feature off
+#[tauri::command]
+pub async fn get_idle_seconds() -> Result<u64, String> {
+    idle_seconds().await
+}
+
+#[cfg(target_os = "macos")]
+async fn idle_seconds() -> Result<u64, String> {
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceSecondsSinceLastEventType(state_id: i32, event_type: u32) -> f64;
+    }
+    /// kCGEventSourceStateCombinedSessionState
+    const COMBINED_SESSION_STATE: i32 = 0;
+    /// kCGAnyInputEventType
+    const ANY_INPUT_EVENT: u32 = !0;
+
+    // SAFETY: plain C call with constant arguments and no pointers
+    let seconds = unsafe {
+        CGEventSourceSecondsSinceLastEventType(COMBINED_SESSION_STATE, ANY_INPUT_EVENT)
+    };
+    Ok(seconds.max(0.0) as u64)
+}
+
+#[cfg(windows)]
+async fn idle_seconds() -> Result<u64, String> {
+    use windows_sys::Win32::System::SystemInformation::GetTickCount;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    // SAFETY: `info` is a properly sized LASTINPUTINFO owned by this frame
+    if unsafe { GetLastInputInfo(&mut info) } == 0 {
+        return Err("GetLastInputInfo failed".to_string());
+    }
+    // SAFETY: no arguments
+    let now = unsafe { GetTickCount() };
+    // Both are 32-bit millisecond tick counts that wrap every ~49 days
+    Ok(u64::from(now.wrapping_sub(info.dwTime)) / 1000)
+}
+
+#[cfg(target_os = "linux")]
+async fn idle_seconds() -> Result<u64, String> {
+    let connection = zbus::Connection::session()
+        .await
+        .map_err(|e| format!("Idle detection unavailable (no session bus): {}", e))?;
+
+    // GNOME exposes idle time through Mutter
+    let mutter = connection
+        .call_method(
+            Some("org.gnome.Mutter.IdleMonitor"),
+            "/org/gnome/Mutter/IdleMonitor/Core",
+            Some("org.gnome.Mutter.IdleMonitor"),
+            "GetIdletime",
+            &(),
+        )
+        .await;
+    if let Ok(reply) = mutter {
+        if let Ok(millis) = reply.body().deserialize::<u64>() {
+            return Ok(millis / 1000);
+        }
+    }
+
+    // KDE and others implement the freedesktop screensaver call
+    let reply = connection
+        .call_method(
+            Some("org.freedesktop.ScreenSaver"),
+            "/org/freedesktop/ScreenSaver",
+            Some("org.freedesktop.ScreenSaver"),
+            "GetSessionIdleTime",
+            &(),
+        )
+        .await
+        .map_err(|e| format!("Idle detection is not supported by this desktop: {}", e))?;
+    let millis: u32 = reply
+        .body()
+        .deserialize()
+        .map_err(|e| format!("Unexpected idle time reply: {}", e))?;
+    Ok(u64::from(millis) / 1000)
+}
+
+#[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
+async fn idle_seconds() -> Result<u64, String> {
+    Err("Idle detection is not supported on this platform".to_string())
+}
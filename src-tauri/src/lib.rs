@@ -1,11 +1,40 @@
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::Mutex;
 
+mod badge;
 mod commands;
+mod deep_link;
+mod formatting;
+mod idle;
+mod network;
+mod notification;
+mod power;
+mod secrets;
 mod socket;
+mod tray;
 
-use commands::{check_for_updates, get_app_version};
-use socket::{connect, disconnect, listen, send, SocketState};
+use badge::set_badge_count;
+use commands::{
+    check_for_updates, clear_skipped_update_version, download_update, get_app_version,
+    open_update_url, skip_update_version, start_update_polling, stop_update_polling,
+    UpdateClientState, UpdatePollingState,
+};
+use deep_link::{take_pending_deep_links, PendingDeepLinks};
+use formatting::{parse_formatting, strip_formatting};
+use idle::get_idle_seconds;
+use network::is_network_available;
+use notification::notify;
+use power::{inhibit_sleep, uninhibit_sleep, SleepInhibitState};
+use secrets::{delete_secret, get_secret, store_secret};
+use socket::{
+    ack_events, connect, disconnect, disconnect_all, flush_connection, get_connection_info,
+    get_isupport, list_connections, listen, measure_latency, pause_connection, ping_server,
+    rename_connection, restore_sessions, resume_connection, send, send_batch, send_bytes,
+    send_privmsg, set_max_connections, set_traffic_log, test_connection, whois, SocketState,
+};
+#[cfg(unix)]
+use socket::connect_fd;
+use tray::{set_close_behavior, set_tray_badge, CloseBehaviorState};
 
 // use tauri_plugin_deep_link::DeepLinkExt;
 
@@ -15,9 +44,14 @@ pub fn run() {
 
     #[cfg(desktop)]
     {
-        builder = builder.plugin(tauri_plugin_single_instance::init(|_app, argv, _cwd| {
-            println!("a new app instance was opened with {argv:?} and the deep link event was already triggered");
+        builder = builder.plugin(tauri_plugin_single_instance::init(|app, argv, _cwd| {
+            // A second launch (e.g. clicking an irc:// link) lands here;
+            // route its URL to the window that is already running
+            log::info!("a new app instance was opened with {argv:?}");
+            deep_link::focus_main_window(app);
+            deep_link::open_urls(app, &argv);
         }));
+        builder = builder.on_window_event(tray::handle_window_event);
     }
 
     builder
@@ -25,6 +59,11 @@ pub fn run() {
         .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_os::init())
         .plugin(tauri_plugin_notification::init())
+        .manage(PendingDeepLinks::default())
+        .manage(CloseBehaviorState::default())
+        .manage(SleepInhibitState::default())
+        .manage(UpdateClientState::default())
+        .manage(UpdatePollingState::default())
         .setup(|app| {
             if cfg!(debug_assertions) {
                 app.handle().plugin(
@@ -41,17 +80,76 @@ pub fn run() {
                 use tauri_plugin_deep_link::DeepLinkExt;
                 app.deep_link().register_all()?;
             }
+            #[cfg(desktop)]
+            tray::setup(app)?;
+            network::watch(app.handle());
+            // A cold start through a link passes it on the command line
+            deep_link::open_urls(app.handle(), std::env::args().skip(1));
+            // macOS and mobile deliver links through the plugin instead of argv
+            {
+                use tauri_plugin_deep_link::DeepLinkExt;
+                let handle = app.handle().clone();
+                app.deep_link().on_open_url(move |event| {
+                    deep_link::open_urls(&handle, event.urls().iter().map(|url| url.as_str()));
+                });
+            }
             Ok(())
         })
         .manage(SocketState(Arc::new(Mutex::new(HashMap::new()))))
         .invoke_handler(tauri::generate_handler![
             connect,
+            #[cfg(unix)]
+            connect_fd,
             disconnect,
+            disconnect_all,
+            rename_connection,
+            test_connection,
             listen,
+            list_connections,
+            set_max_connections,
+            get_connection_info,
+            get_isupport,
+            restore_sessions,
             send,
+            send_batch,
+            send_bytes,
+            send_privmsg,
+            flush_connection,
+            set_traffic_log,
+            measure_latency,
+            ping_server,
+            whois,
+            pause_connection,
+            resume_connection,
+            ack_events,
             check_for_updates,
-            get_app_version
+            download_update,
+            skip_update_version,
+            clear_skipped_update_version,
+            get_app_version,
+            open_update_url,
+            start_update_polling,
+            stop_update_polling,
+            take_pending_deep_links,
+            notify,
+            strip_formatting,
+            parse_formatting,
+            get_idle_seconds,
+            is_network_available,
+            store_secret,
+            get_secret,
+            delete_secret,
+            set_tray_badge,
+            set_badge_count,
+            set_close_behavior,
+            inhibit_sleep,
+            uninhibit_sleep
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::ExitRequested { api, .. } = event {
+                socket::on_exit_requested(app, &api);
+            }
+        });
 }
@@ -0,0 +1,294 @@
+use serde::Serialize;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+/// How often the network is rechecked where the OS doesn't tell us about
+/// changes
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Kind of link the default route goes over
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionType {
+    Wifi,
+    Cellular,
+    Ethernet,
+}
+
+/// Best-effort connectivity, from `is_network_available`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct NetworkStatus {
+    /// False when the system knows it is offline (or behind a captive
+    /// portal); true doesn't guarantee a server is reachable
+    pub available: bool,
+    /// The link in use, when the platform tells us
+    pub connection_type: Option<ConnectionType>,
+}
+
+/// Whether the network looks usable, so connects and update checks can
+/// back off while offline
+///
+/// Asks NetworkManager on Linux. Elsewhere, and when it isn't running,
+/// the answer comes from the routing table: whether there is a route to a
+/// public address at all (no packets are sent). The connection type is
+/// only known on Linux and Android, from the default route's interface.
+#[tauri::command]
+pub async fn is_network_available() -> NetworkStatus {
+    platform::status().await
+}
+
+/// Emit `network-changed` with the new `NetworkStatus` whenever it
+/// changes, for as long as the app runs
+///
+/// On Linux this wakes on NetworkManager's signals. Elsewhere (and
+/// without NetworkManager) the route check is polled every
+/// `POLL_INTERVAL`; NWPathMonitor and the Windows network list manager
+/// aren't bound yet.
+pub fn watch(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut changes = platform::Changes::new().await;
+        let mut last = platform::status().await;
+        loop {
+            changes.next().await;
+            let status = platform::status().await;
+            if status == last {
+                continue;
+            }
+            log::info!("Network changed: {:?}", status);
+            if let Err(e) = app.emit("network-changed", status) {
+                log::warn!("Failed to emit network change: {}", e);
+            }
+            last = status;
+        }
+    });
+}
+
+/// Whether there is a route to the internet over IPv4 or IPv6
+/// "Connecting" a UDP socket only consults the routing table
+async fn has_route() -> bool {
+    for (local, remote) in [("0.0.0.0:0", "8.8.8.8:53"), ("[::]:0", "[2001:4860:4860::8888]:53")] {
+        let Ok(socket) = tokio::net::UdpSocket::bind(local).await else {
+            continue;
+        };
+        if socket.connect(remote).await.is_ok() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Interface of the IPv4 default route in `/proc/net/route`
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+fn default_route_interface(route_table: &str) -> Option<&str> {
+    route_table.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let interface = fields.next()?;
+        let destination = fields.next()?;
+        let mask = fields.nth(5)?;
+        (destination == "00000000" && mask == "00000000").then_some(interface)
+    })
+}
+
+/// Guess the link type from a Linux interface name
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+fn classify_interface(interface: &str) -> Option<ConnectionType> {
+    const PREFIXES: &[(&str, ConnectionType)] = &[
+        ("wl", ConnectionType::Wifi),
+        ("en", ConnectionType::Ethernet),
+        ("eth", ConnectionType::Ethernet),
+        ("rmnet", ConnectionType::Cellular),
+        ("ccmni", ConnectionType::Cellular),
+        ("ww", ConnectionType::Cellular),
+    ];
+    PREFIXES
+        .iter()
+        .find(|(prefix, _)| interface.starts_with(prefix))
+        .map(|&(_, connection_type)| connection_type)
+}
+
+/// Type of the link behind the default route, from the kernel's tables
+#[cfg(any(target_os = "linux", target_os = "android"))]
+async fn default_route_type() -> Option<ConnectionType> {
+    // Not readable by apps on recent Android versions; that just means
+    // the type stays unknown
+    let route_table = tokio::fs::read_to_string("/proc/net/route").await.ok()?;
+    let interface = default_route_interface(&route_table)?;
+    let wireless = std::path::Path::new("/sys/class/net").join(interface).join("wireless");
+    if tokio::fs::try_exists(wireless).await.unwrap_or(false) {
+        return Some(ConnectionType::Wifi);
+    }
+    classify_interface(interface)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{default_route_type, has_route, ConnectionType, NetworkStatus};
+    use futures_util::StreamExt;
+    use zbus::zvariant::OwnedValue;
+
+    /// NM_CONNECTIVITY_UNKNOWN, and NM_CONNECTIVITY_FULL
+    const CONNECTIVITY_UNKNOWN: u32 = 0;
+    const CONNECTIVITY_FULL: u32 = 4;
+
+    pub async fn status() -> NetworkStatus {
+        match network_manager().await {
+            Ok(status) => status,
+            Err(e) => {
+                log::debug!("NetworkManager unavailable, checking routes instead: {}", e);
+                NetworkStatus {
+                    available: has_route().await,
+                    connection_type: default_route_type().await,
+                }
+            }
+        }
+    }
+
+    async fn network_manager() -> zbus::Result<NetworkStatus> {
+        let connection = zbus::Connection::system().await?;
+        let connectivity = u32::try_from(property(&connection, "Connectivity").await?)?;
+        let primary_type = String::try_from(property(&connection, "PrimaryConnectionType").await?)?;
+        let connection_type = match primary_type.as_str() {
+            "802-11-wireless" => Some(ConnectionType::Wifi),
+            "802-3-ethernet" => Some(ConnectionType::Ethernet),
+            "gsm" | "cdma" => Some(ConnectionType::Cellular),
+            _ => default_route_type().await,
+        };
+        let available = match connectivity {
+            // Connectivity checking is off; fall back to the routes
+            CONNECTIVITY_UNKNOWN => has_route().await,
+            // None, a captive portal, or no internet beyond the LAN
+            connectivity => connectivity == CONNECTIVITY_FULL,
+        };
+        Ok(NetworkStatus {
+            available,
+            connection_type,
+        })
+    }
+
+    /// Wakes when NetworkManager announces a change, or on a timer when it
+    /// can't be reached
+    pub struct Changes(Option<zbus::MessageStream>);
+
+    impl Changes {
+        pub async fn new() -> Self {
+            match subscribe().await {
+                Ok(stream) => Changes(Some(stream)),
+                Err(e) => {
+                    log::debug!("Not watching NetworkManager, polling instead: {}", e);
+                    Changes(None)
+                }
+            }
+        }
+
+        pub async fn next(&mut self) {
+            if let Some(stream) = &mut self.0 {
+                // `StateChanged` and `PropertiesChanged` on the manager
+                // object cover connectivity and the primary connection
+                if stream.next().await.is_some() {
+                    return;
+                }
+                log::debug!("NetworkManager signal stream ended, polling instead");
+                self.0 = None;
+            }
+            tokio::time::sleep(super::POLL_INTERVAL).await;
+        }
+    }
+
+    async fn subscribe() -> zbus::Result<zbus::MessageStream> {
+        let connection = zbus::Connection::system().await?;
+        let rule = zbus::MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .sender("org.freedesktop.NetworkManager")?
+            .path("/org/freedesktop/NetworkManager")?
+            .build();
+        zbus::MessageStream::for_match_rule(rule, &connection, None).await
+    }
+
+    async fn property(connection: &zbus::Connection, name: &str) -> zbus::Result<OwnedValue> {
+        let reply = connection
+            .call_method(
+                Some("org.freedesktop.NetworkManager"),
+                "/org/freedesktop/NetworkManager",
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &("org.freedesktop.NetworkManager", name),
+            )
+            .await?;
+        reply.body().deserialize::<OwnedValue>()
+    }
+}
+
+#[cfg(target_os = "android")]
+mod platform {
+    use super::{default_route_type, has_route, NetworkStatus};
+
+    pub async fn status() -> NetworkStatus {
+        NetworkStatus {
+            available: has_route().await,
+            connection_type: default_route_type().await,
+        }
+    }
+
+    /// Ticks every `POLL_INTERVAL`
+    pub struct Changes;
+
+    impl Changes {
+        pub async fn new() -> Self {
+            Changes
+        }
+
+        pub async fn next(&mut self) {
+            tokio::time::sleep(super::POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod platform {
+    use super::{has_route, NetworkStatus};
+
+    pub async fn status() -> NetworkStatus {
+        NetworkStatus {
+            available: has_route().await,
+            connection_type: None,
+        }
+    }
+
+    /// Ticks every `POLL_INTERVAL`
+    pub struct Changes;
+
+    impl Changes {
+        pub async fn new() -> Self {
+            Changes
+        }
+
+        pub async fn next(&mut self) {
+            tokio::time::sleep(super::POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_route_interface() {
+        let route_table = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+            wlp2s0\t0001A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0\n\
+            wlp2s0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0\n";
+        assert_eq!(default_route_interface(route_table), Some("wlp2s0"));
+        assert_eq!(default_route_interface("Iface\tDestination\n"), None);
+    }
+
+    #[test]
+    fn test_classify_interface() {
+        assert_eq!(classify_interface("wlan0"), Some(ConnectionType::Wifi));
+        assert_eq!(classify_interface("enp3s0"), Some(ConnectionType::Ethernet));
+        assert_eq!(classify_interface("eth0"), Some(ConnectionType::Ethernet));
+        assert_eq!(classify_interface("rmnet_data0"), Some(ConnectionType::Cellular));
+        assert_eq!(classify_interface("wwp0s20u4"), Some(ConnectionType::Cellular));
+        assert_eq!(classify_interface("tun0"), None);
+    }
+}
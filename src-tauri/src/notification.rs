@@ -0,0 +1,81 @@
+use serde::Serialize;
+use tauri::AppHandle;
+
+/// Action identifier attached to buffer notifications
+const OPEN_BUFFER_ACTION: &str = "open-buffer";
+
+/// Payload of `notification-clicked`, naming the buffer to switch to
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub struct NotificationClicked {
+    pub client_id: String,
+    pub channel: String,
+    /// Action the user picked, where the platform reports one
+    pub action: Option<String>,
+}
+
+/// Show a notification for `channel` on `client_id`
+/// Activating it focuses the window and emits `notification-clicked`.
+/// Linux reports activation through the notification daemon; on mobile
+/// the ids travel as `extra` data with the `open-buffer` action type for
+/// the frontend's `onAction` listener. macOS and Windows only show it
+#[tauri::command]
+pub fn notify(
+    app: AppHandle,
+    client_id: String,
+    channel: String,
+    title: String,
+    body: String,
+) -> Result<(), String> {
+    show(app, client_id, channel, title, body)
+}
+
+#[cfg(target_os = "linux")]
+fn show(app: AppHandle, client_id: String, channel: String, title: String, body: String) -> Result<(), String> {
+    use tauri::Emitter;
+
+    let handle = notify_rust::Notification::new()
+        .appname("ObsidianIRC")
+        .summary(&title)
+        .body(&body)
+        .action("default", "Open")
+        .show()
+        .map_err(|e| format!("Failed to show notification: {}", e))?;
+
+    // Waiting blocks on D-Bus until the notification is acted on or closed
+    std::thread::spawn(move || {
+        handle.wait_for_action(|action| {
+            if action == "__closed" {
+                return;
+            }
+            crate::deep_link::focus_main_window(&app);
+            // "default" is the daemon's name for clicking the body
+            let action = if action == "default" { OPEN_BUFFER_ACTION } else { action };
+            let payload = NotificationClicked {
+                client_id,
+                channel,
+                action: Some(action.to_string()),
+            };
+            if let Err(e) = app.emit("notification-clicked", payload) {
+                log::error!("Failed to emit notification-clicked: {}", e);
+            }
+        });
+    });
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn show(app: AppHandle, client_id: String, channel: String, title: String, body: String) -> Result<(), String> {
+    use tauri_plugin_notification::NotificationExt;
+
+    app.notification()
+        .builder()
+        .title(title)
+        .body(body)
+        .action_type_id(OPEN_BUFFER_ACTION)
+        .extra("clientId", client_id)
+        .extra("channel", channel)
+        .show()
+        .map_err(|e| format!("Failed to show notification: {}", e))
+}
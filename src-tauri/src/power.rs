@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use tauri::State;
+use tokio::sync::Mutex;
+
+/// Outstanding sleep inhibit requests
+/// The OS-level inhibitor is held while at least one token is live, so
+/// independent callers compose. Every platform ties it to this process,
+/// which means it is also released when the app exits
+#[derive(Default)]
+pub struct SleepInhibitState(Mutex<SleepInhibitor>);
+
+#[derive(Default)]
+struct SleepInhibitor {
+    tokens: HashSet<u64>,
+    next_token: u64,
+    guard: Option<platform::Guard>,
+}
+
+/// Keep the system from idle-sleeping until `uninhibit_sleep` is called
+/// with the returned token
+#[tauri::command]
+pub async fn inhibit_sleep(
+    state: State<'_, SleepInhibitState>,
+    reason: Option<String>,
+) -> Result<u64, String> {
+    let mut inhibitor = state.0.lock().await;
+    if inhibitor.guard.is_none() {
+        let reason = reason.unwrap_or_else(|| "Staying connected to IRC".to_string());
+        inhibitor.guard = Some(platform::inhibit(&reason).await?);
+        log::info!("Sleep inhibited: {}", reason);
+    }
+    inhibitor.next_token += 1;
+    let token = inhibitor.next_token;
+    inhibitor.tokens.insert(token);
+    Ok(token)
+}
+
+/// Drop the request behind `token`; sleep is allowed again once none remain
+#[tauri::command]
+pub async fn uninhibit_sleep(state: State<'_, SleepInhibitState>, token: u64) -> Result<(), String> {
+    let mut inhibitor = state.0.lock().await;
+    if !inhibitor.tokens.remove(&token) {
+        return Err(format!("Unknown sleep inhibit token {}", token));
+    }
+    if inhibitor.tokens.is_empty() {
+        // Dropping the guard releases the OS inhibitor
+        inhibitor.guard = None;
+        log::info!("Sleep no longer inhibited");
+    }
+    Ok(())
+}
+
+/// systemd-logind inhibitor lock; held for as long as the fd stays open
+#[cfg(target_os = "linux")]
+mod platform {
+    pub struct Guard(#[allow(dead_code)] zbus::zvariant::OwnedFd);
+
+    pub async fn inhibit(reason: &str) -> Result<Guard, String> {
+        let connection = zbus::Connection::system()
+            .await
+            .map_err(|e| format!("Sleep inhibit unavailable (no system bus): {}", e))?;
+        let reply = connection
+            .call_method(
+                Some("org.freedesktop.login1"),
+                "/org/freedesktop/login1",
+                Some("org.freedesktop.login1.Manager"),
+                "Inhibit",
+                &("sleep:idle", "ObsidianIRC", reason, "block"),
+            )
+            .await
+            .map_err(|e| format!("Failed to inhibit sleep: {}", e))?;
+        let fd: zbus::zvariant::OwnedFd = reply
+            .body()
+            .deserialize()
+            .map_err(|e| format!("Unexpected inhibit reply: {}", e))?;
+        Ok(Guard(fd))
+    }
+}
+
+/// IOKit power assertion, released on drop
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void, CString};
+
+    type CFStringRef = *const c_void;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringCreateWithCString(alloc: *const c_void, c_str: *const c_char, encoding: u32) -> CFStringRef;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPMAssertionCreateWithName(
+            assertion_type: CFStringRef,
+            level: u32,
+            name: CFStringRef,
+            assertion_id: *mut u32,
+        ) -> i32;
+        fn IOPMAssertionRelease(assertion_id: u32) -> i32;
+    }
+
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const ASSERTION_LEVEL_ON: u32 = 255;
+
+    pub struct Guard(u32);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            // SAFETY: the id came from a successful IOPMAssertionCreateWithName
+            unsafe {
+                IOPMAssertionRelease(self.0);
+            }
+        }
+    }
+
+    fn cf_string(value: &str) -> CFStringRef {
+        let value = CString::new(value.replace('\0', "")).unwrap_or_default();
+        // SAFETY: `value` is a valid NUL-terminated string for the call
+        unsafe { CFStringCreateWithCString(std::ptr::null(), value.as_ptr(), CF_STRING_ENCODING_UTF8) }
+    }
+
+    pub async fn inhibit(reason: &str) -> Result<Guard, String> {
+        let assertion_type = cf_string("PreventUserIdleSystemSleep");
+        let name = cf_string(reason);
+        if assertion_type.is_null() || name.is_null() {
+            return Err("Failed to inhibit sleep: invalid assertion name".to_string());
+        }
+        let mut assertion_id = 0;
+        // SAFETY: both strings are live CFStrings and `assertion_id` outlives the call
+        let result = unsafe {
+            let result = IOPMAssertionCreateWithName(assertion_type, ASSERTION_LEVEL_ON, name, &mut assertion_id);
+            CFRelease(assertion_type);
+            CFRelease(name);
+            result
+        };
+        if result != 0 {
+            return Err(format!("Failed to inhibit sleep: IOKit error {}", result));
+        }
+        Ok(Guard(assertion_id))
+    }
+}
+
+/// `SetThreadExecutionState` only lasts as long as the calling thread, so a
+/// dedicated thread holds it until the guard is dropped
+#[cfg(windows)]
+mod platform {
+    use std::sync::mpsc;
+    use windows_sys::Win32::System::Power::{SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED};
+
+    pub struct Guard(#[allow(dead_code)] mpsc::Sender<()>);
+
+    pub async fn inhibit(_reason: &str) -> Result<Guard, String> {
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (ready_tx, ready_rx) = tokio::sync::oneshot::channel();
+        std::thread::spawn(move || {
+            // SAFETY: plain flag arguments
+            let previous = unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) };
+            let _ = ready_tx.send(previous != 0);
+            // Returns once the guard (and with it the sender) is dropped
+            let _ = release_rx.recv();
+            // SAFETY: plain flag arguments
+            unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+        });
+        match ready_rx.await {
+            Ok(true) => Ok(Guard(release_tx)),
+            _ => Err("Failed to inhibit sleep: SetThreadExecutionState failed".to_string()),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    // Never constructed: there is nothing to inhibit with here
+    #[allow(dead_code)]
+    pub struct Guard;
+
+    pub async fn inhibit(_reason: &str) -> Result<Guard, String> {
+        Err("Sleep inhibit is not supported on this platform".to_string())
+    }
+}
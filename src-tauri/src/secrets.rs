@@ -0,0 +1,91 @@
+/// Keyring service name every ObsidianIRC secret is filed under
+#[cfg(not(target_os = "android"))]
+const SERVICE: &str = "ObsidianIRC";
+
+/// Store `value` under `key` in the OS keyring
+/// Keys are namespaced by the caller per network, e.g. `libera/sasl`
+#[tauri::command]
+pub async fn store_secret(key: String, value: String) -> Result<(), String> {
+    run(move || platform::store(&key, &value)).await
+}
+
+/// Fetch the secret stored under `key`, or `None` if there is none
+#[tauri::command]
+pub async fn get_secret(key: String) -> Result<Option<String>, String> {
+    run(move || platform::get(&key)).await
+}
+
+/// Remove the secret stored under `key`; missing keys are not an error
+#[tauri::command]
+pub async fn delete_secret(key: String) -> Result<(), String> {
+    run(move || platform::delete(&key)).await
+}
+
+/// Keyring backends block (D-Bus, Keychain prompts), keep them off the runtime
+async fn run<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| format!("Keyring task failed: {}", e))?
+}
+
+#[cfg(not(target_os = "android"))]
+mod platform {
+    use super::SERVICE;
+    use keyring::{Entry, Error};
+
+    fn entry(key: &str) -> Result<Entry, String> {
+        if key.is_empty() {
+            return Err("Secret key must not be empty".to_string());
+        }
+        Entry::new(SERVICE, key).map_err(describe)
+    }
+
+    /// Turn keyring errors into messages the UI can show before falling back
+    fn describe(error: Error) -> String {
+        match error {
+            Error::NoStorageAccess(e) => format!("No secure storage available: {}", e),
+            Error::PlatformFailure(e) => format!("Secure storage failed: {}", e),
+            other => format!("Secure storage error: {}", other),
+        }
+    }
+
+    pub fn store(key: &str, value: &str) -> Result<(), String> {
+        entry(key)?.set_password(value).map_err(describe)
+    }
+
+    pub fn get(key: &str) -> Result<Option<String>, String> {
+        match entry(key)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NoEntry) => Ok(None),
+            Err(e) => Err(describe(e)),
+        }
+    }
+
+    pub fn delete(key: &str) -> Result<(), String> {
+        match entry(key)?.delete_credential() {
+            Ok(()) | Err(Error::NoEntry) => Ok(()),
+            Err(e) => Err(describe(e)),
+        }
+    }
+}
+
+#[cfg(target_os = "android")]
+mod platform {
+    const UNSUPPORTED: &str = "Secure storage is not available on this platform";
+
+    pub fn store(_key: &str, _value: &str) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn get(_key: &str) -> Result<Option<String>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn delete(_key: &str) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
@@ -1,37 +1,736 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tauri::{Emitter, State};
+use std::time::{Duration, Instant};
+use tauri::{Manager, State};
 use tokio::net::TcpStream;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::{Mutex, mpsc, oneshot};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{Mutex, Notify, mpsc, oneshot};
 use tokio::task;
 
-// Platform-specific TLS imports
-#[cfg(not(target_os = "android"))]
-use tokio_native_tls::TlsConnector;
-#[cfg(not(target_os = "android"))]
-use native_tls::TlsConnector as NativeTlsConnector;
-
-#[cfg(target_os = "android")]
-use tokio_rustls::TlsConnector;
-#[cfg(target_os = "android")]
-use rustls::pki_types::ServerName;
-#[cfg(target_os = "android")]
-use std::sync::Arc as StdArc;
-#[cfg(target_os = "android")]
-use webpki_roots;
+mod caps;
+mod certificate;
+mod charset;
+mod compression;
+mod dns;
+mod error;
+mod happy_eyeballs;
+mod irc;
+mod proxy;
+mod sasl;
+mod session;
+mod tls;
+mod traffic_log;
+mod websocket;
+mod whois;
+
+pub use error::SocketError;
+pub use sasl::SaslCredentials;
+pub use session::restore_sessions;
+pub use whois::WhoisReply;
+use charset::Charset;
+use error::CertificateProblem;
+use tls::{MinTlsVersion, TlsInfo};
+use traffic_log::{Direction, SharedTrafficLog, TrafficLog};
+
+/// Transport selected by the address scheme
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Transport {
+    Tcp,
+    Tls,
+    /// IRC over WebSocket (`ws://`/`wss://`); `url` is the full address
+    WebSocket { url: String },
+    /// Local Unix domain socket (`unix://`), e.g. a bouncer on the same machine
+    Unix { path: String },
+}
+
+/// Boxed read half of an established stream, whatever the transport
+type BoxedReader = Box<dyn AsyncRead + Send + Unpin>;
+/// Boxed write half of an established stream, whatever the transport
+type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// An established stream ready for the read/write tasks
+struct OpenedStream {
+    reader: BoxedReader,
+    writer: BoxedWriter,
+    /// Negotiated TLS parameters, for TLS connections
+    tls_info: Option<TlsInfo>,
+    /// TLS was requested but we fell back to plaintext
+    downgraded: bool,
+    /// Encrypted end to end to the server (TLS or `wss://`)
+    is_tls: bool,
+    /// Our end and the peer's end of the TCP connection (the proxy, when
+    /// tunneling); unknown for WebSocket and Unix socket transports
+    local_addr: Option<SocketAddr>,
+    remote_addr: Option<SocketAddr>,
+}
+
+impl OpenedStream {
+    fn new<R, W>(reader: R, writer: W) -> Self
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        OpenedStream {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+            tls_info: None,
+            downgraded: false,
+            is_tls: false,
+            local_addr: None,
+            remote_addr: None,
+        }
+    }
+
+    /// Record the endpoints of the TCP connection the stream runs over,
+    /// as returned by `tcp_addrs`
+    fn with_addrs(mut self, (local_addr, remote_addr): (Option<SocketAddr>, Option<SocketAddr>)) -> Self {
+        self.local_addr = local_addr;
+        self.remote_addr = remote_addr;
+        self
+    }
+}
+
+/// Local and peer address of `tcp_stream`, taken before it is split or
+/// wrapped in TLS
+fn tcp_addrs(tcp_stream: &TcpStream) -> (Option<SocketAddr>, Option<SocketAddr>) {
+    (tcp_stream.local_addr().ok(), tcp_stream.peer_addr().ok())
+}
+
+/// Traffic counters of a connection, shared by its read and write tasks
+#[derive(Debug, Default)]
+struct ConnectionStats {
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    lines_in: AtomicU64,
+    lines_out: AtomicU64,
+}
+
+impl ConnectionStats {
+    /// Count data written to the socket, one line per `\n`
+    fn record_out(&self, bytes: &[u8]) {
+        let lines = bytes.iter().filter(|&&b| b == b'\n').count();
+        self.bytes_out.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        self.lines_out.fetch_add(lines as u64, Ordering::Relaxed);
+    }
+}
+
+/// A single outgoing write queued for the write task
+#[derive(Debug)]
+struct OutgoingMessage {
+    data: OutgoingData,
+    /// Append `\r\n` if text data doesn't already end with it
+    append_crlf: bool,
+    /// Set by `send` with `confirm`: emit `sent` with it once written
+    sequence: Option<u64>,
+}
+
+/// What an `OutgoingMessage` writes
+#[derive(Debug)]
+enum OutgoingData {
+    /// Text, encoded with the connection's charset
+    Text(String),
+    /// Bytes written exactly as given, for binary protocols
+    Bytes(Vec<u8>),
+    /// Nothing to write: flush the socket and report back, which means
+    /// everything queued before it has gone out
+    Flush(oneshot::Sender<()>),
+}
+
+impl From<String> for OutgoingData {
+    fn from(text: String) -> Self {
+        OutgoingData::Text(text)
+    }
+}
+
+/// Pause switch shared between a connection's handle and its read task
+///
+/// Also holds the reader while too many events are unacknowledged, when
+/// the connection uses `max_unacked_events`.
+#[derive(Debug, Default)]
+struct ReadPause {
+    paused: AtomicBool,
+    resumed: Notify,
+    /// Unacknowledged events that hold the reader; 0 for no limit
+    max_unacked: AtomicU64,
+    unacked: AtomicU64,
+}
+
+impl ReadPause {
+    fn new(max_unacked: Option<u64>) -> Self {
+        ReadPause {
+            max_unacked: AtomicU64::new(max_unacked.unwrap_or(0)),
+            ..Default::default()
+        }
+    }
+
+    fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.resumed.notify_waiters();
+    }
+
+    /// Count an event that the frontend is expected to acknowledge
+    fn event_sent(&self) {
+        if self.max_unacked.load(Ordering::SeqCst) > 0 {
+            self.unacked.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Take `count` events off the unacknowledged count
+    fn ack(&self, count: u64) {
+        let _ = self
+            .unacked
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |unacked| Some(unacked.saturating_sub(count)));
+        self.resumed.notify_waiters();
+    }
+
+    /// Paused, or waiting for acknowledgements
+    fn is_held(&self) -> bool {
+        let max_unacked = self.max_unacked.load(Ordering::SeqCst);
+        self.paused.load(Ordering::SeqCst) || (max_unacked > 0 && self.unacked.load(Ordering::SeqCst) >= max_unacked)
+    }
+
+    /// Return immediately unless held, otherwise once resumed or
+    /// acknowledged
+    async fn wait_while_paused(&self) {
+        while self.is_held() {
+            let resumed = self.resumed.notified();
+            tokio::pin!(resumed);
+            // Register before re-checking so a resume in between isn't missed
+            resumed.as_mut().enable();
+            if !self.is_held() {
+                break;
+            }
+            resumed.await;
+        }
+    }
+}
 
 /// Connection handle for managing write operations and shutdown
 #[derive(Debug)]
 pub struct ConnectionHandle {
-    write_tx: mpsc::Sender<String>,
+    write_tx: mpsc::Sender<OutgoingMessage>,
+    /// Written ahead of `write_tx`, for keepalive and other control lines
+    priority_tx: mpsc::Sender<OutgoingMessage>,
     shutdown_tx: Option<oneshot::Sender<()>>,
+    traffic_log: SharedTrafficLog,
+    /// Finishes once queued writes are flushed and the writer is shut down
+    write_task: task::JoinHandle<()>,
+    read_task: task::JoinHandle<()>,
+    quit_message: Option<String>,
+    is_tls: bool,
+    /// Latency `PING` tokens awaiting their `PONG`
+    pending_pings: HashMap<String, oneshot::Sender<()>>,
+    read_pause: Arc<ReadPause>,
+    tls_info: Option<TlsInfo>,
+    local_addr: Option<SocketAddr>,
+    remote_addr: Option<SocketAddr>,
+    /// When the connection was established, in Unix milliseconds
+    connected_at: u64,
+    stats: Arc<ConnectionStats>,
+    /// Limit `send` and `send_batch` hold lines to, `None` in raw mode
+    max_line_length: Option<usize>,
+    /// Set when the connection is closed on purpose, so the close event
+    /// reports `reason: "user"`
+    closed_by_user: Arc<AtomicBool>,
+    /// Event the connection's messages go out on
+    event_name: String,
+    /// `RPL_ISUPPORT` tokens received so far
+    isupport: irc::Isupport,
+    /// `whois` requests collecting their reply
+    pending_whois: whois::PendingWhois,
+    /// The key this handle is stored under, as its tasks see it
+    client_id: SharedClientId,
+}
+
+/// A connection's client_id, shared with its read and write tasks so
+/// `rename_connection` can change it under them
+///
+/// Only set while holding the `SocketState` lock, so a task that looks
+/// itself up under that lock always finds its current key.
+#[derive(Debug, Clone)]
+struct SharedClientId(Arc<std::sync::RwLock<String>>);
+
+impl SharedClientId {
+    fn new(client_id: &str) -> Self {
+        SharedClientId(Arc::new(std::sync::RwLock::new(client_id.to_string())))
+    }
+
+    fn get(&self) -> String {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn set(&self, client_id: String) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = client_id;
+    }
+}
+
+impl ConnectionHandle {
+    /// Stop both tasks and wait for them to finish
+    /// The writer gets `timeout` to flush; the reader is aborted, so it
+    /// neither emits a late `connected: false` nor removes a newer handle
+    /// stored under the same id
+    async fn shutdown(mut self, timeout: Duration) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+        if tokio::time::timeout(timeout, &mut self.write_task).await.is_err() {
+            self.write_task.abort();
+        }
+        self.read_task.abort();
+        let _ = self.read_task.await;
+    }
+
+    /// Snapshot for `get_connection_info`
+    fn info(&self) -> ConnectionInfo {
+        let tls_info = self.tls_info.as_ref();
+        ConnectionInfo {
+            connected: Some(true),
+            is_tls: Some(self.is_tls),
+            local_addr: self.local_addr.map(|addr| addr.to_string()),
+            remote_addr: self.remote_addr.map(|addr| addr.to_string()),
+            tls_version: tls_info.and_then(|info| info.protocol_version.clone()),
+            tls_cipher: tls_info.and_then(|info| info.cipher_suite.clone()),
+            bytes_in: Some(self.stats.bytes_in.load(Ordering::Relaxed)),
+            bytes_out: Some(self.stats.bytes_out.load(Ordering::Relaxed)),
+            lines_in: Some(self.stats.lines_in.load(Ordering::Relaxed)),
+            lines_out: Some(self.stats.lines_out.load(Ordering::Relaxed)),
+            connected_at: Some(self.connected_at),
+            ..Default::default()
+        }
+    }
 }
 
 /// Socket state to manage multiple connections
 pub struct SocketState(pub(crate) Arc<Mutex<HashMap<String, ConnectionHandle>>>);
 
+/// Optional per-connection settings passed to `connect`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ConnectOptions {
+    /// Raw passthrough mode. When set, `read_task` skips `\r\n` line
+    /// splitting and emits every socket read as-is, and `write_task` no
+    /// longer appends a line ending. Events then only carry `message` (one
+    /// per read chunk, which may hold partial or multiple lines) and the
+    /// final `error`/`connected` fields on close. Chunks are passed on
+    /// byte for byte unless an `encoding` is also given; then they are
+    /// decoded like lines, and with a UTF-8 `encoding` a character split
+    /// across reads is held back and sent whole with the next chunk.
+    pub raw: bool,
+    /// HTTP proxy (`[http://][user:pass@]host:port`) to tunnel TCP/TLS
+    /// connections through via `CONNECT`
+    pub http_proxy: Option<String>,
+    /// Size of the socket read buffer in bytes (default 4096, clamped to
+    /// 512..=64KB). Larger buffers mean fewer reads during bursts like
+    /// netsplits.
+    pub read_buffer_size: Option<usize>,
+    /// Negotiate these IRCv3 capabilities right after connecting, before
+    /// `connect` returns: `CAP LS 302`, then `CAP REQ` for the ones the
+    /// server offers, then `CAP END`. The caps the server acknowledged are
+    /// emitted as a `caps-acked` event. With `sasl` set, `CAP END` is left
+    /// to the frontend as usual, after the SASL exchange that follows.
+    /// Unset leaves CAP entirely to the frontend.
+    pub request_caps: Option<Vec<String>>,
+    /// Run `CAP REQ :sasl` and `AUTHENTICATE` with these credentials right
+    /// after connecting, before `connect` returns: PLAIN by default, or
+    /// `{ "mechanism": "EXTERNAL" }` to log in with what the server already
+    /// knows from the transport (CertFP). The result is emitted as a
+    /// `sasl-result` event; registration is still up to the frontend.
+    #[serde(skip_serializing)]
+    pub sasl: Option<SaslCredentials>,
+    /// Answer server `PING`s with `PONG` directly from the backend, so
+    /// connections survive while the webview's timers are throttled. The
+    /// `PING` is still emitted to the frontend.
+    pub auto_pong: bool,
+    /// ALPN protocols to offer during the TLS handshake (e.g. `["irc"]`).
+    /// The server's choice, if any, is reported in `tls_info`.
+    pub alpn: Option<Vec<String>>,
+    /// Server name for TLS SNI and certificate validation, when it differs
+    /// from the host we connect to (IP literals, reverse proxies)
+    pub sni_host: Option<String>,
+    /// DANGEROUS: accept any certificate and host name. Only meant for
+    /// local test servers; logged as a warning on every use and flagged as
+    /// `insecure: true` on the connected event.
+    pub danger_skip_verification: bool,
+    /// If the TLS handshake on an `ircs://` address fails, retry the same
+    /// host/port in plaintext and flag `downgraded: true` on the connected
+    /// event. Opt-in, as a silent downgrade exposes all traffic.
+    pub allow_plaintext_fallback: bool,
+    /// Reason sent with `QUIT` if the app exits while this connection is
+    /// open (default "ObsidianIRC")
+    pub quit_message: Option<String>,
+    /// Nameserver (`ip` or `ip:port`) to resolve the host with instead of
+    /// the system resolver. Not used for proxied or WebSocket connections,
+    /// where the proxy or WebSocket client does the lookup.
+    pub resolver: Option<String>,
+    /// Try the resolved addresses one at a time in resolver order, instead
+    /// of racing IPv6 and IPv4 with a 250ms head start each (Happy
+    /// Eyeballs). Every attempt is logged with its timing either way.
+    pub disable_happy_eyeballs: bool,
+    /// Coalesce complete lines arriving within this many milliseconds into
+    /// one event carrying `messages` instead of `message`, to keep bursts
+    /// (netsplit rejoins, big `NAMES`/`WHO` replies) from flooding the IPC
+    /// bridge. Unset or 0 keeps one event per line. Ignored in raw mode.
+    pub batch_window_ms: Option<u64>,
+    /// Charset lines are transcoded from and to: `auto` (the default,
+    /// UTF-8 reading invalid bytes as Latin-1), any WHATWG label such as
+    /// `iso-8859-1` or `koi8-r`, or `binary` to pass bytes through
+    /// untouched. Incoming lines always reach the frontend as UTF-8 unless
+    /// `binary`. Raw mode only transcodes when this is set.
+    pub encoding: Option<String>,
+    /// Recognize CTCP (`\x01`-framed) payloads in `PRIVMSG`/`NOTICE` lines
+    /// and add them to the message as a structured `ctcp` field, next to
+    /// the unchanged raw line
+    pub parse_ctcp: bool,
+    /// Parse each line's IRCv3 `@tag=value;...` block into a `tags` map on
+    /// the message (values unescaped, client-only tags keep their `+`), and
+    /// stamp it with a `time` from the `server-time` tag
+    pub parse_tags: bool,
+    /// Break each line into `prefix` (`nick`/`user`/`host`), `command`,
+    /// `params` and `trailing`, added to the message as a `parsed` field
+    /// next to the raw line. Numeric replies also get the code as
+    /// `numeric` and the nick they are addressed to as `client`.
+    pub parse_messages: bool,
+    /// EXPERIMENTAL: zlib-compress the whole stream in both directions,
+    /// from the first byte on, for servers and bouncers that offer a
+    /// compressed port. Every write is sync-flushed so lines aren't held
+    /// back. Not compatible with servers that don't compress.
+    pub enable_compression: bool,
+    /// Cap on how fast incoming data is read, in bytes per second, for
+    /// metered connections. Nothing is dropped: once the budget is spent
+    /// the socket isn't read, so TCP backpressure paces the server. A low
+    /// cap adds latency, as lines queue up behind bursts like `NAMES`.
+    pub max_bytes_per_sec: Option<u64>,
+    /// Send our own `PING` after this many seconds without incoming data,
+    /// to catch half-open connections where writes still seem to succeed.
+    /// Unset or 0 (the default) leaves keepalive to the frontend. Ignored
+    /// in raw mode.
+    pub ping_interval_secs: Option<u64>,
+    /// How long to wait for the `PONG` to a keepalive `PING` before the
+    /// connection is declared dead (default 30)
+    pub ping_timeout_secs: Option<u64>,
+    /// Oldest TLS version to accept, `"1.2"` (the default) or `"1.3"`; a
+    /// server that can't meet it fails the handshake
+    pub min_tls_version: Option<String>,
+    /// TLS cipher suites to allow, by the names `tls_info` reports (e.g.
+    /// `TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256`), for servers that only
+    /// speak a few. Unknown names fail the connect. Only honored where the
+    /// TLS library lets us choose (rustls, on Android); elsewhere the
+    /// system's suites are used and the `connected` event says so in
+    /// `warnings`.
+    pub cipher_suites: Option<Vec<String>>,
+    /// Save the address and options (without SASL or proxy credentials)
+    /// once connected, so `restore_sessions` can offer the connection again
+    /// after a restart. Cleared by `disconnect`.
+    pub persist_session: bool,
+    /// Longest line `send` and `send_batch` accept, in bytes including the
+    /// `\r\n` but not the IRCv3 tag block, which has its own 8191-byte
+    /// allowance (default 512). Raise it for networks with a higher limit.
+    /// Ignored in raw mode.
+    pub max_line_length: Option<usize>,
+    /// `SO_RCVBUF` for TCP and TLS connections, in bytes (clamped to
+    /// 4KB..=16MB), for high-volume connections such as bouncer playback.
+    /// The OS may round it; the size it grants is logged. Unset keeps the
+    /// OS default.
+    pub recv_buffer: Option<usize>,
+    /// `SO_SNDBUF` for TCP and TLS connections, like `recv_buffer`
+    pub send_buffer: Option<usize>,
+    /// Event to emit this connection's messages on instead of
+    /// `tcp-message`, for embedders that route by event name. Letters,
+    /// digits and `-/:_` only, as Tauri requires.
+    pub event_name: Option<String>,
+    /// Opt-in flow control for frontends that acknowledge events. Every
+    /// event carrying `message` or `messages` counts as one until the
+    /// frontend passes it to `ack_events` once handled; with this many
+    /// outstanding the socket stops being read, so TCP backpressure slows
+    /// the server instead of events piling up in the IPC layer. Status
+    /// events (`connected`, `phase`, errors) need no ack. Server `PING`s
+    /// wait along with everything else, so a frontend that stops acking
+    /// eventually times the connection out. Unset or 0 disables it.
+    pub max_unacked_events: Option<u64>,
+}
+
+/// Event every connection emits on unless it sets `event_name`
+const DEFAULT_EVENT_NAME: &str = "tcp-message";
+
+/// Quit message used when a connection did not set its own
+const DEFAULT_QUIT_MESSAGE: &str = "ObsidianIRC";
+/// How long app exit waits for every connection to flush its `QUIT`
+const EXIT_QUIT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long `connect` waits for a replaced connection to close
+const EVICT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long `disconnect` lets the writer flush before stopping it
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long `measure_latency` waits for the `PONG`
+const LATENCY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Prefix of the tokens `measure_latency` sends, so `read_task` can tell
+/// its `PONG`s from ones the frontend asked for
+const LATENCY_TOKEN_PREFIX: &str = "obsidian-lat-";
+/// How long `whois` waits for the end of the reply
+const WHOIS_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long `flush_connection` waits for the write queue to drain
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+/// `test_connection` warns about certificates expiring sooner than this
+const CERTIFICATE_EXPIRY_WARNING: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// Prefix of keepalive `PING` tokens, whose `PONG`s `read_task` swallows
+const KEEPALIVE_TOKEN_PREFIX: &str = "obsidian-ka-";
+/// Shortest accepted `ping_interval_secs`
+const MIN_PING_INTERVAL: Duration = Duration::from_secs(5);
+/// Default `ping_timeout_secs`
+const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default and maximum size of the `read_task` buffer
+const DEFAULT_READ_BUFFER_SIZE: usize = 4096;
+const MAX_READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Bounds for `recv_buffer` and `send_buffer`
+const MIN_SOCKET_BUFFER: usize = 4 * 1024;
+const MAX_SOCKET_BUFFER: usize = 16 * 1024 * 1024;
+
+/// Default for `set_max_connections`: far more than anyone uses, but a
+/// frontend stuck in a reconnect loop can't exhaust file descriptors
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+/// Most entries `SocketState` may hold, changed by `set_max_connections`
+static MAX_CONNECTIONS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_CONNECTIONS);
+
+/// Upper bound on `batch_window_ms`, so batching can't stall the UI
+const MAX_BATCH_WINDOW: Duration = Duration::from_secs(1);
+/// A batch is emitted early once it holds this many lines
+const MAX_BATCH_LINES: usize = 500;
+
+impl ConnectOptions {
+    /// Read buffer size to use, clamped to a sane range (a zero-sized
+    /// buffer would make every read look like EOF)
+    fn read_buffer_size(&self) -> usize {
+        self.read_buffer_size
+            .unwrap_or(DEFAULT_READ_BUFFER_SIZE)
+            .clamp(512, MAX_READ_BUFFER_SIZE)
+    }
+
+    /// Charset for the connection's lines
+    fn charset(&self) -> Result<Charset, SocketError> {
+        match self.encoding.as_deref() {
+            None if self.raw => Ok(Charset::Binary),
+            None => Ok(Charset::default()),
+            Some(label) => Charset::from_label(label)
+                .ok_or_else(|| SocketError::InvalidOption(format!("Unknown encoding: {}", label))),
+        }
+    }
+
+    /// These options with every credential removed, safe to write to disk
+    fn without_secrets(&self) -> ConnectOptions {
+        ConnectOptions {
+            sasl: None,
+            http_proxy: self.http_proxy.as_deref().map(proxy::strip_credentials),
+            ..self.clone()
+        }
+    }
+
+    /// TLS version floor for the connection
+    fn min_tls_version(&self) -> Result<MinTlsVersion, SocketError> {
+        match self.min_tls_version.as_deref() {
+            None => Ok(MinTlsVersion::default()),
+            Some(label) => MinTlsVersion::from_label(label)
+                .ok_or_else(|| SocketError::InvalidOption(format!("Unsupported minimum TLS version: {}", label))),
+        }
+    }
+
+    /// Event the connection's messages are emitted on
+    fn event_name(&self) -> Result<&str, SocketError> {
+        match self.event_name.as_deref() {
+            None => Ok(DEFAULT_EVENT_NAME),
+            Some(name)
+                if !name.is_empty()
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || "-/:_".contains(c)) =>
+            {
+                Ok(name)
+            }
+            Some(name) => Err(SocketError::InvalidOption(format!("Invalid event name: {:?}", name))),
+        }
+    }
+
+    /// Line length limit for outgoing lines, `None` in raw mode
+    fn max_line_length(&self) -> Option<usize> {
+        if self.raw {
+            return None;
+        }
+        Some(self.max_line_length.filter(|&length| length > 0).unwrap_or(irc::MAX_LINE_LENGTH))
+    }
+
+    /// Keepalive state for a connection opened at `now`, if enabled
+    fn keepalive(&self, now: tokio::time::Instant) -> Option<Keepalive> {
+        if self.raw {
+            return None;
+        }
+        let interval = self.ping_interval_secs.filter(|&secs| secs > 0)?;
+        let interval = Duration::from_secs(interval).max(MIN_PING_INTERVAL);
+        let timeout = self
+            .ping_timeout_secs
+            .filter(|&secs| secs > 0)
+            .map_or(DEFAULT_PING_TIMEOUT, Duration::from_secs);
+        Some(Keepalive::new(interval, timeout, now))
+    }
+
+    /// Window for coalescing lines, `None` for one event per line
+    fn batch_window(&self) -> Option<Duration> {
+        match self.batch_window_ms {
+            None | Some(0) => None,
+            Some(ms) => Some(Duration::from_millis(ms).min(MAX_BATCH_WINDOW)),
+        }
+    }
+}
+
+/// Token bucket pacing reads to `rate` bytes per second
+/// Holds at most one second's worth, so idle time buys a short burst
+struct ReadThrottle {
+    rate: f64,
+    tokens: f64,
+    last_refill: tokio::time::Instant,
+}
+
+impl ReadThrottle {
+    fn new(bytes_per_sec: u64, now: tokio::time::Instant) -> Self {
+        let rate = bytes_per_sec.max(1) as f64;
+        ReadThrottle {
+            rate,
+            tokens: rate,
+            last_refill: now,
+        }
+    }
+
+    /// Bytes that may be read at `now`, or how long to wait for the next one
+    fn available(&mut self, now: tokio::time::Instant) -> Result<usize, Duration> {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            Ok(self.tokens as usize)
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+
+    fn consume(&mut self, bytes: usize) {
+        self.tokens -= bytes as f64;
+    }
+}
+
+/// Client-side keepalive: `PING` the server once the connection has been
+/// idle for `interval`, and give up if no `PONG` follows within `timeout`
+struct Keepalive {
+    interval: Duration,
+    timeout: Duration,
+    next_ping: tokio::time::Instant,
+    /// Token and deadline of the `PING` awaiting its `PONG`
+    awaiting: Option<(String, tokio::time::Instant)>,
+    sent: u64,
+}
+
+/// What `Keepalive::poll` wants done
+#[derive(Debug, PartialEq)]
+enum KeepaliveAction {
+    Wait,
+    /// Send `PING :<token>`
+    Ping(String),
+    TimedOut,
+}
+
+impl Keepalive {
+    fn new(interval: Duration, timeout: Duration, now: tokio::time::Instant) -> Self {
+        Keepalive {
+            interval,
+            timeout,
+            next_ping: now + interval,
+            awaiting: None,
+            sent: 0,
+        }
+    }
+
+    /// When `poll` next has something to do
+    fn deadline(&self) -> tokio::time::Instant {
+        self.awaiting.as_ref().map_or(self.next_ping, |(_, deadline)| *deadline)
+    }
+
+    fn poll(&mut self, now: tokio::time::Instant) -> KeepaliveAction {
+        match &self.awaiting {
+            Some((_, deadline)) if now >= *deadline => KeepaliveAction::TimedOut,
+            Some(_) => KeepaliveAction::Wait,
+            None if now >= self.next_ping => {
+                self.sent += 1;
+                let token = format!("{}{}", KEEPALIVE_TOKEN_PREFIX, self.sent);
+                self.awaiting = Some((token.clone(), now + self.timeout));
+                KeepaliveAction::Ping(token)
+            }
+            None => KeepaliveAction::Wait,
+        }
+    }
+
+    /// Data arrived, so there's no need to ping until the next idle period
+    /// An outstanding `PING` still needs its `PONG`
+    fn received(&mut self, now: tokio::time::Instant) {
+        if self.awaiting.is_none() {
+            self.next_ping = now + self.interval;
+        }
+    }
+
+    /// Handle a `PONG` token; true if it answered our `PING`
+    fn pong(&mut self, token: &str, now: tokio::time::Instant) -> bool {
+        if !token.starts_with(KEEPALIVE_TOKEN_PREFIX) {
+            return false;
+        }
+        if self.awaiting.as_ref().is_some_and(|(awaited, _)| awaited == token) {
+            self.restart(now);
+        }
+        true
+    }
+
+    /// Forget any outstanding `PING` and wait a full interval again
+    fn restart(&mut self, now: tokio::time::Instant) {
+        self.awaiting = None;
+        self.next_ping = now + self.interval;
+    }
+}
+
+/// Lines waiting to be emitted together in batching mode
+struct LineBatch {
+    window: Duration,
+    lines: Vec<MessageData>,
+    /// When the batch is due, counted from its first line
+    deadline: Option<tokio::time::Instant>,
+}
+
+impl LineBatch {
+    fn new(window: Duration) -> Self {
+        LineBatch {
+            window,
+            lines: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    /// Add a line; true if the batch is full and should go out now
+    fn push(&mut self, line: MessageData) -> bool {
+        if self.lines.is_empty() {
+            self.deadline = Some(tokio::time::Instant::now() + self.window);
+        }
+        self.lines.push(line);
+        self.lines.len() >= MAX_BATCH_LINES
+    }
+
+    /// Take the pending lines, if any, and reset the deadline
+    fn take(&mut self) -> Option<Vec<MessageData>> {
+        self.deadline = None;
+        (!self.lines.is_empty()).then(|| std::mem::take(&mut self.lines))
+    }
+}
+
 /// Payload we send back to TS whenever we receive data
 #[derive(Serialize, Clone)]
 struct ReceivedPayload {
@@ -39,352 +738,3117 @@ struct ReceivedPayload {
     event: MessageEvent,
 }
 
-#[derive(Serialize, Clone)]
+/// Step a connection has reached, reported as `phase` so the UI can say
+/// more than "connecting"
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ConnectionPhase {
+    /// Looking up the host (or reaching the proxy or WebSocket endpoint)
+    Resolving,
+    TcpConnected,
+    TlsHandshaking,
+    TlsEstablished,
+    /// Connected and handed over to the frontend; sent with `connected: true`
+    Ready,
+    /// `disconnect` was called; `closed` follows once the socket is done
+    Closing,
+    /// Sent with `connected: false`
+    Closed,
+}
+
+/// Why a connection closed, on the `connected: false` event
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CloseReason {
+    /// Closed by `disconnect` or `disconnect_all`; not to be reconnected
+    User,
+    /// The server closed the connection
+    Server,
+    /// A read or write failed, or the keepalive timed out
+    Error,
+}
+
+/// `observed`, unless the connection was closed on purpose first, in which
+/// case whatever ended it counts as the user's doing
+fn close_reason(closed_by_user: &AtomicBool, observed: CloseReason) -> CloseReason {
+    if closed_by_user.load(Ordering::SeqCst) {
+        CloseReason::User
+    } else {
+        observed
+    }
+}
+
+/// Whether `client_id` now belongs to a connection that replaced the one
+/// owning `closed_by_user` (`replace_existing`). The replaced connection's
+/// tasks may still be winding down, and must neither report its close
+/// under the reused id nor touch the new handle.
+async fn is_replaced(
+    state: &Mutex<HashMap<String, ConnectionHandle>>,
+    client_id: &str,
+    closed_by_user: &Arc<AtomicBool>,
+) -> bool {
+    state
+        .lock()
+        .await
+        .get(client_id)
+        .is_some_and(|handle| !Arc::ptr_eq(&handle.closed_by_user, closed_by_user))
+}
+
+/// Remove the handle stored for `client_id` if it is still the one owning
+/// `closed_by_user`
+fn remove_own_handle(
+    connections: &mut HashMap<String, ConnectionHandle>,
+    client_id: &str,
+    closed_by_user: &Arc<AtomicBool>,
+) -> Option<ConnectionHandle> {
+    match connections.get(client_id) {
+        Some(handle) if Arc::ptr_eq(&handle.closed_by_user, closed_by_user) => connections.remove(client_id),
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Clone, Default)]
 struct MessageEvent {
     message: Option<MessageData>,
+    /// Several complete lines at once, when `batch_window_ms` is set
+    messages: Option<Vec<MessageData>>,
     error: Option<String>,
     connected: Option<bool>,
+    /// Negotiated TLS parameters, only on the `connected: true` event
+    tls_info: Option<TlsInfo>,
+    /// Set on the `connected: true` event when certificate verification was
+    /// skipped, so the UI can show a warning
+    insecure: Option<bool>,
+    /// Set on the `connected: true` event when a failed TLS handshake fell
+    /// back to plaintext
+    downgraded: Option<bool>,
+    /// The address that was connected to, on the `connected: true` event
+    /// (one of several when a fallback list was given)
+    address: Option<String>,
+    /// Whether the connection is encrypted, on the `connected: true` event
+    is_tls: Option<bool>,
+    /// Options the connection couldn't honor, on the `connected: true`
+    /// event
+    warnings: Option<Vec<String>>,
+    /// Progress of `connect` and `disconnect`; phase-only events carry
+    /// nothing else
+    phase: Option<ConnectionPhase>,
+    /// Why the connection closed, on the `connected: false` event
+    reason: Option<CloseReason>,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, Default)]
 struct MessageData {
     data: Vec<u8>,
+    /// CTCP content of a `PRIVMSG`/`NOTICE` line, when `parse_ctcp` is set
+    ctcp: Option<irc::CtcpMessage>,
+    /// Unescaped IRCv3 message tags, when `parse_tags` is set and the line
+    /// has any
+    tags: Option<HashMap<String, String>>,
+    /// When the line was sent, in Unix milliseconds, with `parse_tags`:
+    /// the `server-time` `time` tag, or the receive time if there is none
+    time: Option<u64>,
+    /// Set when `time` is the receive time rather than the server's
+    time_synthesized: Option<bool>,
+    /// The line split into prefix, command and parameters, when
+    /// `parse_messages` is set
+    parsed: Option<irc::ParsedLine>,
+}
+
+/// Payload of the `caps-acked` event
+#[derive(Serialize, Clone)]
+struct CapsAckedPayload {
+    id: String,
+    /// Caps the server acknowledged
+    caps: Vec<String>,
+    /// Everything the server listed in `CAP LS`, values included
+    available: Vec<String>,
+    /// Set if negotiation was cut short; `caps` holds what got through
+    error: Option<String>,
+}
+
+/// Payload of the `sent` event, for lines sent with `confirm`
+#[derive(Serialize, Clone)]
+struct SentPayload {
+    id: String,
+    /// The number `send` returned for the line
+    sequence: u64,
+}
+
+/// Payload of the `sasl-result` event
+#[derive(Serialize, Clone)]
+struct SaslResultPayload {
+    id: String,
+    success: bool,
+    message: String,
+}
+
+/// Where socket events go: the app handle in practice, a recorder in
+/// tests, so the protocol logic runs without a Tauri app
+///
+/// Shadows `tauri::Emitter::emit`, which this module doesn't import.
+trait EventSink {
+    fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) -> tauri::Result<()>;
+}
+
+impl EventSink for tauri::AppHandle {
+    fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) -> tauri::Result<()> {
+        tauri::Emitter::emit(self, event, payload)
+    }
 }
 
 /// Read task for handling incoming data from the socket
-async fn read_task<R>(
-    client_id: String,
+#[allow(clippy::too_many_arguments)]
+async fn read_task<R, E>(
+    client_id: SharedClientId,
     mut reader: R,
-    app_handle: tauri::AppHandle,
+    events: E,
     state: Arc<Mutex<HashMap<String, ConnectionHandle>>>,
+    options: ConnectOptions,
+    traffic_log: SharedTrafficLog,
+    priority_tx: mpsc::Sender<OutgoingMessage>,
+    read_pause: Arc<ReadPause>,
+    stats: Arc<ConnectionStats>,
+    closed_by_user: Arc<AtomicBool>,
 ) where
     R: AsyncReadExt + Unpin,
+    E: EventSink,
 {
-    let mut read_buf = vec![0u8; 4096];
+    let raw = options.raw;
+    let mut read_buf = vec![0u8; options.read_buffer_size()];
     let mut line_buffer = Vec::new();
+    let mut batch = options.batch_window().map(LineBatch::new);
+    // Already validated by `connect`
+    let charset = options.charset().unwrap_or_default();
+    let event_name = options.event_name().unwrap_or(DEFAULT_EVENT_NAME);
+    let mut throttle = options
+        .max_bytes_per_sec
+        .map(|rate| ReadThrottle::new(rate, tokio::time::Instant::now()));
+    let mut keepalive = options.keepalive(tokio::time::Instant::now());
 
     loop {
-        match reader.read(&mut read_buf).await {
+        // While paused the socket isn't read, so TCP backpressure holds
+        // the server's data until resume
+        if read_pause.is_held() {
+            read_pause.wait_while_paused().await;
+            // A PONG can't have been read meanwhile; don't count that
+            if let Some(keepalive) = keepalive.as_mut() {
+                keepalive.restart(tokio::time::Instant::now());
+            }
+        }
+
+        // Likewise while the throttle's budget is spent
+        let mut limit = read_buf.len();
+        if let Some(throttle) = throttle.as_mut() {
+            match throttle.available(tokio::time::Instant::now()) {
+                Ok(available) => limit = limit.min(available),
+                Err(wait) => {
+                    // Don't hold finished lines back while waiting
+                    emit_batch(&events, event_name, &client_id.get(), batch.as_mut(), &read_pause);
+                    tokio::time::sleep(wait).await;
+                    continue;
+                }
+            }
+        }
+
+        let batch_deadline = batch.as_ref().and_then(|batch| batch.deadline);
+        let keepalive_deadline = keepalive.as_ref().map(Keepalive::deadline);
+        let deadline = batch_deadline.into_iter().chain(keepalive_deadline).min();
+        let read = match deadline {
+            // `read` is cancel safe, so giving up on it for a timer loses nothing
+            Some(deadline) => tokio::select! {
+                biased;
+
+                read = reader.read(&mut read_buf[..limit]) => read,
+                _ = tokio::time::sleep_until(deadline) => {
+                    let now = tokio::time::Instant::now();
+                    if batch_deadline.is_some_and(|due| due <= now) {
+                        emit_batch(&events, event_name, &client_id.get(), batch.as_mut(), &read_pause);
+                    }
+                    match keepalive.as_mut().map(|keepalive| keepalive.poll(now)) {
+                        Some(KeepaliveAction::Ping(token)) => {
+                            let ping = OutgoingMessage {
+                                data: format!("PING :{}", token).into(),
+                                append_crlf: true,
+                                sequence: None,
+                            };
+                            if let Err(e) = priority_tx.try_send(ping) {
+                                log::warn!("Dropped keepalive PING for {}: {}", client_id.get(), e);
+                            }
+                        }
+                        Some(KeepaliveAction::TimedOut) => {
+                            if is_replaced(&state, &client_id.get(), &closed_by_user).await {
+                                traffic_log::flush(&traffic_log).await;
+                                break;
+                            }
+                            emit_batch(&events, event_name, &client_id.get(), batch.as_mut(), &read_pause);
+                            let timeout = keepalive.as_ref().map_or(DEFAULT_PING_TIMEOUT, |k| k.timeout).as_secs();
+                            let _ = events.emit(event_name, ReceivedPayload {
+                                id: client_id.get(),
+                                event: MessageEvent {
+                                    error: Some(format!("Ping timeout: no PONG within {} seconds", timeout)),
+                                    connected: Some(false),
+                                    phase: Some(ConnectionPhase::Closed),
+                                    reason: Some(close_reason(&closed_by_user, CloseReason::Error)),
+                                    ..Default::default()
+                                },
+                            });
+
+                            // Remove connection from state
+                            let mut connections = state.lock().await;
+                            remove_own_handle(&mut connections, &client_id.get(), &closed_by_user);
+                            traffic_log::flush(&traffic_log).await;
+                            break;
+                        }
+                        Some(KeepaliveAction::Wait) | None => {}
+                    }
+                    continue;
+                }
+            },
+            None => reader.read(&mut read_buf[..limit]).await,
+        };
+        if let (Some(throttle), Ok(n)) = (throttle.as_mut(), &read) {
+            throttle.consume(*n);
+        }
+        if let Ok(n) = &read {
+            stats.bytes_in.fetch_add(*n as u64, Ordering::Relaxed);
+        }
+        if let (Some(keepalive), Ok(n)) = (keepalive.as_mut(), &read) {
+            if *n > 0 {
+                keepalive.received(tokio::time::Instant::now());
+            }
+        }
+
+        match read {
             Ok(0) => {
                 // Connection closed by server
+                if is_replaced(&state, &client_id.get(), &closed_by_user).await {
+                    traffic_log::flush(&traffic_log).await;
+                    break;
+                }
+                emit_batch(&events, event_name, &client_id.get(), batch.as_mut(), &read_pause);
                 // Emit any remaining partial data as a final message
                 if !line_buffer.is_empty() {
-                    let _ = app_handle.emit("tcp-message", ReceivedPayload {
-                        id: client_id.clone(),
+                    let _ = events.emit(event_name, ReceivedPayload {
+                        id: client_id.get(),
                         event: MessageEvent {
-                            message: Some(MessageData { data: line_buffer.clone() }),
-                            error: None,
-                            connected: None,
+                            message: Some(MessageData { data: charset.decode(&line_buffer), ..Default::default() }),
+                            ..Default::default()
                         },
                     });
                 }
 
-                let _ = app_handle.emit("tcp-message", ReceivedPayload {
-                    id: client_id.clone(),
+                let _ = events.emit(event_name, ReceivedPayload {
+                    id: client_id.get(),
                     event: MessageEvent {
-                        message: None,
-                        error: None,
                         connected: Some(false),
+                        phase: Some(ConnectionPhase::Closed),
+                        reason: Some(close_reason(&closed_by_user, CloseReason::Server)),
+                        ..Default::default()
                     },
                 });
 
                 // Remove connection from state
                 let mut connections = state.lock().await;
-                connections.remove(&client_id);
+                remove_own_handle(&mut connections, &client_id.get(), &closed_by_user);
+                traffic_log::flush(&traffic_log).await;
                 break;
             }
+            Ok(n) if raw => {
+                // Raw mode - pass the chunk through without line splitting.
+                // When it is read as UTF-8, a character cut off by the read
+                // waits in `line_buffer` for the rest of it, so the frontend
+                // never decodes half of one
+                traffic_log::record(&traffic_log, Direction::Incoming, &read_buf[..n]).await;
+                line_buffer.extend_from_slice(&read_buf[..n]);
+                let held_back = if charset.is_utf8() { charset::incomplete_utf8_tail(&line_buffer) } else { 0 };
+                let complete = line_buffer.len() - held_back;
+                if complete == 0 {
+                    continue;
+                }
+                let data = charset.decode(&line_buffer.drain(..complete).collect::<Vec<u8>>());
+                read_pause.event_sent();
+                let _ = events.emit(event_name, ReceivedPayload {
+                    id: client_id.get(),
+                    event: MessageEvent {
+                        message: Some(MessageData { data, ..Default::default() }),
+                        ..Default::default()
+                    },
+                });
+            }
             Ok(n) => {
                 // Append new data to line buffer
                 line_buffer.extend_from_slice(&read_buf[..n]);
 
-                // Extract complete lines (ending with \r\n)
-                loop {
-                    if let Some(pos) = line_buffer.windows(2).position(|w| w == b"\r\n") {
-                        // Extract the complete line including \r\n
-                        let line_data = line_buffer[..pos + 2].to_vec();
+                while let Some(line_data) = take_line(&mut line_buffer) {
+                    // Lines already read wait here while paused
+                    read_pause.wait_while_paused().await;
+                    stats.lines_in.fetch_add(1, Ordering::Relaxed);
+
+                    traffic_log::record(&traffic_log, Direction::Incoming, &line_data).await;
+
+                    if let Some(token) = irc::pong_token(&line_data) {
+                        let now = tokio::time::Instant::now();
+                        if keepalive.as_mut().is_some_and(|keepalive| keepalive.pong(&token, now)) {
+                            // Answer to our keepalive PING; not for the frontend
+                            continue;
+                        }
+                        if token.starts_with(LATENCY_TOKEN_PREFIX) {
+                            // Answer to measure_latency; not for the frontend
+                            let mut connections = state.lock().await;
+                            if let Some(waiter) = connections
+                                .get_mut(&client_id.get())
+                                .and_then(|handle| handle.pending_pings.remove(&token))
+                            {
+                                let _ = waiter.send(());
+                            }
+                            continue;
+                        }
+                    }
+
+                    if let Some(whois_line) = whois::parse_reply(&line_data) {
+                        let mut connections = state.lock().await;
+                        if let Some(handle) = connections.get_mut(&client_id.get()) {
+                            if whois::route(&mut handle.pending_whois, whois_line) {
+                                // Part of the reply to a whois call; not for the frontend
+                                continue;
+                            }
+                        }
+                    }
+
+                    // Still emitted; the merged tokens are kept for get_isupport
+                    if let Some(tokens) = irc::isupport_tokens(&line_data) {
+                        if let Some(handle) = state.lock().await.get_mut(&client_id.get()) {
+                            irc::merge_isupport(&mut handle.isupport, tokens);
+                        }
+                    }
 
-                        // Remove the line from buffer
-                        line_buffer.drain(..pos + 2);
+                    if options.auto_pong {
+                        if let Some(pong) = irc::pong_reply(&line_data) {
+                            let pong = OutgoingMessage { data: pong.into(), append_crlf: true, sequence: None };
+                            if let Err(e) = priority_tx.try_send(pong) {
+                                log::warn!("Dropped auto-PONG for {}: {}", client_id.get(), e);
+                            }
+                        }
+                    }
 
+                    let data = charset.decode(&line_data);
+                    let ctcp = if options.parse_ctcp { irc::parse_ctcp(&data) } else { None };
+                    let parsed = if options.parse_messages { irc::parse_line(&data) } else { None };
+                    let mut message = MessageData { data, ctcp, parsed, ..Default::default() };
+                    if options.parse_tags {
+                        message.tags = irc::parse_tags(&message.data);
+                        let server_time = message
+                            .tags
+                            .as_ref()
+                            .and_then(|tags| tags.get("time"))
+                            .and_then(|time| irc::parse_server_time(time));
+                        message.time = Some(server_time.unwrap_or_else(unix_millis));
+                        message.time_synthesized = server_time.is_none().then_some(true);
+                    }
+                    match batch.as_mut() {
+                        Some(pending) => {
+                            if pending.push(message) {
+                                emit_batch(&events, event_name, &client_id.get(), batch.as_mut(), &read_pause);
+                            }
+                        }
                         // Emit the complete line
-                        let _ = app_handle.emit("tcp-message", ReceivedPayload {
-                            id: client_id.clone(),
-                            event: MessageEvent {
-                                message: Some(MessageData { data: line_data }),
-                                error: None,
-                                connected: None,
-                            },
-                        });
-                    } else {
-                        // No complete line found, wait for more data
-                        break;
+                        None => {
+                            read_pause.event_sent();
+                            let _ = events.emit(event_name, ReceivedPayload {
+                                id: client_id.get(),
+                                event: MessageEvent {
+                                    message: Some(message),
+                                    ..Default::default()
+                                },
+                            });
+                        }
                     }
                 }
             }
             Err(e) => {
                 // Read error - emit error event and stop
-                let _ = app_handle.emit("tcp-message", ReceivedPayload {
-                    id: client_id.clone(),
+                if is_replaced(&state, &client_id.get(), &closed_by_user).await {
+                    traffic_log::flush(&traffic_log).await;
+                    break;
+                }
+                emit_batch(&events, event_name, &client_id.get(), batch.as_mut(), &read_pause);
+                let _ = events.emit(event_name, ReceivedPayload {
+                    id: client_id.get(),
                     event: MessageEvent {
-                        message: None,
                         error: Some(format!("Read error: {}", e)),
                         connected: Some(false),
+                        phase: Some(ConnectionPhase::Closed),
+                        reason: Some(close_reason(&closed_by_user, CloseReason::Error)),
+                        ..Default::default()
                     },
                 });
 
                 // Remove connection from state
                 let mut connections = state.lock().await;
-                connections.remove(&client_id);
+                remove_own_handle(&mut connections, &client_id.get(), &closed_by_user);
+                traffic_log::flush(&traffic_log).await;
                 break;
             }
         }
     }
 }
 
+/// Remove the first complete line, including its `\r\n`, from
+/// `line_buffer`
+fn take_line(line_buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let pos = line_buffer.windows(2).position(|w| w == b"\r\n")?;
+    Some(line_buffer.drain(..pos + 2).collect())
+}
+
+/// Current time in Unix milliseconds
+fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Emit the lines pending in `batch` as one `messages` event
+fn emit_batch(
+    events: &impl EventSink,
+    event_name: &str,
+    client_id: &str,
+    batch: Option<&mut LineBatch>,
+    read_pause: &ReadPause,
+) {
+    let Some(messages) = batch.and_then(LineBatch::take) else {
+        return;
+    };
+    read_pause.event_sent();
+    let _ = events.emit(event_name, ReceivedPayload {
+        id: client_id.to_string(),
+        event: MessageEvent {
+            messages: Some(messages),
+            ..Default::default()
+        },
+    });
+}
+
 /// Write task for handling outgoing data to the socket
-async fn write_task<W>(
+/// Returns the error that stopped it, if a write or flush failed
+#[allow(clippy::too_many_arguments)]
+async fn write_task<W, E>(
     mut writer: W,
-    mut write_rx: mpsc::Receiver<String>,
+    mut write_rx: mpsc::Receiver<OutgoingMessage>,
+    mut priority_rx: mpsc::Receiver<OutgoingMessage>,
     mut shutdown_rx: oneshot::Receiver<()>,
-) where
+    raw: bool,
+    charset: Charset,
+    traffic_log: SharedTrafficLog,
+    stats: Arc<ConnectionStats>,
+    events: E,
+    client_id: SharedClientId,
+) -> std::io::Result<()>
+where
     W: AsyncWriteExt + Unpin,
+    E: EventSink,
 {
     loop {
         tokio::select! {
-            // Handle write commands
-            Some(data) = write_rx.recv() => {
-                // Add IRC line ending if not present
-                let data_with_crlf = if data.ends_with("\r\n") {
-                    data
-                } else {
-                    format!("{}\r\n", data)
-                };
-
-                if let Err(e) = writer.write_all(data_with_crlf.as_bytes()).await {
-                    eprintln!("Write error: {}", e);
-                    break;
-                }
+            // Drain priority writes first, then regular writes, so anything
+            // queued before a shutdown still goes out
+            biased;
 
-                if let Err(e) = writer.flush().await {
-                    eprintln!("Flush error: {}", e);
-                    break;
+            // Handle priority write commands (keepalive replies)
+            Some(OutgoingMessage { data, append_crlf, .. }) = priority_rx.recv() => {
+                write_message(&mut writer, data, append_crlf, raw, charset, &traffic_log, &stats).await?;
+            }
+            // Handle write commands
+            Some(OutgoingMessage { data, append_crlf, sequence }) = write_rx.recv() => {
+                write_message(&mut writer, data, append_crlf, raw, charset, &traffic_log, &stats).await?;
+                if let Some(sequence) = sequence {
+                    let _ = events.emit("sent", SentPayload { id: client_id.get(), sequence });
                 }
             }
             // Handle shutdown signal
             _ = &mut shutdown_rx => {
                 let _ = writer.shutdown().await;
-                break;
+                traffic_log::flush(&traffic_log).await;
+                return Ok(());
             }
         }
     }
 }
 
-/// Connect to IRC server with real TCP/TLS implementation
-#[tauri::command]
-pub async fn connect(
-    client_id: String,
-    address: String,
-    state: State<'_, SocketState>,
-    app_handle: tauri::AppHandle,
-) -> Result<(), String> {
-    // Parse the address to determine protocol and extract host:port
-    let (use_tls, host, port) = parse_address(&address)?;
+/// Write a single queued message to the socket and flush it
+async fn write_message<W>(
+    writer: &mut W,
+    data: OutgoingData,
+    append_crlf: bool,
+    raw: bool,
+    charset: Charset,
+    traffic_log: &SharedTrafficLog,
+    stats: &ConnectionStats,
+) -> std::io::Result<()>
+where
+    W: AsyncWriteExt + Unpin,
+{
+    let bytes = match data {
+        OutgoingData::Flush(done) => {
+            writer.flush().await?;
+            let _ = done.send(());
+            return Ok(());
+        }
+        // Add IRC line ending if not present (never in raw mode)
+        OutgoingData::Text(text) if raw || !append_crlf || text.ends_with("\r\n") => charset.encode(&text),
+        OutgoingData::Text(text) => charset.encode(&format!("{}\r\n", text)),
+        OutgoingData::Bytes(bytes) => bytes,
+    };
+    traffic_log::record(traffic_log, Direction::Outgoing, &bytes).await;
 
-    // Create TCP connection
-    let tcp_stream = TcpStream::connect(format!("{}:{}", host, port))
-        .await
-        .map_err(|e| format!("Failed to connect to {}:{}: {}", host, port, e))?;
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
+    stats.record_out(&bytes);
+    Ok(())
+}
 
-    // Create channels for write operations
-    let (write_tx, write_rx) = mpsc::channel::<String>(100);
-    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+/// One server address, or a fallback list to try in order
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ServerAddresses {
+    List(Vec<String>),
+    /// A single address, or several separated by commas
+    One(String),
+}
 
-    // Handle TLS if needed
-    if use_tls {
-        // Create TLS connection based on platform
-        #[cfg(not(target_os = "android"))]
-        {
-            let connector = TlsConnector::from(
-                NativeTlsConnector::builder()
-                    .build()
-                    .map_err(|e| format!("Failed to create TLS connector: {}", e))?
-            );
+impl ServerAddresses {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            ServerAddresses::List(addresses) => addresses,
+            ServerAddresses::One(address) => split_addresses(&address),
+        }
+    }
+}
 
-            let tls_stream = connector
-                .connect(&host, tcp_stream)
-                .await
-                .map_err(|e| format!("TLS handshake failed: {}", e))?;
+/// Split a comma-separated address list
+///
+/// URL paths may themselves list channels with commas
+/// (`ircs://host/#a,#b`), so once an address has a path, following
+/// pieces without a scheme stay part of it.
+fn split_addresses(list: &str) -> Vec<String> {
+    let mut addresses: Vec<String> = Vec::new();
+    for piece in list.split(',') {
+        let piece = piece.trim();
+        match addresses.last_mut() {
+            Some(last) if !piece.contains("://") && has_path(last) => {
+                last.push(',');
+                last.push_str(piece);
+            }
+            _ if piece.is_empty() => {}
+            _ => addresses.push(piece.to_string()),
+        }
+    }
+    addresses
+}
 
-            // Split the TLS stream using tokio::io::split
-            let (reader, writer) = tokio::io::split(tls_stream);
+/// Whether `address` has anything after its authority
+fn has_path(address: &str) -> bool {
+    let authority = address.split_once("://").map_or(address, |(_, rest)| rest);
+    authority.contains(['/', '?', '#'])
+}
 
-            // Spawn read task
-            let client_id_read = client_id.clone();
-            let app_handle_read = app_handle.clone();
-            let state_clone = state.0.clone();
-            task::spawn(async move {
-                read_task(client_id_read, reader, app_handle_read, state_clone).await;
-            });
+/// Connect to IRC server with real TCP/TLS implementation
+///
+/// `address` may be a fallback list (an array, or comma-separated); each
+/// entry is tried in order until one connects, and the `connected` event
+/// reports which one did.
+///
+/// A `client_id` that is already connected fails with `AlreadyConnected`
+/// and leaves that connection alone, unless `replace_existing` is set, in
+/// which case the old connection is shut down first.
+#[tauri::command]
+pub async fn connect(
+    client_id: String,
+    address: ServerAddresses,
+    options: Option<ConnectOptions>,
+    replace_existing: Option<bool>,
+    state: State<'_, SocketState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), SocketError> {
+    let options = options.unwrap_or_default();
+    // Checked up front so a bad value fails before anything connects
+    options.charset()?;
+    options.min_tls_version()?;
+    let event_name = options.event_name()?;
+    if let Some(credentials) = &options.sasl {
+        credentials.validate()?;
+    }
+    if let Some(request_caps) = &options.request_caps {
+        caps::validate(request_caps)?;
+    }
+    if let Some(cipher_suites) = &options.cipher_suites {
+        tls::validate_cipher_suites(cipher_suites)?;
+    }
+    let replace_existing = replace_existing.unwrap_or(false);
 
-            // Spawn write task
-            task::spawn(async move {
-                write_task(writer, write_rx, shutdown_rx).await;
-            });
+    {
+        let mut connections = state.0.lock().await;
+        if connections.contains_key(&client_id) && !replace_existing {
+            return Err(SocketError::already_connected(&client_id));
+        }
+        // A reconnect reusing this id must not leave the old tasks running
+        if let Some(handle) = connections.remove(&client_id) {
+            drop(connections);
+            log::warn!("Evicting live connection for {} before reconnecting", client_id);
+            handle.shutdown(EVICT_TIMEOUT).await;
+        } else {
+            check_connection_limit(&connections, &client_id)?;
         }
+    }
 
-        #[cfg(target_os = "android")]
-        {
-            // Create rustls config with webpki roots
-            let root_store = rustls::RootCertStore {
-                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
-            };
+    let report_phase = |phase| {
+        let _ = app_handle.emit(event_name, ReceivedPayload {
+            id: client_id.clone(),
+            event: MessageEvent {
+                phase: Some(phase),
+                ..Default::default()
+            },
+        });
+    };
+    let addresses = address.into_vec();
+    let (address, stream) = open_first(addresses.clone(), &options, &report_phase).await?;
+    attach_stream(
+        client_id.clone(),
+        address,
+        stream,
+        &options,
+        replace_existing,
+        &state,
+        &app_handle,
+    )
+    .await?;
 
-            let config = rustls::ClientConfig::builder()
-                .with_root_certificates(root_store)
-                .with_no_client_auth();
+    if options.persist_session {
+        session::save(&app_handle, session::SavedSession {
+            client_id,
+            address: addresses,
+            options: options.without_secrets(),
+        })
+        .await;
+    } else {
+        // Reusing the id without persistence drops what an earlier
+        // connection saved
+        session::remove(&app_handle, &client_id).await;
+    }
+
+    Ok(())
+}
 
-            let connector = TlsConnector::from(StdArc::new(config));
+/// Result of `test_connection`
+#[derive(Debug, Default, Serialize)]
+pub struct ConnectionTestReport {
+    host: String,
+    port: u16,
+    is_tls: bool,
+    /// Every address the host resolved to; empty through an HTTP proxy,
+    /// which resolves the host itself
+    resolved_addresses: Vec<String>,
+    /// The address that accepted the connection
+    remote_addr: Option<String>,
+    resolve_ms: Option<u64>,
+    /// TCP connect time, after resolution (including the proxy tunnel)
+    connect_ms: u64,
+    tls_handshake_ms: Option<u64>,
+    tls_info: Option<TlsInfo>,
+    /// Why the certificate failed validation; `tls_info` still describes
+    /// it, from a second handshake that skipped verification
+    certificate_problem: Option<CertificateProblem>,
+    /// Things worth showing next to a successful result, e.g. a
+    /// certificate about to expire
+    warnings: Vec<String>,
+}
 
-            let server_name = ServerName::try_from(host.clone())
-                .map_err(|_| format!("Invalid DNS name: {}", host))?;
+/// Check that `address` is reachable without connecting for real: resolve
+/// it, open a TCP connection and, for `ircs://`, complete the TLS
+/// handshake, then close the socket again
+///
+/// Nothing is sent, no `ConnectionHandle` is created and no events are
+/// emitted. `options` apply as they would to `connect` (`resolver`,
+/// `http_proxy`, `sni_host`, `min_tls_version`, ...). A certificate that
+/// fails validation doesn't fail the test: it is reported in
+/// `certificate_problem` along with its details. Every other failure
+/// returns the error `connect` would have. Only `irc://` and `ircs://`
+/// addresses can be tested.
+#[tauri::command]
+pub async fn test_connection(
+    address: String,
+    options: Option<ConnectOptions>,
+) -> Result<ConnectionTestReport, SocketError> {
+    let options = options.unwrap_or_default();
+    options.min_tls_version()?;
+    if let Some(cipher_suites) = &options.cipher_suites {
+        tls::validate_cipher_suites(cipher_suites)?;
+    }
+    let (transport, host, port) = parse_address(&address)?;
+    if !matches!(transport, Transport::Tcp | Transport::Tls) {
+        return Err(SocketError::Unsupported(format!(
+            "Only irc:// and ircs:// addresses can be tested: {}",
+            address
+        )));
+    }
+    let mut report = ConnectionTestReport {
+        host: host.clone(),
+        port,
+        is_tls: transport == Transport::Tls,
+        ..Default::default()
+    };
 
-            let tls_stream = connector
-                .connect(server_name, tcp_stream)
-                .await
-                .map_err(|e| format!("TLS handshake failed: {}", e))?;
+    let tcp_stream = test_tcp(&host, port, &options, &mut report).await?;
+    if !report.is_tls {
+        report.warnings.push("The connection is not encrypted".to_string());
+        return Ok(report);
+    }
+    if options.danger_skip_verification {
+        report.warnings.push("Certificate verification is disabled".to_string());
+    }
+    if options.cipher_suites.is_some() && !tls::CIPHER_SUITES_CONFIGURABLE {
+        report.warnings.push(tls::CIPHER_SUITES_IGNORED.to_string());
+    }
 
-            // Split the TLS stream using tokio::io::split
-            let (reader, writer) = tokio::io::split(tls_stream);
+    let started = Instant::now();
+    let stream = match tls::handshake(&host, tcp_stream, &options).await {
+        Ok(stream) => stream,
+        Err(SocketError::TlsCertificate(message, problem)) => {
+            // Look again without verification, to show what was presented
+            report.warnings.push(message);
+            report.certificate_problem = Some(problem);
+            let insecure = ConnectOptions {
+                danger_skip_verification: true,
+                ..options.clone()
+            };
+            let tcp_stream = open_tcp(&host, port, &options).await?;
+            let started = Instant::now();
+            let stream = tls::handshake(&host, tcp_stream, &insecure).await?;
+            report.tls_handshake_ms = Some(started.elapsed().as_millis() as u64);
+            stream
+        }
+        Err(e) => return Err(e),
+    };
+    report.tls_handshake_ms.get_or_insert(started.elapsed().as_millis() as u64);
+    if let Some(certificate) = stream.tls_info.as_ref().and_then(|info| info.certificate.as_ref()) {
+        report.warnings.extend(certificate_warnings(certificate, unix_millis() / 1000));
+    }
+    report.tls_info = stream.tls_info;
+    Ok(report)
+}
 
-            // Spawn read task
-            let client_id_read = client_id.clone();
-            let app_handle_read = app_handle.clone();
-            let state_clone = state.0.clone();
-            task::spawn(async move {
-                read_task(client_id_read, reader, app_handle_read, state_clone).await;
-            });
+/// The TCP part of `test_connection`, timing resolution and connect
+/// separately where `open_tcp` does both
+async fn test_tcp(
+    host: &str,
+    port: u16,
+    options: &ConnectOptions,
+    report: &mut ConnectionTestReport,
+) -> Result<TcpStream, SocketError> {
+    let tcp_stream = match &options.http_proxy {
+        Some(_) => {
+            let started = Instant::now();
+            let tcp_stream = open_tcp(host, port, options).await?;
+            report.connect_ms = started.elapsed().as_millis() as u64;
+            tcp_stream
+        }
+        None => {
+            let started = Instant::now();
+            let addrs = resolve(host, port, options).await?;
+            report.resolve_ms = Some(started.elapsed().as_millis() as u64);
+            report.resolved_addresses = addrs.iter().map(SocketAddr::to_string).collect();
+            let started = Instant::now();
+            let tcp_stream = happy_eyeballs::connect(&join_host_port(host, port), addrs, options.disable_happy_eyeballs).await?;
+            report.connect_ms = started.elapsed().as_millis() as u64;
+            tcp_stream
+        }
+    };
+    report.remote_addr = tcp_stream.peer_addr().ok().map(|addr| addr.to_string());
+    Ok(tcp_stream)
+}
 
-            // Spawn write task
-            task::spawn(async move {
-                write_task(writer, write_rx, shutdown_rx).await;
-            });
+/// Warnings about a certificate that validated, or was let through, as of
+/// `now` (Unix seconds)
+fn certificate_warnings(certificate: &certificate::CertificateInfo, now: u64) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if let Some(not_after) = certificate.not_after {
+        let remaining = not_after.saturating_sub(now);
+        if remaining > 0 && remaining < CERTIFICATE_EXPIRY_WARNING.as_secs() {
+            warnings.push(format!("The certificate expires in {} days", remaining.div_ceil(86_400)));
         }
-    } else {
-        // Plain TCP - use into_split for owned halves
-        let (reader, writer) = tcp_stream.into_split();
+    }
+    if certificate.subject.is_some() && certificate.subject == certificate.issuer {
+        warnings.push("The certificate is self-signed".to_string());
+    }
+    warnings
+}
+
+/// Take over an already connected TCP socket, e.g. one opened by a
+/// spawned stunnel or a test harness, and run it as a plain connection
+///
+/// The backend owns `raw_fd` from then on and closes it on disconnect. An
+/// fd that isn't an open, connected TCP socket fails with `InvalidAddress`
+/// and is left untouched.
+#[cfg(unix)]
+#[tauri::command]
+pub async fn connect_fd(
+    client_id: String,
+    raw_fd: i32,
+    state: State<'_, SocketState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), SocketError> {
+    {
+        let connections = state.0.lock().await;
+        if connections.contains_key(&client_id) {
+            return Err(SocketError::already_connected(&client_id));
+        }
+        check_connection_limit(&connections, &client_id)?;
+    }
+
+    let tcp_stream = tcp_stream_from_fd(raw_fd)?;
+    let addrs = tcp_addrs(&tcp_stream);
+    let (reader, writer) = tcp_stream.into_split();
+    attach_stream(
+        client_id,
+        format!("fd:{}", raw_fd),
+        OpenedStream::new(reader, writer).with_addrs(addrs),
+        &ConnectOptions::default(),
+        false,
+        &state,
+        &app_handle,
+    )
+    .await
+}
+
+/// Wrap `raw_fd` in a `TcpStream`, after checking it is an open socket
+/// with a TCP peer
+#[cfg(unix)]
+fn tcp_stream_from_fd(raw_fd: i32) -> Result<TcpStream, SocketError> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+    let invalid = |reason: String| SocketError::InvalidAddress(format!("fd {} {}", raw_fd, reason));
+
+    // `from_raw_fd` requires an open fd; `/dev/fd` only lists those
+    let is_socket = raw_fd >= 0
+        && std::fs::metadata(format!("/dev/fd/{}", raw_fd)).is_ok_and(|meta| meta.file_type().is_socket());
+    if !is_socket {
+        return Err(invalid("is not an open socket".to_string()));
+    }
+
+    // SAFETY: the fd is open, and the caller hands its ownership to us
+    let std_stream = unsafe { std::net::TcpStream::from_raw_fd(raw_fd) };
+    // `SO_TYPE` rules out datagram sockets, which can have a peer too;
+    // `peer_addr` fails for listening and unconnected sockets and for
+    // non-IP ones
+    let checked = socket2::SockRef::from(&std_stream)
+        .r#type()
+        .and_then(|kind| match kind {
+            socket2::Type::STREAM => Ok(()),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a stream socket")),
+        })
+        .and_then(|_| std_stream.peer_addr())
+        .and_then(|_| std_stream.set_nonblocking(true));
+    if let Err(e) = checked {
+        // Give the fd back rather than close it
+        let _ = std_stream.into_raw_fd();
+        return Err(invalid(format!("is not a connected TCP socket: {}", e)));
+    }
+    TcpStream::from_std(std_stream).map_err(|e| SocketError::Io(format!("Failed to adopt fd {}: {}", raw_fd, e)))
+}
+
+/// Run compression and SASL over an opened `stream`, start its read and
+/// write tasks, store the handle and emit the `connected` event
+async fn attach_stream(
+    client_id: String,
+    address: String,
+    stream: OpenedStream,
+    options: &ConnectOptions,
+    replace_existing: bool,
+    state: &SocketState,
+    app_handle: &tauri::AppHandle,
+) -> Result<(), SocketError> {
+    let raw = options.raw;
+    let charset = options.charset()?;
+    let event_name = options.event_name()?.to_string();
+    let read_options = options.clone();
+    let OpenedStream {
+        mut reader,
+        mut writer,
+        tls_info,
+        downgraded,
+        is_tls,
+        local_addr,
+        remote_addr,
+    } = stream;
 
-        // Spawn read task
-        let client_id_read = client_id.clone();
-        let app_handle_read = app_handle.clone();
-        let state_clone = state.0.clone();
-        task::spawn(async move {
-            read_task(client_id_read, reader, app_handle_read, state_clone).await;
+    if options.enable_compression {
+        // Below SASL and the line logic, which only ever see plain bytes
+        reader = Box::new(compression::ZlibReader::new(reader));
+        writer = Box::new(compression::ZlibWriter::new(writer));
+    }
+
+    if let Some(request_caps) = &options.request_caps {
+        let keep_open = options.sasl.is_some();
+        let (outcome, pending) = caps::negotiate(&mut reader, &mut writer, request_caps, keep_open).await;
+        log::info!("CAP for {}: acked [{}] ({:?})", client_id, outcome.acked.join(" "), outcome.error);
+        let _ = app_handle.emit("caps-acked", CapsAckedPayload {
+            id: client_id.clone(),
+            caps: outcome.acked,
+            available: outcome.available,
+            error: outcome.error,
         });
 
-        // Spawn write task
-        task::spawn(async move {
-            write_task(writer, write_rx, shutdown_rx).await;
+        reader = Box::new(std::io::Cursor::new(pending).chain(reader));
+    }
+
+    if let Some(credentials) = &options.sasl {
+        let (outcome, pending) = sasl::authenticate(&mut reader, &mut writer, credentials).await;
+        log::info!("SASL for {}: success={} ({})", client_id, outcome.success, outcome.message);
+        let _ = app_handle.emit("sasl-result", SaslResultPayload {
+            id: client_id.clone(),
+            success: outcome.success,
+            message: outcome.message,
         });
+
+        // Lines read during the exchange that weren't part of it go first
+        reader = Box::new(std::io::Cursor::new(pending).chain(reader));
     }
 
-    // Store the connection handle
+    // Create channels for write operations
+    let (write_tx, write_rx) = mpsc::channel::<OutgoingMessage>(100);
+    // Keepalive replies jump ahead of regular writes
+    let (priority_tx, priority_rx) = mpsc::channel::<OutgoingMessage>(16);
+    let priority_tx_handle = priority_tx.clone();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let traffic_log: SharedTrafficLog = Arc::new(Mutex::new(None));
+    let read_pause = Arc::new(ReadPause::new(options.max_unacked_events));
+    let stats = Arc::new(ConnectionStats::default());
+
+    // Held until the handle is stored, so a task failing right away still
+    // finds it to remove
     let mut connections = state.0.lock().await;
+
+    // A second connect for this id (e.g. a double click) may have finished
+    // while this one was opening; dropping our stream closes it again
+    if connections.contains_key(&client_id) && !replace_existing {
+        return Err(SocketError::already_connected(&client_id));
+    }
+    // Checked again now that the lock is held, as other connects may have
+    // filled the last slot meanwhile
+    check_connection_limit(&connections, &client_id)?;
+    let displaced = connections.remove(&client_id);
+
+    // Spawn read task
+    let shared_client_id = SharedClientId::new(&client_id);
+    let client_id_read = shared_client_id.clone();
+    let app_handle_read = app_handle.clone();
+    let state_clone = state.0.clone();
+    let traffic_log_read = traffic_log.clone();
+    let read_pause_read = read_pause.clone();
+    let stats_read = stats.clone();
+    let closed_by_user = Arc::new(AtomicBool::new(false));
+    let closed_by_user_read = closed_by_user.clone();
+    let read_handle = task::spawn(async move {
+        read_task(
+            client_id_read,
+            reader,
+            app_handle_read,
+            state_clone,
+            read_options,
+            traffic_log_read,
+            priority_tx,
+            read_pause_read,
+            stats_read,
+            closed_by_user_read,
+        )
+        .await;
+    });
+
+    // Spawn write task
+    let client_id_write = shared_client_id.clone();
+    let app_handle_write = app_handle.clone();
+    let state_write = state.0.clone();
+    let traffic_log_write = traffic_log.clone();
+    let stats_write = stats.clone();
+    let closed_by_user_write = closed_by_user.clone();
+    let event_name_write = event_name.clone();
+    let write_handle = task::spawn(async move {
+        let result = write_task(
+            writer,
+            write_rx,
+            priority_rx,
+            shutdown_rx,
+            raw,
+            charset,
+            traffic_log_write.clone(),
+            stats_write,
+            app_handle_write.clone(),
+            client_id_write.clone(),
+        )
+        .await;
+        if let Err(e) = result {
+            if is_replaced(&state_write, &client_id_write.get(), &closed_by_user_write).await {
+                traffic_log::flush(&traffic_log_write).await;
+                return;
+            }
+            // Surface the failure instead of letting sends queue into a
+            // dead channel
+            let _ = app_handle_write.emit(&event_name_write, ReceivedPayload {
+                id: client_id_write.get(),
+                event: MessageEvent {
+                    error: Some(format!("Write failed: {}", e)),
+                    connected: Some(false),
+                    phase: Some(ConnectionPhase::Closed),
+                    reason: Some(close_reason(&closed_by_user_write, CloseReason::Error)),
+                    ..Default::default()
+                },
+            });
+
+            // Remove connection from state; its reader would otherwise
+            // report the same disconnect again
+            let mut connections = state_write.lock().await;
+            if let Some(handle) = remove_own_handle(&mut connections, &client_id_write.get(), &closed_by_user_write) {
+                handle.read_task.abort();
+            }
+            drop(connections);
+            traffic_log::flush(&traffic_log_write).await;
+        }
+    });
+
+    // Store the connection handle
     connections.insert(client_id.clone(), ConnectionHandle {
         write_tx,
+        priority_tx: priority_tx_handle,
         shutdown_tx: Some(shutdown_tx),
+        traffic_log,
+        write_task: write_handle,
+        read_task: read_handle,
+        quit_message: options.quit_message.clone(),
+        is_tls,
+        pending_pings: HashMap::new(),
+        read_pause,
+        tls_info: tls_info.clone(),
+        local_addr,
+        remote_addr,
+        connected_at: unix_millis(),
+        stats,
+        max_line_length: options.max_line_length(),
+        closed_by_user,
+        event_name: event_name.clone(),
+        isupport: HashMap::new(),
+        pending_whois: HashMap::new(),
+        client_id: shared_client_id,
     });
+    drop(connections);
+    if let Some(handle) = displaced {
+        log::warn!("Evicting live connection for {} before reconnecting", client_id);
+        handle.shutdown(EVICT_TIMEOUT).await;
+    }
+
+    // `tls_info` is only set by our own handshake; the WebSocket client
+    // picks its suites itself
+    let cipher_suites_ignored = is_tls
+        && options.cipher_suites.is_some()
+        && !(tls_info.is_some() && tls::CIPHER_SUITES_CONFIGURABLE);
 
     // Emit connected event
-    let _ = app_handle.emit("tcp-message", ReceivedPayload {
-        id: client_id,
+    let _ = app_handle.emit(&event_name, ReceivedPayload {
+        id: client_id.clone(),
         event: MessageEvent {
-            message: None,
-            error: None,
             connected: Some(true),
+            warnings: cipher_suites_ignored.then(|| vec![tls::CIPHER_SUITES_IGNORED.to_string()]),
+            insecure: (tls_info.is_some() && options.danger_skip_verification).then_some(true),
+            tls_info,
+            downgraded: downgraded.then_some(true),
+            address: Some(address),
+            is_tls: Some(is_tls),
+            phase: Some(ConnectionPhase::Ready),
+            ..Default::default()
         },
     });
 
     Ok(())
 }
 
-/// Parse address string to extract protocol, host, and port
-fn parse_address(address: &str) -> Result<(bool, String, u16), String> {
+/// Open a stream to the first address in `addresses` that accepts one
+/// A lone address fails with its own error; a list that fails entirely
+/// returns `AllAddressesFailed` naming every failure
+async fn open_first(
+    addresses: Vec<String>,
+    options: &ConnectOptions,
+    report_phase: &PhaseReporter<'_>,
+) -> Result<(String, OpenedStream), SocketError> {
+    if addresses.len() <= 1 {
+        let address = addresses.into_iter().next().unwrap_or_default();
+        let stream = open_address(&address, options, report_phase).await?;
+        return Ok((address, stream));
+    }
+
+    let mut failures = Vec::new();
+    for address in addresses {
+        match open_address(&address, options, report_phase).await {
+            Ok(stream) => return Ok((address, stream)),
+            Err(e) => {
+                log::warn!("Connecting to {} failed, trying the next address: {}", address, e);
+                failures.push(format!("{}: {}", address, e));
+            }
+        }
+    }
+    Err(SocketError::AllAddressesFailed(format!(
+        "All addresses failed ({})",
+        failures.join("; ")
+    )))
+}
+
+/// Parse `address` and open a stream to it
+async fn open_address(
+    address: &str,
+    options: &ConnectOptions,
+    report_phase: &PhaseReporter<'_>,
+) -> Result<OpenedStream, SocketError> {
+    let (transport, host, port) = parse_address(address)?;
+    open_stream(&transport, &host, port, options, report_phase).await
+}
+
+/// Callback `open_stream` reports each `ConnectionPhase` it reaches to
+type PhaseReporter<'a> = dyn Fn(ConnectionPhase) + Sync + 'a;
+
+/// Establish the underlying stream for `transport` and split it into halves
+async fn open_stream(
+    transport: &Transport,
+    host: &str,
+    port: u16,
+    options: &ConnectOptions,
+    report_phase: &PhaseReporter<'_>,
+) -> Result<OpenedStream, SocketError> {
+    match transport {
+        Transport::WebSocket { url } => {
+            // The WebSocket client resolves, connects and handshakes in one go
+            report_phase(ConnectionPhase::Resolving);
+            let stream = websocket::connect(url).await.map_err(SocketError::WebSocket)?;
+            let (reader, writer) = tokio::io::split(stream);
+            let mut stream = OpenedStream::new(reader, writer);
+            stream.is_tls = url.starts_with("wss://");
+            report_phase(ConnectionPhase::TcpConnected);
+            if stream.is_tls {
+                report_phase(ConnectionPhase::TlsEstablished);
+            }
+            return Ok(stream);
+        }
+        Transport::Unix { path } => return open_unix_stream(path).await,
+        Transport::Tcp | Transport::Tls => {}
+    }
+
+    report_phase(ConnectionPhase::Resolving);
+    let tcp_stream = open_tcp(host, port, options).await?;
+    let addrs = tcp_addrs(&tcp_stream);
+    report_phase(ConnectionPhase::TcpConnected);
+
+    if *transport != Transport::Tls {
+        // Plain TCP - use into_split for owned halves
+        let (reader, writer) = tcp_stream.into_split();
+        return Ok(OpenedStream::new(reader, writer).with_addrs(addrs));
+    }
+
+    report_phase(ConnectionPhase::TlsHandshaking);
+    match tls::handshake(host, tcp_stream, options).await {
+        Err(e @ (SocketError::TlsHandshake(_) | SocketError::TlsCertificate(..) | SocketError::TlsProtocolVersion(_)))
+            if options.allow_plaintext_fallback =>
+        {
+            log::warn!(
+                "TLS handshake with {}:{} failed ({}), retrying in plaintext",
+                host,
+                port,
+                e
+            );
+            // The failed handshake consumed the old stream, start over
+            report_phase(ConnectionPhase::Resolving);
+            let tcp_stream = open_tcp(host, port, options).await?;
+            let addrs = tcp_addrs(&tcp_stream);
+            report_phase(ConnectionPhase::TcpConnected);
+            let (reader, writer) = tcp_stream.into_split();
+            let mut stream = OpenedStream::new(reader, writer).with_addrs(addrs);
+            stream.downgraded = true;
+            Ok(stream)
+        }
+        result => result.map(|mut stream| {
+            report_phase(ConnectionPhase::TlsEstablished);
+            stream.is_tls = true;
+            stream.with_addrs(addrs)
+        }),
+    }
+}
+
+/// Open a TCP connection, tunneled through the HTTP proxy if one is set
+async fn open_tcp(host: &str, port: u16, options: &ConnectOptions) -> Result<TcpStream, SocketError> {
+    let tcp_stream = match &options.http_proxy {
+        Some(http_proxy) => proxy::http_connect(http_proxy, host, port)
+            .await
+            .map_err(SocketError::Proxy)?,
+        None => connect_tcp(host, port, options).await?,
+    };
+    apply_socket_buffers(&tcp_stream, options);
+    Ok(tcp_stream)
+}
+
+/// Apply the `recv_buffer`/`send_buffer` options to `tcp_stream` before
+/// anything is read or written, logging the sizes the OS settled on
+/// Failures are logged only; the connection works either way
+fn apply_socket_buffers(tcp_stream: &TcpStream, options: &ConnectOptions) {
+    let clamp = |size: usize| size.clamp(MIN_SOCKET_BUFFER, MAX_SOCKET_BUFFER);
+    let socket = socket2::SockRef::from(tcp_stream);
+    if let Some(size) = options.recv_buffer.map(clamp) {
+        match socket.set_recv_buffer_size(size).and_then(|_| socket.recv_buffer_size()) {
+            Ok(applied) => log::info!("SO_RCVBUF set to {} bytes (asked for {})", applied, size),
+            Err(e) => log::warn!("Failed to set SO_RCVBUF to {} bytes: {}", size, e),
+        }
+    }
+    if let Some(size) = options.send_buffer.map(clamp) {
+        match socket.set_send_buffer_size(size).and_then(|_| socket.send_buffer_size()) {
+            Ok(applied) => log::info!("SO_SNDBUF set to {} bytes (asked for {})", applied, size),
+            Err(e) => log::warn!("Failed to set SO_SNDBUF to {} bytes: {}", size, e),
+        }
+    }
+}
+
+/// Resolve `host` (through the `resolver` option if set) and open a TCP
+/// connection to it
+async fn connect_tcp(host: &str, port: u16, options: &ConnectOptions) -> Result<TcpStream, SocketError> {
+    let addrs = resolve(host, port, options).await?;
+    happy_eyeballs::connect(&join_host_port(host, port), addrs, options.disable_happy_eyeballs).await
+}
+
+/// Addresses of `host`, through the `resolver` option if set; never empty
+async fn resolve(host: &str, port: u16, options: &ConnectOptions) -> Result<Vec<SocketAddr>, SocketError> {
+    let addrs: Vec<_> = match options.resolver.as_deref() {
+        Some(resolver) => dns::resolve(host, port, resolver).await?,
+        None => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| SocketError::ResolveFailed(format!("Failed to resolve {}: {}", host, e)))?
+            .collect(),
+    };
+
+    if addrs.is_empty() {
+        return Err(SocketError::ResolveFailed(format!("No addresses found for {}", host)));
+    }
+
+    log::info!("Resolved {} to {:?}", join_host_port(host, port), addrs);
+    Ok(addrs)
+}
+
+/// Connect to a Unix domain socket at `path`
+#[cfg(unix)]
+async fn open_unix_stream(path: &str) -> Result<OpenedStream, SocketError> {
+    let stream = tokio::net::UnixStream::connect(path)
+        .await
+        .map_err(|e| SocketError::from_connect_io(&format!("unix socket {}", path), e))?;
+
+    let (reader, writer) = stream.into_split();
+    Ok(OpenedStream::new(reader, writer))
+}
+
+/// Unix domain sockets are not available on this platform
+#[cfg(not(unix))]
+async fn open_unix_stream(path: &str) -> Result<OpenedStream, SocketError> {
+    Err(SocketError::Unsupported(format!(
+        "Unix socket connections are not supported on this platform: {}",
+        path
+    )))
+}
+
+/// Parse address string to extract transport, host, and port
+pub(crate) fn parse_address(address: &str) -> Result<(Transport, String, u16), SocketError> {
     if let Some(stripped) = address.strip_prefix("ircs://") {
-        let (host, port) = parse_host_port(stripped, 6697)?;
-        Ok((true, host, port))
+        let (host, port) = parse_host_port(strip_path(stripped), 6697)?;
+        Ok((Transport::Tls, host, port))
     } else if let Some(stripped) = address.strip_prefix("irc://") {
-        let (host, port) = parse_host_port(stripped, 6667)?;
-        Ok((false, host, port))
+        let (host, port) = parse_host_port(strip_path(stripped), 6667)?;
+        Ok((Transport::Tcp, host, port))
+    } else if let Some(path) = address.strip_prefix("unix://") {
+        if path.is_empty() {
+            return Err(SocketError::InvalidAddress(
+                "Missing socket path in unix:// address".to_string(),
+            ));
+        }
+        // No host/port for local sockets; the path doubles as the display host
+        Ok((Transport::Unix { path: path.to_string() }, path.to_string(), 0))
+    } else if let Some(stripped) = address.strip_prefix("wss://") {
+        let (host, port) = parse_host_port(strip_path(stripped), 443)?;
+        Ok((Transport::WebSocket { url: address.to_string() }, host, port))
+    } else if let Some(stripped) = address.strip_prefix("ws://") {
+        let (host, port) = parse_host_port(strip_path(stripped), 80)?;
+        Ok((Transport::WebSocket { url: address.to_string() }, host, port))
     } else {
         // Assume plain IRC if no protocol specified
         let (host, port) = parse_host_port(address, 6667)?;
-        Ok((false, host, port))
+        Ok((Transport::Tcp, host, port))
     }
 }
 
+/// Drop any `/path`, `?query` or `#fragment` following the authority of a URL
+fn strip_path(authority: &str) -> &str {
+    authority
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or(authority)
+}
+
 /// Parse host:port string with default port fallback
-fn parse_host_port(host_port: &str, default_port: u16) -> Result<(String, u16), String> {
-    if let Some((host, port_str)) = host_port.rsplit_once(':') {
-        // Check if this is actually a valid port number
-        if let Ok(port) = port_str.parse::<u16>() {
-            Ok((host.to_string(), port))
-        } else {
-            // If port parsing fails, treat the whole thing as hostname
-            Ok((host_port.to_string(), default_port))
+/// IPv6 literals are written in brackets (`[2001:db8::1]:6697`) and come
+/// back without them. Internationalized host names come back as punycode
+/// (`xn--`), which is what DNS and TLS SNI expect; the caller's address
+/// string keeps the original form for display
+fn parse_host_port(host_port: &str, default_port: u16) -> Result<(String, u16), SocketError> {
+    let invalid = |reason: &str| SocketError::InvalidAddress(format!("{} in address: {}", reason, host_port));
+
+    let (host, port_str) = if let Some(bracketed) = host_port.strip_prefix('[') {
+        let (host, rest) = bracketed.split_once(']').ok_or_else(|| invalid("Unclosed '['"))?;
+        if host.parse::<std::net::Ipv6Addr>().is_err() {
+            return Err(invalid("Invalid IPv6 address"));
         }
+        let port_str = match rest {
+            "" => None,
+            _ => Some(rest.strip_prefix(':').ok_or_else(|| invalid("Unexpected text after ']'"))?),
+        };
+        (host, port_str)
+    } else if host_port.matches(':').count() > 1 {
+        // Only a bare IPv6 address has several colons and no brackets
+        if host_port.parse::<std::net::Ipv6Addr>().is_err() {
+            return Err(invalid("Unbracketed IPv6 address or stray ':'"));
+        }
+        (host_port, None)
     } else {
-        Ok((host_port.to_string(), default_port))
+        match host_port.split_once(':') {
+            Some((host, port_str)) => (host, Some(port_str)),
+            None => (host_port, None),
+        }
+    };
+
+    if host.is_empty() {
+        return Err(invalid("Missing host"));
     }
+    let port = match port_str {
+        // An empty port (`host:`) means the default, as in URLs
+        None | Some("") => default_port,
+        Some(port_str) => match port_str.parse::<u16>() {
+            Ok(port) if port != 0 => port,
+            _ => return Err(invalid(&format!("Invalid port '{}'", port_str))),
+        },
+    };
+    Ok((to_ascii_host(host)?, port))
 }
 
-/// Disconnect a specific client connection
-#[tauri::command]
-pub async fn disconnect(client_id: String, state: State<'_, SocketState>) -> Result<(), String> {
-    let mut connections = state.0.lock().await;
-    if let Some(mut handle) = connections.remove(&client_id) {
-        // Send shutdown signal if available
-        if let Some(shutdown_tx) = handle.shutdown_tx.take() {
-            let _ = shutdown_tx.send(());
-        }
-        Ok(())
+/// Join a host and port as `host:port`, bracketing IPv6 literals
+pub(crate) fn join_host_port(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
     } else {
-        Err(format!("No connection found for client_id: {}", client_id))
+        format!("{}:{}", host, port)
     }
 }
 
-/// Start listening for messages from all active connections
-#[tauri::command]
-pub async fn listen(
-    _state: State<'_, SocketState>,
-    _app_handle: tauri::AppHandle,
-) -> Result<(), String> {
-    // This is a placeholder - actual listening is handled by the read tasks
-    // spawned during connection
-    Ok(())
+/// Convert a non-ASCII host name to its punycode form
+fn to_ascii_host(host: &str) -> Result<String, SocketError> {
+    if host.is_ascii() {
+        return Ok(host.to_string());
+    }
+    idna::domain_to_ascii(host)
+        .map_err(|_| SocketError::InvalidAddress(format!("Invalid host name: {}", host)))
 }
 
-/// Send data to a specific client connection
+/// Prefix of tokens `ping_server` generates
+const PING_TOKEN_PREFIX: &str = "obsidian-ping-";
+
+/// `token`, checked to fit in a `PING` line, or a fresh one
+fn ping_token(token: Option<String>) -> Result<String, SocketError> {
+    static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+    match token {
+        Some(token) if token.is_empty() || token.contains(['\r', '\n', '\0']) => {
+            Err(SocketError::InvalidOption(format!("Invalid PING token: {:?}", token)))
+        }
+        Some(token) => Ok(token),
+        None => Ok(format!("{}{}", PING_TOKEN_PREFIX, NEXT_TOKEN.fetch_add(1, Ordering::Relaxed))),
+    }
+}
+
+/// Send `PING :<token>` without waiting for the reply, e.g. to keep a
+/// flaky connection warm
+///
+/// Goes out ahead of queued lines. The `PONG` reaches the frontend like any
+/// other line. Without a `token` one is generated; either way the token
+/// sent is returned.
 #[tauri::command]
-pub async fn send(
+pub async fn ping_server(
     client_id: String,
-    data: String,
+    token: Option<String>,
     state: State<'_, SocketState>,
-) -> Result<(), String> {
-    // Extract write_tx without holding the mutex across .await
-    let write_tx = {
+) -> Result<String, SocketError> {
+    let token = ping_token(token)?;
+    let priority_tx = {
         let connections = state.0.lock().await;
         connections
             .get(&client_id)
-            .map(|handle| handle.write_tx.clone())
+            .map(|handle| handle.priority_tx.clone())
+            .ok_or_else(|| SocketError::not_connected(&client_id))?
+    };
+    queue_message(&priority_tx, OutgoingMessage {
+        data: format!("PING :{}", token).into(),
+        append_crlf: true,
+        sequence: None,
+    })?;
+    Ok(token)
+}
+
+/// Round-trip time to the server in milliseconds
+///
+/// Sends `PING :<token>` and waits up to `LATENCY_TIMEOUT` for the
+/// matching `PONG`, which `read_task` swallows instead of emitting.
+#[tauri::command]
+pub async fn measure_latency(
+    client_id: String,
+    state: State<'_, SocketState>,
+) -> Result<u64, SocketError> {
+    static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+    let token = format!("{}{}", LATENCY_TOKEN_PREFIX, NEXT_TOKEN.fetch_add(1, Ordering::Relaxed));
+    let (pong_tx, pong_rx) = oneshot::channel();
+
+    let started = {
+        let mut connections = state.0.lock().await;
+        let handle = connections
+            .get_mut(&client_id)
+            .ok_or_else(|| SocketError::not_connected(&client_id))?;
+        queue_message(&handle.write_tx, OutgoingMessage {
+            data: format!("PING :{}", token).into(),
+            append_crlf: true,
+            sequence: None,
+        })?;
+        handle.pending_pings.insert(token.clone(), pong_tx);
+        Instant::now()
     };
 
-    if let Some(write_tx) = write_tx {
-        write_tx
-            .send(data)
-            .await
-            .map_err(|e| format!("Failed to send data: {}", e))?;
-        Ok(())
-    } else {
-        Err(format!("No connection found for client_id: {}", client_id))
+    match tokio::time::timeout(LATENCY_TIMEOUT, pong_rx).await {
+        Ok(Ok(())) => Ok(started.elapsed().as_millis() as u64),
+        // The handle (and with it the sender) went away: disconnected
+        Ok(Err(_)) => Err(SocketError::not_connected(&client_id)),
+        Err(_) => {
+            if let Some(handle) = state.0.lock().await.get_mut(&client_id) {
+                handle.pending_pings.remove(&token);
+            }
+            Err(SocketError::Timeout(format!(
+                "No PONG within {} seconds",
+                LATENCY_TIMEOUT.as_secs()
+            )))
+        }
+    }
+}
+
+/// Look up `nick` with `WHOIS` and collect the reply numerics into one
+/// result
+///
+/// Waits up to `WHOIS_TIMEOUT` for `RPL_ENDOFWHOIS`. The lines that make up
+/// the reply go to this call instead of being emitted. A nick the server
+/// doesn't know comes back with `exists: false`. Concurrent calls for the
+/// same nick share one `WHOIS`.
+#[tauri::command]
+pub async fn whois(
+    client_id: String,
+    nick: String,
+    state: State<'_, SocketState>,
+) -> Result<WhoisReply, SocketError> {
+    if nick.is_empty() || nick.contains([' ', ',', '\r', '\n', '\0']) {
+        return Err(SocketError::InvalidOption(format!("Invalid nick: {:?}", nick)));
+    }
+    let key = whois::nick_key(&nick);
+    let (reply_tx, reply_rx) = oneshot::channel();
+
+    {
+        let mut connections = state.0.lock().await;
+        let handle = connections
+            .get_mut(&client_id)
+            .ok_or_else(|| SocketError::not_connected(&client_id))?;
+        match handle.pending_whois.get_mut(&key) {
+            Some(request) => request.waiters.push(reply_tx),
+            None => {
+                queue_message(&handle.write_tx, OutgoingMessage {
+                    data: format!("WHOIS {}", nick).into(),
+                    append_crlf: true,
+                    sequence: None,
+                })?;
+                handle.pending_whois.insert(key.clone(), whois::WhoisRequest::new(&nick, reply_tx));
+            }
+        }
+    }
+
+    match tokio::time::timeout(WHOIS_TIMEOUT, reply_rx).await {
+        Ok(Ok(reply)) => Ok(reply),
+        // The handle (and with it the sender) went away: disconnected
+        Ok(Err(_)) => Err(SocketError::not_connected(&client_id)),
+        Err(_) => {
+            // Our receiver is gone now; leave the request to other callers
+            if let Some(handle) = state.0.lock().await.get_mut(&client_id) {
+                if let Some(request) = handle.pending_whois.get_mut(&key) {
+                    request.waiters.retain(|waiter| !waiter.is_closed());
+                    if request.waiters.is_empty() {
+                        handle.pending_whois.remove(&key);
+                    }
+                }
+            }
+            Err(SocketError::Timeout(format!(
+                "No end of WHOIS for {} within {} seconds",
+                nick,
+                WHOIS_TIMEOUT.as_secs()
+            )))
+        }
+    }
+}
+
+/// Send `QUIT` on every open connection and shut them down, waiting up to
+/// `timeout` for the writes to go out
+///
+/// `quit_message` replaces each connection's own reason. `closing` is
+/// called with each client_id and event name before its `QUIT` is queued.
+/// The readers are aborted like in `ConnectionHandle::shutdown`, so
+/// callers that want the frontend told must emit the close themselves.
+/// Returns the client_id and event name of each connection that was
+/// closed, sorted.
+async fn quit_all(
+    state: &SocketState,
+    quit_message: Option<&str>,
+    timeout: Duration,
+    mut closing: impl FnMut(&str, &str),
+) -> Vec<(String, String)> {
+    // Taken out under the lock, which is released before any waiting
+    let handles: Vec<(String, ConnectionHandle)> = state.0.lock().await.drain().collect();
+    let mut client_ids = Vec::with_capacity(handles.len());
+    let mut shutdowns = Vec::with_capacity(handles.len());
+    for (client_id, handle) in handles {
+        handle.closed_by_user.store(true, Ordering::SeqCst);
+        closing(&client_id, &handle.event_name);
+        let message = quit_message
+            .or(handle.quit_message.as_deref())
+            .unwrap_or(DEFAULT_QUIT_MESSAGE);
+        // Queued ahead of the shutdown signal, which write_task handles last
+        let quit = OutgoingMessage {
+            data: format!("QUIT :{}\r\n", message).into(),
+            append_crlf: false,
+            sequence: None,
+        };
+        if let Err(e) = handle.write_tx.try_send(quit) {
+            // A full send queue shouldn't cost the QUIT; it jumps the
+            // queued lines instead
+            let queued = match e {
+                mpsc::error::TrySendError::Full(quit) => handle.priority_tx.try_send(quit).is_ok(),
+                mpsc::error::TrySendError::Closed(_) => false,
+            };
+            if !queued {
+                log::warn!("Could not queue QUIT for {}", client_id);
+            }
+        }
+        client_ids.push((client_id, handle.event_name.clone()));
+        shutdowns.push(handle.shutdown(timeout));
+    }
+    client_ids.sort();
+    if !shutdowns.is_empty() {
+        log::info!("Sending QUIT on {} connection(s)", shutdowns.len());
+        // All at once, so the total wait stays within `timeout`
+        futures_util::future::join_all(shutdowns).await;
+    }
+    client_ids
+}
+
+/// Tell the frontend that a connection closed on purpose is gone, which
+/// its aborted reader no longer does
+fn emit_user_close(app_handle: &tauri::AppHandle, event_name: &str, client_id: &str) {
+    let _ = app_handle.emit(event_name, ReceivedPayload {
+        id: client_id.to_string(),
+        event: MessageEvent {
+            connected: Some(false),
+            phase: Some(ConnectionPhase::Closed),
+            reason: Some(CloseReason::User),
+            ..Default::default()
+        },
+    });
+}
+
+/// Close every connection, sending `QUIT` first
+///
+/// `quit_message` is used for all of them; without it each connection
+/// quits with its own `quit_message` option. Like `disconnect`, this
+/// forgets their saved sessions. Returns the client_ids that were closed.
+#[tauri::command]
+pub async fn disconnect_all(
+    quit_message: Option<String>,
+    state: State<'_, SocketState>,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<String>, SocketError> {
+    // Closing goes out before the QUIT, as in `disconnect`
+    let closed = quit_all(&state, quit_message.as_deref(), EXIT_QUIT_TIMEOUT, |client_id, event_name| {
+        let _ = app_handle.emit(event_name, ReceivedPayload {
+            id: client_id.to_string(),
+            event: MessageEvent {
+                phase: Some(ConnectionPhase::Closing),
+                ..Default::default()
+            },
+        });
+    })
+    .await;
+    let mut client_ids = Vec::with_capacity(closed.len());
+    for (client_id, event_name) in closed {
+        emit_user_close(&app_handle, &event_name, &client_id);
+        session::remove(&app_handle, &client_id).await;
+        client_ids.push(client_id);
+    }
+    Ok(client_ids)
+}
+
+/// Set once exit teardown has run, so the second exit request goes through
+static EXIT_TEARDOWN_DONE: AtomicBool = AtomicBool::new(false);
+
+/// Hook for `RunEvent::ExitRequested`: hold the exit until every
+/// connection has sent `QUIT`, then exit for real
+pub(crate) fn on_exit_requested(app: &tauri::AppHandle, api: &tauri::ExitRequestApi) {
+    if EXIT_TEARDOWN_DONE.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    api.prevent_exit();
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        quit_all(&app.state::<SocketState>(), None, EXIT_QUIT_TIMEOUT, |_, _| {}).await;
+        app.exit(0);
+    });
+}
+
+/// Disconnect a specific client connection
+///
+/// Returns once both tasks have stopped, after the writer has had up to
+/// `DISCONNECT_TIMEOUT` to flush, so a reconnect under the same id can't
+/// be disturbed by the old connection.
+#[tauri::command]
+pub async fn disconnect(
+    client_id: String,
+    state: State<'_, SocketState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), SocketError> {
+    // Closed on purpose, so not to be restored; this holds even if the
+    // connection had already dropped
+    session::remove(&app_handle, &client_id).await;
+
+    let handle = state.0.lock().await.remove(&client_id);
+    let Some(handle) = handle else {
+        return Err(SocketError::not_connected(&client_id));
+    };
+    handle.closed_by_user.store(true, Ordering::SeqCst);
+    let event_name = handle.event_name.clone();
+    let _ = app_handle.emit(&event_name, ReceivedPayload {
+        id: client_id.clone(),
+        event: MessageEvent {
+            phase: Some(ConnectionPhase::Closing),
+            ..Default::default()
+        },
+    });
+    handle.shutdown(DISCONNECT_TIMEOUT).await;
+    emit_user_close(&app_handle, &event_name, &client_id);
+    Ok(())
+}
+
+/// Move a connection to a new client_id without reconnecting
+///
+/// Events from then on carry `new_id`, and a session saved with
+/// `persist_session` moves along. Fails with `NotConnected` for an unknown
+/// `old_id` and with `AlreadyConnected` if `new_id` is taken.
+#[tauri::command]
+pub async fn rename_connection(
+    old_id: String,
+    new_id: String,
+    state: State<'_, SocketState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), SocketError> {
+    {
+        let mut connections = state.0.lock().await;
+        if !connections.contains_key(&old_id) {
+            return Err(SocketError::not_connected(&old_id));
+        }
+        if old_id == new_id {
+            return Ok(());
+        }
+        if connections.contains_key(&new_id) {
+            return Err(SocketError::already_connected(&new_id));
+        }
+        if let Some(handle) = connections.remove(&old_id) {
+            handle.client_id.set(new_id.clone());
+            connections.insert(new_id.clone(), handle);
+        }
+    }
+    log::info!("Renamed connection {} to {}", old_id, new_id);
+    session::rename(&app_handle, &old_id, &new_id).await;
+    Ok(())
+}
+
+/// Stop reading from a connection without closing it
+///
+/// Nothing is dropped: the socket simply isn't drained, so the server's
+/// data waits in the kernel buffers (and then TCP backpressure applies)
+/// until `resume_connection`. Server `PING`s go unanswered meanwhile, so
+/// long pauses can time the connection out.
+#[tauri::command]
+pub async fn pause_connection(
+    client_id: String,
+    state: State<'_, SocketState>,
+) -> Result<(), SocketError> {
+    let connections = state.0.lock().await;
+    let handle = connections
+        .get(&client_id)
+        .ok_or_else(|| SocketError::not_connected(&client_id))?;
+    handle.read_pause.pause();
+    Ok(())
+}
+
+/// Resume reading from a connection paused with `pause_connection`
+#[tauri::command]
+pub async fn resume_connection(
+    client_id: String,
+    state: State<'_, SocketState>,
+) -> Result<(), SocketError> {
+    let connections = state.0.lock().await;
+    let handle = connections
+        .get(&client_id)
+        .ok_or_else(|| SocketError::not_connected(&client_id))?;
+    handle.read_pause.resume();
+    Ok(())
+}
+
+/// Acknowledge `count` handled events of a connection using
+/// `max_unacked_events`, letting its reader continue once it is back under
+/// the limit
+///
+/// Acknowledging more than is outstanding is harmless, and on a connection
+/// without the option this does nothing.
+#[tauri::command]
+pub async fn ack_events(
+    client_id: String,
+    count: u64,
+    state: State<'_, SocketState>,
+) -> Result<(), SocketError> {
+    let connections = state.0.lock().await;
+    let handle = connections
+        .get(&client_id)
+        .ok_or_else(|| SocketError::not_connected(&client_id))?;
+    handle.read_pause.ack(count);
+    Ok(())
+}
+
+/// Fail with `ConnectionLimit` if adding `client_id` would take
+/// `connections` past the limit; replacing an existing id never does
+fn check_connection_limit(
+    connections: &HashMap<String, ConnectionHandle>,
+    client_id: &str,
+) -> Result<(), SocketError> {
+    let max = MAX_CONNECTIONS.load(Ordering::Relaxed);
+    if connections.len() >= max && !connections.contains_key(client_id) {
+        return Err(SocketError::connection_limit(max));
+    }
+    Ok(())
+}
+
+/// Cap the number of open connections, as a safety valve against a
+/// frontend bug opening sockets in a loop
+///
+/// Connects that would go over it fail with `ConnectionLimit` before
+/// opening a socket. Lowering it below the current count closes nothing;
+/// it only stops new connections until enough have closed. Defaults to
+/// `DEFAULT_MAX_CONNECTIONS`.
+#[tauri::command]
+pub async fn set_max_connections(max: usize) -> Result<(), SocketError> {
+    if max == 0 {
+        return Err(SocketError::InvalidOption(
+            "max_connections must be at least 1".to_string(),
+        ));
+    }
+    MAX_CONNECTIONS.store(max, Ordering::Relaxed);
+    Ok(())
+}
+
+/// One entry of `list_connections`
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ConnectionSummary {
+    id: String,
+    is_tls: bool,
+}
+
+/// Result of `list_connections`
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ConnectionList {
+    /// Sorted by client_id
+    connections: Vec<ConnectionSummary>,
+    count: usize,
+    /// The limit `set_max_connections` set
+    max_connections: usize,
+}
+
+/// Currently open connections, with how many there are against the limit
+#[tauri::command]
+pub async fn list_connections(
+    state: State<'_, SocketState>,
+) -> Result<ConnectionList, SocketError> {
+    let connections = state.0.lock().await;
+    let mut summaries: Vec<_> = connections
+        .iter()
+        .map(|(id, handle)| ConnectionSummary {
+            id: id.clone(),
+            is_tls: handle.is_tls,
+        })
+        .collect();
+    summaries.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(ConnectionList {
+        count: summaries.len(),
+        connections: summaries,
+        max_connections: MAX_CONNECTIONS.load(Ordering::Relaxed),
+    })
+}
+
+/// Snapshot of one connection for status displays, from `get_connection_info`
+/// Fields are `None` when unknown for the transport or not yet available
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct ConnectionInfo {
+    connected: Option<bool>,
+    is_tls: Option<bool>,
+    local_addr: Option<String>,
+    remote_addr: Option<String>,
+    /// e.g. "TLS 1.3"
+    tls_version: Option<String>,
+    tls_cipher: Option<String>,
+    bytes_in: Option<u64>,
+    bytes_out: Option<u64>,
+    lines_in: Option<u64>,
+    lines_out: Option<u64>,
+    /// When the connection was established, in Unix milliseconds
+    connected_at: Option<u64>,
+    /// Always `None`: the backend never reconnects by itself, so only the
+    /// frontend knows whether a reconnect is pending
+    reconnect_pending: Option<bool>,
+}
+
+/// Everything known about a connection, in one read-only snapshot
+/// An unknown client_id reports `connected: false` and nothing else
+#[tauri::command]
+pub async fn get_connection_info(
+    client_id: String,
+    state: State<'_, SocketState>,
+) -> Result<ConnectionInfo, SocketError> {
+    let connections = state.0.lock().await;
+    Ok(connections.get(&client_id).map_or_else(
+        || ConnectionInfo {
+            connected: Some(false),
+            ..Default::default()
+        },
+        ConnectionHandle::info,
+    ))
+}
+
+/// `RPL_ISUPPORT` (005) tokens the server has sent on a connection, merged
+/// across lines
+///
+/// Tokens withdrawn with `-TOKEN` are gone; a token without a value maps
+/// to `null`. Empty until the server has sent its first 005. Raw mode
+/// connections are not tracked.
+#[tauri::command]
+pub async fn get_isupport(
+    client_id: String,
+    state: State<'_, SocketState>,
+) -> Result<HashMap<String, Option<String>>, SocketError> {
+    let connections = state.0.lock().await;
+    let handle = connections
+        .get(&client_id)
+        .ok_or_else(|| SocketError::not_connected(&client_id))?;
+    Ok(handle.isupport.clone())
+}
+
+/// Start listening for messages from all active connections
+#[tauri::command]
+pub async fn listen(
+    _state: State<'_, SocketState>,
+    _app_handle: tauri::AppHandle,
+) -> Result<(), SocketError> {
+    // This is a placeholder - actual listening is handled by the read tasks
+    // spawned during connection
+    Ok(())
+}
+
+/// Send data to a specific client connection
+///
+/// `append_crlf` defaults to true; pass false to write `data` exactly as
+/// given (e.g. pre-framed or multi-line payloads).
+///
+/// With `confirm`, returns a sequence number, increasing across all
+/// connections, and emits `sent` with it once the line has been written
+/// and flushed to the socket. Without it, returns `None` and emits
+/// nothing.
+#[tauri::command]
+pub async fn send(
+    client_id: String,
+    data: String,
+    append_crlf: Option<bool>,
+    confirm: Option<bool>,
+    state: State<'_, SocketState>,
+) -> Result<Option<u64>, SocketError> {
+    static NEXT_SEQUENCE: AtomicU64 = AtomicU64::new(1);
+
+    // Extract write_tx without holding the mutex across .await
+    let connection = {
+        let connections = state.0.lock().await;
+        connections
+            .get(&client_id)
+            .map(|handle| (handle.write_tx.clone(), handle.max_line_length))
+    };
+
+    if let Some((write_tx, max_line_length)) = connection {
+        if let Some(max_length) = max_line_length {
+            check_line_lengths(&data, max_length)?;
+        }
+        let sequence = confirm
+            .unwrap_or(false)
+            .then(|| NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed));
+        queue_message(&write_tx, OutgoingMessage {
+            data: data.into(),
+            append_crlf: append_crlf.unwrap_or(true),
+            sequence,
+        })?;
+        Ok(sequence)
+    } else {
+        Err(SocketError::not_connected(&client_id))
+    }
+}
+
+/// Send raw bytes to a specific client connection
+///
+/// Written exactly as given: no line ending is added and no charset is
+/// applied, so binary payloads such as DCC handshakes arrive intact. Pairs
+/// with the `raw` connect option for binary protocols. Sent bytes are not
+/// held to `max_line_length`.
+#[tauri::command]
+pub async fn send_bytes(
+    client_id: String,
+    data: Vec<u8>,
+    state: State<'_, SocketState>,
+) -> Result<(), SocketError> {
+    let write_tx = {
+        let connections = state.0.lock().await;
+        connections
+            .get(&client_id)
+            .map(|handle| handle.write_tx.clone())
+            .ok_or_else(|| SocketError::not_connected(&client_id))?
+    };
+    queue_message(&write_tx, OutgoingMessage {
+        data: OutgoingData::Bytes(data),
+        append_crlf: false,
+        sequence: None,
+    })
+}
+
+/// Queue a message on the write channel without waiting for space
+///
+/// A full queue means the socket isn't draining as fast as the frontend is
+/// sending; fail fast with `QueueFull` rather than stalling the command.
+fn queue_message(
+    write_tx: &mpsc::Sender<OutgoingMessage>,
+    message: OutgoingMessage,
+) -> Result<(), SocketError> {
+    write_tx.try_send(message).map_err(|e| match e {
+        mpsc::error::TrySendError::Full(_) => SocketError::QueueFull("Send queue full".to_string()),
+        mpsc::error::TrySendError::Closed(_) => {
+            SocketError::SendFailed("Failed to send data: connection closed".to_string())
+        }
+    })
+}
+
+/// Send several lines to a specific client connection in one call
+///
+/// All lines are queued on the same write channel in order, so they are
+/// written after any previously sent data and before anything sent later.
+#[tauri::command]
+pub async fn send_batch(
+    client_id: String,
+    lines: Vec<String>,
+    state: State<'_, SocketState>,
+) -> Result<(), SocketError> {
+    let connection = {
+        let connections = state.0.lock().await;
+        connections
+            .get(&client_id)
+            .map(|handle| (handle.write_tx.clone(), handle.max_line_length))
+    };
+
+    let Some((write_tx, max_line_length)) = connection else {
+        return Err(SocketError::not_connected(&client_id));
+    };
+
+    if let Some(max_length) = max_line_length {
+        for line in &lines {
+            check_line_lengths(line, max_length)?;
+        }
+    }
+    queue_lines(&write_tx, lines)
+}
+
+/// Refuse `data` if any of its lines is longer than the server accepts,
+/// rather than let the server truncate it
+///
+/// The tag block of a tagged line counts against `irc::MAX_TAGS_LENGTH`;
+/// the rest of the line plus its `\r\n` against `max_length`.
+fn check_line_lengths(data: &str, max_length: usize) -> Result<(), SocketError> {
+    for line in data.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let tags_length = irc::tags_length(line);
+        if tags_length > irc::MAX_TAGS_LENGTH {
+            return Err(SocketError::LineTooLong(format!(
+                "Message tags are {} bytes, over the {}-byte limit",
+                tags_length,
+                irc::MAX_TAGS_LENGTH
+            )));
+        }
+        let length = line.len() - tags_length + 2;
+        if length > max_length {
+            return Err(SocketError::LineTooLong(format!(
+                "Line is {} bytes, over the {}-byte limit",
+                length, max_length
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Queue `lines` in order, refusing the whole batch if it doesn't fit
+fn queue_lines(
+    write_tx: &mpsc::Sender<OutgoingMessage>,
+    lines: Vec<String>,
+) -> Result<(), SocketError> {
+    // Refuse the whole batch up front rather than queueing half of it
+    if write_tx.capacity() < lines.len() {
+        return Err(SocketError::QueueFull(format!(
+            "Send queue full: {} lines queued, room for {}",
+            lines.len(),
+            write_tx.capacity()
+        )));
+    }
+
+    for line in lines {
+        queue_message(write_tx, OutgoingMessage {
+            data: line.into(),
+            append_crlf: true,
+            sequence: None,
+        })?;
+    }
+    Ok(())
+}
+
+/// Send a PRIVMSG, split into several lines if it exceeds the IRC limit
+///
+/// Lines are sized so they still fit the connection's `max_line_length`
+/// once the server adds our `nick!user@host`. Splits on UTF-8 boundaries,
+/// preferring whitespace. A `target` that would break the line (empty, or
+/// with spaces or control characters) fails with `InvalidOption`. Returns
+/// how many lines were sent.
+#[tauri::command]
+pub async fn send_privmsg(
+    client_id: String,
+    target: String,
+    text: String,
+    state: State<'_, SocketState>,
+) -> Result<usize, SocketError> {
+    if target.is_empty() || target.contains([' ', '\r', '\n', '\0']) {
+        return Err(SocketError::InvalidOption(format!("Invalid target: {:?}", target)));
+    }
+    let connection = {
+        let connections = state.0.lock().await;
+        connections
+            .get(&client_id)
+            .map(|handle| (handle.write_tx.clone(), handle.max_line_length))
+    };
+
+    let Some((write_tx, max_line_length)) = connection else {
+        return Err(SocketError::not_connected(&client_id));
+    };
+
+    let max_line_length = max_line_length.unwrap_or(irc::MAX_LINE_LENGTH);
+    let lines = irc::split_privmsg(&target, &text, max_line_length);
+    let count = lines.len();
+    queue_lines(&write_tx, lines)?;
+    Ok(count)
+}
+
+/// Wait until everything queued for `client_id` so far has been written
+/// and the socket flushed, e.g. before a deliberate disconnect or suspend
+///
+/// Lines sent after this call aren't waited for. Fails with `Timeout` if
+/// the queue hasn't drained within `FLUSH_TIMEOUT`, and with
+/// `NotConnected` if the connection closes first.
+#[tauri::command]
+pub async fn flush_connection(
+    client_id: String,
+    state: State<'_, SocketState>,
+) -> Result<(), SocketError> {
+    let write_tx = {
+        let connections = state.0.lock().await;
+        connections
+            .get(&client_id)
+            .map(|handle| handle.write_tx.clone())
+            .ok_or_else(|| SocketError::not_connected(&client_id))?
+    };
+
+    let (done_tx, done_rx) = oneshot::channel();
+    let flushed = async {
+        // Waits for room in a full queue rather than failing with `QueueFull`
+        write_tx
+            .send(OutgoingMessage {
+                data: OutgoingData::Flush(done_tx),
+                append_crlf: false,
+                sequence: None,
+            })
+            .await
+            .map_err(|_| ())?;
+        done_rx.await.map_err(|_| ())
+    };
+    match tokio::time::timeout(FLUSH_TIMEOUT, flushed).await {
+        Ok(Ok(())) => Ok(()),
+        // The write task ended before getting there
+        Ok(Err(())) => Err(SocketError::not_connected(&client_id)),
+        Err(_) => Err(SocketError::Timeout(format!(
+            "Write queue not flushed within {} seconds",
+            FLUSH_TIMEOUT.as_secs()
+        ))),
+    }
+}
+
+/// Enable or disable raw traffic logging for a connection
+///
+/// With a `path`, every incoming and outgoing line is appended to that file
+/// with a timestamp and a `<<`/`>>` direction marker. `redact_auth` (default
+/// true) masks SASL payloads sent via `AUTHENTICATE`. Passing no path stops
+/// logging and flushes the file.
+#[tauri::command]
+pub async fn set_traffic_log(
+    client_id: String,
+    path: Option<String>,
+    redact_auth: Option<bool>,
+    state: State<'_, SocketState>,
+) -> Result<(), SocketError> {
+    let traffic_log = {
+        let connections = state.0.lock().await;
+        connections
+            .get(&client_id)
+            .map(|handle| handle.traffic_log.clone())
+            .ok_or_else(|| SocketError::not_connected(&client_id))?
+    };
+
+    let new_log = match path {
+        Some(path) => Some(
+            TrafficLog::open(&path, redact_auth.unwrap_or(true))
+                .await
+                .map_err(|e| SocketError::Io(format!("Failed to open traffic log {}: {}", path, e)))?,
+        ),
+        None => None,
+    };
+
+    let mut current = traffic_log.lock().await;
+    if let Some(old_log) = current.as_mut() {
+        let _ = old_log.flush().await;
+    }
+    *current = new_log;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address_websocket() {
+        assert_eq!(
+            parse_address("wss://irc.example.com/webirc").unwrap(),
+            (
+                Transport::WebSocket { url: "wss://irc.example.com/webirc".to_string() },
+                "irc.example.com".to_string(),
+                443,
+            )
+        );
+        assert_eq!(
+            parse_address("ws://localhost:8097").unwrap(),
+            (
+                Transport::WebSocket { url: "ws://localhost:8097".to_string() },
+                "localhost".to_string(),
+                8097,
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_address_irc_url_path() {
+        // Deep links carry channels after the authority
+        assert_eq!(
+            parse_address("ircs://irc.libera.chat/%23rust,%23tauri?key=secret").unwrap(),
+            (Transport::Tls, "irc.libera.chat".to_string(), 6697)
+        );
+        assert_eq!(
+            parse_address("irc://irc.example.com:6668/#chan").unwrap(),
+            (Transport::Tcp, "irc.example.com".to_string(), 6668)
+        );
+    }
+
+    #[test]
+    fn test_parse_address_idn() {
+        assert_eq!(
+            parse_address("ircs://irc.bücher.example:6697").unwrap(),
+            (Transport::Tls, "irc.xn--bcher-kva.example".to_string(), 6697)
+        );
+        assert_eq!(
+            parse_address("例え.テスト").unwrap(),
+            (Transport::Tcp, "xn--r8jz45g.xn--zckzah".to_string(), 6667)
+        );
+        assert!(matches!(
+            parse_address("irc://bad\u{2488}host"),
+            Err(SocketError::InvalidAddress(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_address_ipv6() {
+        assert_eq!(
+            parse_address("ircs://[2001:db8::1]:6697").unwrap(),
+            (Transport::Tls, "2001:db8::1".to_string(), 6697)
+        );
+        assert_eq!(
+            parse_address("irc://[2001:db8::1]/#chan").unwrap(),
+            (Transport::Tcp, "2001:db8::1".to_string(), 6667)
+        );
+        assert_eq!(
+            parse_address("::1").unwrap(),
+            (Transport::Tcp, "::1".to_string(), 6667)
+        );
+        assert!(parse_address("irc://[2001:db8::1").is_err());
+        assert!(parse_address("irc://[2001:db8::1]x").is_err());
+        assert!(parse_address("irc://[irc.example.com]:6667").is_err());
+        assert!(parse_address("irc://irc.example.com:66:67").is_err());
+    }
+
+    #[test]
+    fn test_parse_address_invalid_port() {
+        assert_eq!(
+            parse_address("irc://irc.example.com:").unwrap(),
+            (Transport::Tcp, "irc.example.com".to_string(), 6667)
+        );
+        for address in [
+            "irc://example.com:notaport",
+            "irc://example.com:65536",
+            "irc://example.com:0",
+            "irc://example.com:-1",
+            "ircs://[::1]:99999",
+            "irc://:6667",
+        ] {
+            assert!(
+                matches!(parse_address(address), Err(SocketError::InvalidAddress(_))),
+                "{} should be rejected",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn test_join_host_port() {
+        assert_eq!(join_host_port("irc.example.com", 6697), "irc.example.com:6697");
+        assert_eq!(join_host_port("2001:db8::1", 6697), "[2001:db8::1]:6697");
+    }
+
+    #[test]
+    fn test_certificate_warnings() {
+        let day = 86_400;
+        let certificate = certificate::CertificateInfo {
+            subject: Some("CN=irc.example.com".to_string()),
+            issuer: Some("CN=Example CA".to_string()),
+            not_after: Some(1_000 * day),
+            ..Default::default()
+        };
+        assert!(certificate_warnings(&certificate, 900 * day).is_empty());
+        assert_eq!(
+            certificate_warnings(&certificate, 990 * day + 1),
+            vec!["The certificate expires in 10 days"]
+        );
+        // Expiry itself is a validation failure, reported elsewhere
+        assert!(certificate_warnings(&certificate, 1_001 * day).is_empty());
+
+        let self_signed = certificate::CertificateInfo {
+            issuer: certificate.subject.clone(),
+            ..certificate
+        };
+        assert_eq!(certificate_warnings(&self_signed, 0), vec!["The certificate is self-signed"]);
+    }
+
+    #[test]
+    fn test_event_name() {
+        let options = |name: &str| ConnectOptions {
+            event_name: Some(name.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ConnectOptions::default().event_name(), Ok("tcp-message"));
+        assert_eq!(options("irc:libera/main_1").event_name(), Ok("irc:libera/main_1"));
+        assert!(matches!(options("").event_name(), Err(SocketError::InvalidOption(_))));
+        assert!(matches!(options("irc message").event_name(), Err(SocketError::InvalidOption(_))));
+    }
+
+    #[test]
+    fn test_check_line_lengths() {
+        let line = format!("PRIVMSG #c :{}", "a".repeat(498));
+        assert_eq!(line.len() + 2, 512);
+        assert!(check_line_lengths(&line, 512).is_ok());
+        let err = check_line_lengths(&format!("{}a", line), 512).unwrap_err();
+        assert_eq!(err, SocketError::LineTooLong("Line is 513 bytes, over the 512-byte limit".to_string()));
+        assert!(check_line_lengths(&format!("{}a", line), 1024).is_ok());
+
+        // Tags have their own allowance
+        let tagged = format!("@+draft/reply=abc {}", line);
+        assert!(check_line_lengths(&tagged, 512).is_ok());
+        let huge_tags = format!("@+x={} {}", "y".repeat(8190), line);
+        assert!(matches!(check_line_lengths(&huge_tags, 512), Err(SocketError::LineTooLong(_))));
+
+        // Each line of pre-framed data is checked on its own
+        assert!(check_line_lengths(&format!("{}\r\n{}\r\n", line, line), 512).is_ok());
+        assert!(check_line_lengths(&format!("PING x\r\n{}a\r\n", line), 512).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_tcp_stream_from_fd() {
+        use std::os::unix::io::{AsRawFd, IntoRawFd};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        let mut stream = tcp_stream_from_fd(client.into_raw_fd()).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), server.local_addr().unwrap());
+        stream.write_all(b"PING x\r\n").await.unwrap();
+        let mut received = [0u8; 8];
+        std::io::Read::read_exact(&mut server, &mut received).unwrap();
+        assert_eq!(&received, b"PING x\r\n");
+
+        assert!(matches!(tcp_stream_from_fd(-1), Err(SocketError::InvalidAddress(_))));
+        // A listening socket has no peer, and stays open after the refusal
+        assert!(matches!(
+            tcp_stream_from_fd(listener.as_raw_fd()),
+            Err(SocketError::InvalidAddress(_))
+        ));
+        assert!(listener.local_addr().is_ok());
+
+        // A connected UDP socket has a peer but is not a stream
+        let udp = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp.connect(listener.local_addr().unwrap()).unwrap();
+        assert!(matches!(tcp_stream_from_fd(udp.as_raw_fd()), Err(SocketError::InvalidAddress(_))));
+        assert!(udp.local_addr().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_apply_socket_buffers() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_stream = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let options = ConnectOptions {
+            recv_buffer: Some(256 * 1024),
+            // Clamped up to the minimum
+            send_buffer: Some(1),
+            ..Default::default()
+        };
+        apply_socket_buffers(&tcp_stream, &options);
+
+        // The OS may round or cap the sizes (Linux doubles them), so only
+        // check that they took effect
+        let socket = socket2::SockRef::from(&tcp_stream);
+        assert!(socket.recv_buffer_size().unwrap() >= 128 * 1024);
+        assert!(socket.send_buffer_size().unwrap() >= MIN_SOCKET_BUFFER / 2);
+    }
+
+    #[test]
+    fn test_split_addresses() {
+        assert_eq!(
+            split_addresses("ircs://a.example.net, ircs://b.example.net:7000,c.example.net"),
+            vec!["ircs://a.example.net", "ircs://b.example.net:7000", "c.example.net"]
+        );
+        // Commas inside a URL path belong to the channel list
+        assert_eq!(
+            split_addresses("ircs://a.example.net/#rust,#tauri,ircs://b.example.net/#rust"),
+            vec!["ircs://a.example.net/#rust,#tauri", "ircs://b.example.net/#rust"]
+        );
+        assert_eq!(split_addresses("irc.example.net,,"), vec!["irc.example.net"]);
+        assert!(split_addresses("").is_empty());
+    }
+
+    #[test]
+    fn test_server_addresses_deserialize() {
+        let one: ServerAddresses = serde_json::from_str(r#""a.example.net,b.example.net""#).unwrap();
+        assert_eq!(one.into_vec(), vec!["a.example.net", "b.example.net"]);
+        let list: ServerAddresses = serde_json::from_str(r#"["a.example.net", "b.example.net"]"#).unwrap();
+        assert_eq!(list.into_vec(), vec!["a.example.net", "b.example.net"]);
+    }
+
+    #[tokio::test]
+    async fn test_open_first_falls_back() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let good = format!("irc://{}", listener.local_addr().unwrap());
+        let addresses = vec!["irc://example.com:notaport".to_string(), good.clone()];
+
+        let phases = std::sync::Mutex::new(Vec::new());
+        let report_phase = |phase| phases.lock().unwrap().push(phase);
+        let (address, _stream) = open_first(addresses, &ConnectOptions::default(), &report_phase).await.unwrap();
+        assert_eq!(address, good);
+        // The unparseable address fails before reaching any phase
+        assert_eq!(
+            phases.into_inner().unwrap(),
+            vec![ConnectionPhase::Resolving, ConnectionPhase::TcpConnected]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_first_reports_every_failure() {
+        let addresses = vec!["irc://bad:port".to_string(), "irc://[::1".to_string()];
+        let Err(SocketError::AllAddressesFailed(message)) =
+            open_first(addresses, &ConnectOptions::default(), &|_| {}).await
+        else {
+            panic!("expected AllAddressesFailed");
+        };
+        assert!(message.contains("irc://bad:port: "), "{}", message);
+        assert!(message.contains("irc://[::1: "), "{}", message);
+
+        // A single address keeps its specific error
+        let result = open_first(vec!["irc://bad:port".to_string()], &ConnectOptions::default(), &|_| {}).await;
+        assert!(matches!(result, Err(SocketError::InvalidAddress(_))));
+    }
+
+    #[test]
+    fn test_parse_address_unix() {
+        assert_eq!(
+            parse_address("unix:///run/user/1000/znc.sock").unwrap(),
+            (
+                Transport::Unix { path: "/run/user/1000/znc.sock".to_string() },
+                "/run/user/1000/znc.sock".to_string(),
+                0,
+            )
+        );
+        assert!(parse_address("unix://").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_task_verbatim_send() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let writer = spawn_write_task(client, Charset::default(), RecordingSink::default());
+
+        // Pre-framed payload must not get a second terminator
+        writer
+            .write_tx
+            .send(OutgoingMessage {
+                data: "NICK a\r\nUSER a 0 * :a\r\n".to_string().into(),
+                append_crlf: false,
+                sequence: None,
+            })
+            .await
+            .unwrap();
+        // Default path still appends CRLF
+        writer
+            .write_tx
+            .send(OutgoingMessage {
+                data: "PING :x".to_string().into(),
+                append_crlf: true,
+                sequence: None,
+            })
+            .await
+            .unwrap();
+
+        let expected = b"NICK a\r\nUSER a 0 * :a\r\nPING :x\r\n";
+        let mut received = vec![0u8; expected.len()];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+
+        let _ = writer.shutdown_tx.send(());
+        writer.task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_task_sends_bytes_untouched() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let writer = spawn_write_task(client, Charset::from_label("koi8-r").unwrap(), RecordingSink::default());
+
+        // Not UTF-8, not re-encoded, and no line ending added
+        let payload = b"\x01DCC SEND f \xff\xfe\x01".to_vec();
+        writer
+            .write_tx
+            .send(OutgoingMessage {
+                data: OutgoingData::Bytes(payload.clone()),
+                append_crlf: true,
+                sequence: None,
+            })
+            .await
+            .unwrap();
+        let _ = writer.shutdown_tx.send(());
+        writer.task.await.unwrap().unwrap();
+
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, payload);
+    }
+
+    #[test]
+    fn test_batch_window() {
+        let mut options = ConnectOptions::default();
+        assert_eq!(options.batch_window(), None);
+        options.batch_window_ms = Some(0);
+        assert_eq!(options.batch_window(), None);
+        options.batch_window_ms = Some(16);
+        assert_eq!(options.batch_window(), Some(Duration::from_millis(16)));
+        options.batch_window_ms = Some(60_000);
+        assert_eq!(options.batch_window(), Some(MAX_BATCH_WINDOW));
+    }
+
+    #[test]
+    fn test_read_throttle() {
+        let start = tokio::time::Instant::now();
+        let mut throttle = ReadThrottle::new(1000, start);
+        // Starts with a second's worth
+        assert_eq!(throttle.available(start), Ok(1000));
+        throttle.consume(1000);
+
+        // Spent: wait for the next byte
+        assert_eq!(throttle.available(start), Err(Duration::from_millis(1)));
+        // Refills at the configured rate
+        assert_eq!(throttle.available(start + Duration::from_millis(250)), Ok(250));
+        // But never beyond one second's worth
+        assert_eq!(throttle.available(start + Duration::from_secs(10)), Ok(1000));
+
+        // A zero rate is treated as one byte per second rather than never
+        let mut slow = ReadThrottle::new(0, start);
+        assert_eq!(slow.available(start), Ok(1));
+    }
+
+    #[test]
+    fn test_connection_stats() {
+        let stats = ConnectionStats::default();
+        stats.record_out(b"NICK a\r\nUSER a 0 * :a\r\n");
+        stats.record_out(b"PING :x\r\n");
+        assert_eq!(stats.bytes_out.load(Ordering::Relaxed), 32);
+        assert_eq!(stats.lines_out.load(Ordering::Relaxed), 3);
+        // Raw writes without a line ending count no lines
+        stats.record_out(b"partial");
+        assert_eq!(stats.lines_out.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_keepalive() {
+        let start = tokio::time::Instant::now();
+        let secs = Duration::from_secs;
+        let mut keepalive = Keepalive::new(secs(60), secs(30), start);
+        assert_eq!(keepalive.poll(start), KeepaliveAction::Wait);
+        assert_eq!(keepalive.deadline(), start + secs(60));
+
+        // Incoming data postpones the PING
+        keepalive.received(start + secs(50));
+        assert_eq!(keepalive.poll(start + secs(60)), KeepaliveAction::Wait);
+
+        // Idle: PING, then the PONG resets the cycle
+        let token = match keepalive.poll(start + secs(110)) {
+            KeepaliveAction::Ping(token) => token,
+            other => panic!("expected a PING, got {:?}", other),
+        };
+        assert_eq!(keepalive.deadline(), start + secs(140));
+        // Other traffic doesn't count as the PONG
+        keepalive.received(start + secs(120));
+        assert!(!keepalive.pong("user-token", start + secs(120)));
+        assert!(keepalive.pong(&token, start + secs(125)));
+        assert_eq!(keepalive.deadline(), start + secs(185));
+
+        // No PONG in time
+        assert!(matches!(keepalive.poll(start + secs(185)), KeepaliveAction::Ping(_)));
+        assert_eq!(keepalive.poll(start + secs(200)), KeepaliveAction::Wait);
+        assert_eq!(keepalive.poll(start + secs(215)), KeepaliveAction::TimedOut);
+    }
+
+    #[test]
+    fn test_keepalive_options() {
+        let now = tokio::time::Instant::now();
+        let options = |interval, timeout, raw| ConnectOptions {
+            ping_interval_secs: interval,
+            ping_timeout_secs: timeout,
+            raw,
+            ..Default::default()
+        };
+        assert!(options(None, None, false).keepalive(now).is_none());
+        assert!(options(Some(0), None, false).keepalive(now).is_none());
+        assert!(options(Some(60), None, true).keepalive(now).is_none());
+
+        let keepalive = options(Some(1), None, false).keepalive(now).unwrap();
+        assert_eq!(keepalive.interval, MIN_PING_INTERVAL);
+        assert_eq!(keepalive.timeout, DEFAULT_PING_TIMEOUT);
+        let keepalive = options(Some(90), Some(10), false).keepalive(now).unwrap();
+        assert_eq!((keepalive.interval, keepalive.timeout), (Duration::from_secs(90), Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_line_batch() {
+        let mut batch = LineBatch::new(Duration::from_millis(16));
+        assert!(batch.take().is_none());
+
+        assert!(!batch.push(MessageData { data: b"PING :a\r\n".to_vec(), ..Default::default() }));
+        let deadline = batch.deadline.unwrap();
+        assert!(!batch.push(MessageData { data: b"PING :b\r\n".to_vec(), ..Default::default() }));
+        // The window runs from the first line, not the latest
+        assert_eq!(batch.deadline, Some(deadline));
+
+        let lines = batch.take().unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].data, b"PING :b\r\n");
+        assert!(batch.deadline.is_none());
+        assert!(batch.take().is_none());
+
+        // A full batch asks to be flushed right away
+        let full = (0..MAX_BATCH_LINES).map(|_| batch.push(MessageData { data: Vec::new(), ..Default::default() }));
+        assert_eq!(full.filter(|&flush| flush).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_read_pause() {
+        let pause = Arc::new(ReadPause::default());
+        // Not paused: returns right away
+        pause.wait_while_paused().await;
+
+        pause.pause();
+        let waiter = tokio::spawn({
+            let pause = pause.clone();
+            async move { pause.wait_while_paused().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        pause.resume();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("resume should wake the reader")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_pause_waits_for_acks() {
+        let pause = Arc::new(ReadPause::new(Some(2)));
+        pause.event_sent();
+        pause.wait_while_paused().await;
+        pause.event_sent();
+        assert!(pause.is_held());
+
+        let waiter = tokio::spawn({
+            let pause = pause.clone();
+            async move { pause.wait_while_paused().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        pause.ack(1);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("an ack should wake the reader")
+            .unwrap();
+
+        // Over-acking doesn't bank credit
+        pause.ack(5);
+        pause.event_sent();
+        pause.event_sent();
+        assert!(pause.is_held());
+
+        // Without the option nothing is counted
+        let pause = ReadPause::default();
+        (0..100).for_each(|_| pause.event_sent());
+        assert!(!pause.is_held());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_both_tasks() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let writer = spawn_write_task(client, Charset::default(), RecordingSink::default());
+        // Stands in for a read task blocked on a socket that never closes
+        let handle = test_handle(writer, task::spawn(std::future::pending::<()>()));
+        tokio::time::timeout(Duration::from_secs(1), handle.shutdown(Duration::from_secs(1)))
+            .await
+            .expect("shutdown should not hang on the reader");
+
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert!(received.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_quit_all_sends_quit_before_closing() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let writer = spawn_write_task(client, Charset::default(), RecordingSink::default());
+        let mut handle = test_handle(writer, task::spawn(async {}));
+        handle.quit_message = Some("bye".to_string());
+
+        let state = SocketState(Arc::new(Mutex::new(HashMap::new())));
+        state.0.lock().await.insert("c1".to_string(), handle);
+
+        let mut closing = Vec::new();
+        let closed = quit_all(&state, None, Duration::from_secs(1), |client_id, _| closing.push(client_id.to_string())).await;
+        assert_eq!(closing, vec!["c1"]);
+        assert_eq!(closed, vec![("c1".to_string(), DEFAULT_EVENT_NAME.to_string())]);
+        assert!(state.0.lock().await.is_empty());
+
+        // QUIT goes out, then the writer is shut down (EOF)
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"QUIT :bye\r\n");
+
+        assert!(quit_all(&state, None, Duration::from_secs(1), |_, _| {}).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_auto_pong_reply_is_written() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let writer = spawn_write_task(client, Charset::default(), RecordingSink::default());
+
+        // What read_task queues when it sees the PING
+        let pong = irc::pong_reply(b"PING :abc\r\n").unwrap();
+        writer
+            .priority_tx
+            .send(OutgoingMessage { data: pong.into(), append_crlf: true, sequence: None })
+            .await
+            .unwrap();
+
+        let expected = b"PONG :abc\r\n";
+        let mut received = vec![0u8; expected.len()];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+
+        let _ = writer.shutdown_tx.send(());
+        writer.task.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_ping_token() {
+        assert_eq!(ping_token(Some("warm".to_string())).unwrap(), "warm");
+        let first = ping_token(None).unwrap();
+        assert!(first.starts_with(PING_TOKEN_PREFIX));
+        assert_ne!(ping_token(None).unwrap(), first);
+        assert!(matches!(ping_token(Some(String::new())), Err(SocketError::InvalidOption(_))));
+        assert!(matches!(
+            ping_token(Some("x\r\nQUIT".to_string())),
+            Err(SocketError::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_close_reason() {
+        let closed_by_user = AtomicBool::new(false);
+        assert_eq!(close_reason(&closed_by_user, CloseReason::Server), CloseReason::Server);
+        assert_eq!(close_reason(&closed_by_user, CloseReason::Error), CloseReason::Error);
+        closed_by_user.store(true, Ordering::SeqCst);
+        assert_eq!(close_reason(&closed_by_user, CloseReason::Server), CloseReason::User);
+        assert_eq!(close_reason(&closed_by_user, CloseReason::Error), CloseReason::User);
+    }
+
+    /// Records emitted events as `(name, payload)` pairs
+    #[derive(Clone, Default)]
+    struct RecordingSink(Arc<std::sync::Mutex<Vec<(String, serde_json::Value)>>>);
+
+    impl EventSink for RecordingSink {
+        fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) -> tauri::Result<()> {
+            let payload = serde_json::to_value(payload).unwrap();
+            self.0.lock().unwrap().push((event.to_string(), payload));
+            Ok(())
+        }
+    }
+
+    /// A connection's queues and its `write_task`, running on `writer`
+    struct TestWriter {
+        write_tx: mpsc::Sender<OutgoingMessage>,
+        priority_tx: mpsc::Sender<OutgoingMessage>,
+        shutdown_tx: oneshot::Sender<()>,
+        traffic_log: SharedTrafficLog,
+        task: task::JoinHandle<std::io::Result<()>>,
+    }
+
+    fn spawn_write_task<W>(writer: W, charset: Charset, events: RecordingSink) -> TestWriter
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let (write_tx, write_rx) = mpsc::channel(8);
+        let (priority_tx, priority_rx) = mpsc::channel(8);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let traffic_log: SharedTrafficLog = Arc::new(Mutex::new(None));
+        let handle = task::spawn(write_task(
+            writer,
+            write_rx,
+            priority_rx,
+            shutdown_rx,
+            false,
+            charset,
+            traffic_log.clone(),
+            Arc::default(),
+            events,
+            SharedClientId::new("c1"),
+        ));
+        TestWriter { write_tx, priority_tx, shutdown_tx, traffic_log, task: handle }
+    }
+
+    /// A handle for connection `c1` around `writer` and `read_task`, with
+    /// nothing else set
+    fn test_handle(writer: TestWriter, read_task: task::JoinHandle<()>) -> ConnectionHandle {
+        let write_task = writer.task;
+        ConnectionHandle {
+            write_tx: writer.write_tx,
+            priority_tx: writer.priority_tx,
+            shutdown_tx: Some(writer.shutdown_tx),
+            traffic_log: writer.traffic_log,
+            write_task: task::spawn(async move { write_task.await.unwrap().unwrap() }),
+            read_task,
+            quit_message: None,
+            is_tls: false,
+            pending_pings: HashMap::new(),
+            read_pause: Arc::default(),
+            tls_info: None,
+            local_addr: None,
+            remote_addr: None,
+            connected_at: 0,
+            stats: Arc::default(),
+            max_line_length: None,
+            closed_by_user: Arc::default(),
+            event_name: DEFAULT_EVENT_NAME.to_string(),
+            isupport: HashMap::new(),
+            pending_whois: HashMap::new(),
+            client_id: SharedClientId::new("c1"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_task_emits_complete_lines() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let (priority_tx, _priority_rx) = mpsc::channel(8);
+        let events = RecordingSink::default();
+        let reader = task::spawn(read_task(
+            SharedClientId::new("c1"),
+            client,
+            events.clone(),
+            Arc::default(),
+            ConnectOptions::default(),
+            Arc::new(Mutex::new(None)),
+            priority_tx,
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        // A line split across writes is only emitted once complete
+        server.write_all(b"PRIVMSG #x :hi\r\nPI").await.unwrap();
+        server.write_all(b"NG :y\r\n").await.unwrap();
+        drop(server);
+        tokio::time::timeout(Duration::from_secs(1), reader)
+            .await
+            .expect("read task should stop at EOF")
+            .unwrap();
+
+        let events = events.0.lock().unwrap();
+        assert!(events.iter().all(|(name, payload)| name == "tcp-message" && payload["id"] == "c1"));
+        let lines: Vec<_> = events
+            .iter()
+            .filter_map(|(_, payload)| payload["event"]["message"]["data"].as_array())
+            .map(|data| data.iter().map(|byte| byte.as_u64().unwrap() as u8).collect::<Vec<u8>>())
+            .collect();
+        assert_eq!(lines, vec![b"PRIVMSG #x :hi\r\n".to_vec(), b"PING :y\r\n".to_vec()]);
+        let closed = &events.last().unwrap().1["event"];
+        assert_eq!(closed["connected"], false);
+        assert_eq!(closed["reason"], "server");
+        assert_eq!(events.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_replaced_connection_close_leaves_new_handle() {
+        let (old_client, old_server) = tokio::io::duplex(1024);
+        let (priority_tx, _priority_rx) = mpsc::channel(8);
+        let events = RecordingSink::default();
+        let state = Arc::new(Mutex::new(HashMap::new()));
+        let old_reader = task::spawn(read_task(
+            SharedClientId::new("c1"),
+            old_client,
+            events.clone(),
+            state.clone(),
+            ConnectOptions::default(),
+            Arc::new(Mutex::new(None)),
+            priority_tx,
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        // The replacement is stored before the old connection is evicted,
+        // and the old server hangs up meanwhile
+        let (new_client, _new_server) = tokio::io::duplex(1024);
+        let writer = spawn_write_task(new_client, Charset::default(), RecordingSink::default());
+        let new_handle = test_handle(writer, task::spawn(std::future::pending::<()>()));
+        let new_closed_by_user = new_handle.closed_by_user.clone();
+        state.lock().await.insert("c1".to_string(), new_handle);
+        drop(old_server);
+        tokio::time::timeout(Duration::from_secs(1), old_reader)
+            .await
+            .expect("read task should stop at EOF")
+            .unwrap();
+
+        let connections = state.lock().await;
+        let handle = connections.get("c1").expect("the new handle should survive");
+        assert!(Arc::ptr_eq(&handle.closed_by_user, &new_closed_by_user));
+        assert!(!handle.read_task.is_finished());
+        // Nor is the old close reported under the reused id
+        assert!(events.0.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_read_task_follows_rename() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let (priority_tx, _priority_rx) = mpsc::channel(8);
+        let events = RecordingSink::default();
+        let client_id = SharedClientId::new("old");
+        let reader = task::spawn(read_task(
+            client_id.clone(),
+            client,
+            events.clone(),
+            Arc::default(),
+            ConnectOptions::default(),
+            Arc::new(Mutex::new(None)),
+            priority_tx,
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        server.write_all(b"PING :a\r\n").await.unwrap();
+        while events.0.lock().unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        client_id.set("new".to_string());
+        server.write_all(b"PING :b\r\n").await.unwrap();
+        drop(server);
+        reader.await.unwrap();
+
+        let events = events.0.lock().unwrap();
+        let ids: Vec<_> = events.iter().map(|(_, payload)| payload["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["old", "new", "new"]);
+    }
+
+    /// Reader handing out one queued chunk per `read`, then EOF
+    struct ChunkedReader(std::collections::VecDeque<Vec<u8>>);
+
+    impl AsyncRead for ChunkedReader {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if let Some(chunk) = self.0.pop_front() {
+                buf.put_slice(&chunk);
+            }
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_raw_read_holds_back_split_utf8() {
+        let emoji = "😀".as_bytes();
+        let chunks = vec![[b"hi ", &emoji[..2]].concat(), emoji[2..].to_vec(), vec![0xf0]];
+        let (priority_tx, _priority_rx) = mpsc::channel(8);
+        let events = RecordingSink::default();
+        read_task(
+            SharedClientId::new("c1"),
+            ChunkedReader(chunks.into()),
+            events.clone(),
+            Arc::default(),
+            ConnectOptions { raw: true, encoding: Some("auto".to_string()), ..Default::default() },
+            Arc::new(Mutex::new(None)),
+            priority_tx,
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        )
+        .await;
+
+        let events = events.0.lock().unwrap();
+        let chunks: Vec<_> = events
+            .iter()
+            .filter_map(|(_, payload)| payload["event"]["message"]["data"].as_array())
+            .map(|data| data.iter().map(|byte| byte.as_u64().unwrap() as u8).collect::<Vec<u8>>())
+            .collect();
+        // The emoji arrives whole in the second event; a sequence still
+        // incomplete at EOF is decoded as it is (0xf0 as Latin-1 "ð")
+        assert_eq!(chunks, vec![b"hi ".to_vec(), emoji.to_vec(), "ð".as_bytes().to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_write_task_acks_flush_after_queued_lines() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        // Spawned, but it doesn't run before this test yields
+        let writer = spawn_write_task(client, Charset::default(), RecordingSink::default());
+
+        let (done_tx, mut done_rx) = oneshot::channel();
+        for data in ["PRIVMSG #c :one".to_string().into(), "PRIVMSG #c :two".to_string().into(), OutgoingData::Flush(done_tx)] {
+            writer.write_tx.send(OutgoingMessage { data, append_crlf: true, sequence: None }).await.unwrap();
+        }
+        assert!(done_rx.try_recv().is_err());
+
+        tokio::time::timeout(Duration::from_secs(1), done_rx)
+            .await
+            .expect("flush should be acknowledged")
+            .unwrap();
+        // Both lines were written by then, and the flush wrote nothing
+        let mut buf = vec![0u8; 1024];
+        let n = server.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"PRIVMSG #c :one\r\nPRIVMSG #c :two\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_write_task_emits_sent() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let events = RecordingSink::default();
+        let writer = spawn_write_task(client, Charset::default(), events.clone());
+
+        for (text, sequence) in [("PRIVMSG #c :unconfirmed", None), ("PRIVMSG #c :confirmed", Some(7))] {
+            writer
+                .write_tx
+                .send(OutgoingMessage { data: text.to_string().into(), append_crlf: true, sequence })
+                .await
+                .unwrap();
+        }
+        let mut buf = vec![0u8; 1024];
+        let mut written = Vec::new();
+        while !written.ends_with(b"confirmed\r\n") {
+            let n = server.read(&mut buf).await.unwrap();
+            written.extend_from_slice(&buf[..n]);
+        }
+        let _ = writer.shutdown_tx.send(());
+        writer.task.await.unwrap().unwrap();
+
+        // Only the line that asked for it, after it was written
+        let emitted = events.0.lock().unwrap().clone();
+        assert_eq!(emitted, vec![("sent".to_string(), serde_json::json!({ "id": "c1", "sequence": 7 }))]);
+    }
+
+    #[tokio::test]
+    async fn test_write_task_returns_write_errors() {
+        let (client, server) = tokio::io::duplex(1024);
+        let writer = spawn_write_task(client, Charset::default(), RecordingSink::default());
+
+        // The peer is gone, so the first write fails and ends the task
+        drop(server);
+        writer
+            .write_tx
+            .send(OutgoingMessage { data: "PRIVMSG #c :lost".to_string().into(), append_crlf: true, sequence: None })
+            .await
+            .unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(1), writer.task)
+            .await
+            .expect("write task should stop on a write error")
+            .unwrap();
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::BrokenPipe);
     }
 }
@@ -0,0 +1,254 @@
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::irc::split_line;
+use super::SocketError;
+
+/// How long the whole `CAP LS`/`CAP REQ` exchange may take
+const CAP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Longest cap list put in one `CAP REQ`, so the line stays well under 512
+/// bytes with the prefix a server echoes back in its `ACK`
+const CAP_REQ_MAX_LEN: usize = 400;
+
+/// Reject cap names that would break the `CAP REQ` line
+pub(super) fn validate(caps: &[String]) -> Result<(), SocketError> {
+    match caps.iter().find(|cap| cap.is_empty() || cap.contains([' ', '\r', '\n', '\0'])) {
+        Some(cap) => Err(SocketError::InvalidOption(format!("Invalid capability name: {:?}", cap))),
+        None => Ok(()),
+    }
+}
+
+/// Result of the negotiation, reported to the frontend as `caps-acked`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct CapsOutcome {
+    /// Every cap the server listed, with its value (`sasl=PLAIN,EXTERNAL`)
+    pub available: Vec<String>,
+    /// Caps the server acknowledged
+    pub acked: Vec<String>,
+    /// Why negotiation stopped early, if it did
+    pub error: Option<String>,
+}
+
+/// Run `CAP LS 302`, then `CAP REQ` for the caps in `requested` the server
+/// offers, on a freshly opened stream. `CAP END` follows unless `keep_open`
+/// (a SASL exchange is still to come).
+///
+/// Returns the outcome and any bytes that were read but aren't part of the
+/// exchange, which the caller must feed to the regular read path.
+pub(super) async fn negotiate<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    requested: &[String],
+    keep_open: bool,
+) -> (CapsOutcome, Vec<u8>)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut outcome = CapsOutcome::default();
+    let mut passthrough = Vec::new();
+    let exchange = run_exchange(reader, writer, requested, &mut outcome, &mut passthrough);
+    match tokio::time::timeout(CAP_TIMEOUT, exchange).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => outcome.error = Some(e),
+        Err(_) => outcome.error = Some("Capability negotiation timed out".to_string()),
+    }
+    if !keep_open {
+        // Even after a failure, so registration isn't left hanging
+        if let Err(e) = write_line(writer, "CAP END").await {
+            outcome.error.get_or_insert(format!("Failed to end capability negotiation: {}", e));
+        }
+    }
+    (outcome, passthrough)
+}
+
+async fn run_exchange<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    requested: &[String],
+    outcome: &mut CapsOutcome,
+    passthrough: &mut Vec<u8>,
+) -> Result<(), String>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    write_line(writer, "CAP LS 302")
+        .await
+        .map_err(|e| format!("Failed to list capabilities: {}", e))?;
+
+    let mut read_buf = [0u8; 1024];
+    let mut line_buffer: Vec<u8> = Vec::new();
+    // `CAP REQ` lines still waiting for their `ACK`/`NAK`; None while
+    // the `LS` reply is still coming in
+    let mut unanswered: Option<usize> = None;
+
+    loop {
+        while let Some(pos) = line_buffer.windows(2).position(|w| w == b"\r\n") {
+            let line_data: Vec<u8> = line_buffer.drain(..pos + 2).collect();
+            let line = String::from_utf8_lossy(&line_data[..pos]).into_owned();
+            let (command, params) = split_line(&line);
+            let param = |i: usize| params.get(i).map(String::as_str).unwrap_or_default();
+
+            match command.as_str() {
+                "PING" => write_line(writer, &format!("PONG :{}", param(0)))
+                    .await
+                    .map_err(|e| format!("Capability negotiation write failed: {}", e))?,
+                // Multiline replies mark every line but the last with `*`
+                "CAP" if unanswered.is_none() && param(1).eq_ignore_ascii_case("LS") => {
+                    let more = param(2) == "*";
+                    let list = if more { param(3) } else { param(2) };
+                    outcome.available.extend(list.split_whitespace().map(str::to_string));
+                    if !more {
+                        let requests = request_lines(&wanted(requested, &outcome.available));
+                        for caps in &requests {
+                            write_line(writer, &format!("CAP REQ :{}", caps))
+                                .await
+                                .map_err(|e| format!("Failed to request capabilities: {}", e))?;
+                        }
+                        unanswered = Some(requests.len());
+                    }
+                }
+                "CAP" if param(1).eq_ignore_ascii_case("ACK") => {
+                    outcome.acked.extend(
+                        param(2)
+                            .split_whitespace()
+                            .filter(|cap| !cap.starts_with('-'))
+                            .map(str::to_string),
+                    );
+                    unanswered = unanswered.map(|n| n.saturating_sub(1));
+                }
+                // A NAK rejects its whole `CAP REQ`
+                "CAP" if param(1).eq_ignore_ascii_case("NAK") => {
+                    log::info!("Server refused capabilities: {}", param(2));
+                    unanswered = unanswered.map(|n| n.saturating_sub(1));
+                }
+                // ERR_UNKNOWNCOMMAND for `CAP` itself: a server without IRCv3
+                "421" if param(1).eq_ignore_ascii_case("CAP") => {
+                    return Err("Server does not support capability negotiation".to_string());
+                }
+                _ => passthrough.extend_from_slice(&line_data),
+            }
+
+            if unanswered == Some(0) {
+                passthrough.extend_from_slice(&line_buffer);
+                return Ok(());
+            }
+        }
+
+        match reader.read(&mut read_buf).await {
+            Ok(0) => return Err("Connection closed during capability negotiation".to_string()),
+            Ok(n) => line_buffer.extend_from_slice(&read_buf[..n]),
+            Err(e) => return Err(format!("Capability negotiation read failed: {}", e)),
+        }
+    }
+}
+
+/// Requested caps the server offers, in the order they were requested
+fn wanted<'a>(requested: &'a [String], available: &[String]) -> Vec<&'a str> {
+    requested
+        .iter()
+        .map(String::as_str)
+        .filter(|cap| available.iter().any(|offered| offered.split('=').next() == Some(*cap)))
+        .collect()
+}
+
+/// Space-joined cap lists of at most `CAP_REQ_MAX_LEN` bytes each
+fn request_lines(caps: &[&str]) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for cap in caps {
+        match lines.last_mut() {
+            Some(line) if line.len() + 1 + cap.len() <= CAP_REQ_MAX_LEN => {
+                line.push(' ');
+                line.push_str(cap);
+            }
+            _ => lines.push(cap.to_string()),
+        }
+    }
+    lines
+}
+
+async fn write_line<W>(writer: &mut W, line: &str) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(format!("{}\r\n", line).as_bytes()).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(list: &[&str]) -> Vec<String> {
+        list.iter().map(|cap| cap.to_string()).collect()
+    }
+
+    #[test]
+    fn test_wanted_and_request_lines() {
+        let available = caps(&["sasl=PLAIN,EXTERNAL", "server-time", "multi-prefix"]);
+        let requested = caps(&["multi-prefix", "away-notify", "sasl"]);
+        assert_eq!(wanted(&requested, &available), vec!["multi-prefix", "sasl"]);
+
+        let long = "x".repeat(300);
+        assert_eq!(request_lines(&["a", "b"]), vec!["a b"]);
+        assert_eq!(request_lines(&[&long, &long]), vec![long.clone(), long]);
+        assert!(request_lines(&[]).is_empty());
+        assert!(validate(&caps(&["a b"])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_negotiate() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let (mut reader, mut writer) = tokio::io::split(client);
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 1024];
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"CAP LS 302\r\n");
+            server
+                .write_all(b":irc CAP * LS * :multi-prefix sasl=PLAIN\r\n:irc NOTICE * :hello\r\n:irc CAP * LS :server-time\r\n")
+                .await
+                .unwrap();
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"CAP REQ :server-time multi-prefix\r\n");
+            server
+                .write_all(b":irc CAP * ACK :server-time multi-prefix\r\n:irc NOTICE")
+                .await
+                .unwrap();
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"CAP END\r\n");
+        });
+
+        let requested = caps(&["server-time", "away-notify", "multi-prefix"]);
+        let (outcome, passthrough) = negotiate(&mut reader, &mut writer, &requested, false).await;
+        server_task.await.unwrap();
+        assert_eq!(
+            outcome,
+            CapsOutcome {
+                available: caps(&["multi-prefix", "sasl=PLAIN", "server-time"]),
+                acked: caps(&["server-time", "multi-prefix"]),
+                error: None,
+            }
+        );
+        assert_eq!(passthrough, b":irc NOTICE * :hello\r\n:irc NOTICE");
+    }
+
+    #[tokio::test]
+    async fn test_negotiate_nothing_offered_keeps_open() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let (mut reader, mut writer) = tokio::io::split(client);
+        server.write_all(b":irc CAP * LS :sasl\r\n").await.unwrap();
+
+        let (outcome, passthrough) = negotiate(&mut reader, &mut writer, &caps(&["echo-message"]), true).await;
+        assert!(outcome.acked.is_empty());
+        assert!(outcome.error.is_none());
+        assert!(passthrough.is_empty());
+        // Only the `LS`: no `REQ` for nothing, and no `END` before SASL
+        drop((reader, writer));
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).await.unwrap();
+        assert_eq!(sent, "CAP LS 302\r\n");
+    }
+}
@@ -0,0 +1,212 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::irc::{days_from_civil, parse_digits};
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const BMP_STRING: u8 = 0x1e;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+/// `[0]`, the explicit version tag that v1 certificates leave out
+const VERSION: u8 = 0xa0;
+
+/// Attribute types shown in names, by their OID under 2.5.4
+const NAME_ATTRIBUTES: &[(u8, &str)] = &[
+    (3, "CN"),
+    (6, "C"),
+    (7, "L"),
+    (8, "ST"),
+    (10, "O"),
+    (11, "OU"),
+];
+
+/// Details of the server's certificate, read from its DER encoding
+///
+/// Fields the certificate doesn't encode the usual way are `None`; the
+/// fingerprint is always there.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub(super) struct CertificateInfo {
+    /// e.g. "C=US, O=Example, CN=irc.example.com"
+    pub subject: Option<String>,
+    pub issuer: Option<String>,
+    /// Validity period in Unix seconds
+    pub not_before: Option<u64>,
+    pub not_after: Option<u64>,
+    /// SHA-256 of the DER encoding as colon-separated hex, the form CertFP
+    /// and `openssl x509 -fingerprint` show
+    pub sha256_fingerprint: String,
+}
+
+impl CertificateInfo {
+    pub(super) fn from_der(der: &[u8]) -> Self {
+        let mut info = CertificateInfo {
+            sha256_fingerprint: fingerprint(der),
+            ..Default::default()
+        };
+        if let Some(fields) = tbs_fields(der) {
+            info.issuer = name(fields.issuer);
+            info.subject = name(fields.subject);
+            let mut validity = Some(fields.validity);
+            info.not_before = next_time(&mut validity);
+            info.not_after = next_time(&mut validity);
+        }
+        info
+    }
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Contents of the `TBSCertificate` fields we report
+struct TbsFields<'a> {
+    issuer: &'a [u8],
+    validity: &'a [u8],
+    subject: &'a [u8],
+}
+
+fn tbs_fields(der: &[u8]) -> Option<TbsFields<'_>> {
+    let (certificate, _) = expect(der, SEQUENCE)?;
+    let (tbs, _) = expect(certificate, SEQUENCE)?;
+    let mut rest = tbs;
+    if let Some((VERSION, _, after)) = element(rest) {
+        rest = after;
+    }
+    // Serial number and signature algorithm
+    let (_, _, rest) = element(rest)?;
+    let (_, _, rest) = element(rest)?;
+    let (issuer, rest) = expect(rest, SEQUENCE)?;
+    let (validity, rest) = expect(rest, SEQUENCE)?;
+    let (subject, _) = expect(rest, SEQUENCE)?;
+    Some(TbsFields { issuer, validity, subject })
+}
+
+/// One DER element at the start of `data`: its tag, its contents and what
+/// follows it
+fn element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let (len_bytes, rest) = rest.split_at(count);
+        (len_bytes.iter().fold(0, |len, &byte| len << 8 | usize::from(byte)), rest)
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// Like `element`, but only if it has tag `tag`
+fn expect(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    let (found, contents, rest) = element(data)?;
+    (found == tag).then_some((contents, rest))
+}
+
+/// A distinguished name as "C=US, O=Example, CN=host", in encoded order,
+/// keeping only the common attribute types
+fn name(mut rdns: &[u8]) -> Option<String> {
+    let mut parts = Vec::new();
+    while let Some((rdn, rest)) = expect(rdns, SET) {
+        rdns = rest;
+        let mut attributes = rdn;
+        while let Some((attribute, rest)) = expect(attributes, SEQUENCE) {
+            attributes = rest;
+            let Some((oid, value)) = expect(attribute, OBJECT_IDENTIFIER) else {
+                continue;
+            };
+            let label = match oid {
+                [0x55, 0x04, id] => NAME_ATTRIBUTES.iter().find(|(known, _)| known == id),
+                _ => None,
+            };
+            if let (Some((_, label)), Some((tag, value, _))) = (label, element(value)) {
+                parts.push(format!("{}={}", label, string(tag, value)));
+            }
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+/// A directory string; every type but BMPString is ASCII or UTF-8 in
+/// practice, so only that one needs decoding
+fn string(tag: u8, value: &[u8]) -> String {
+    match tag {
+        BMP_STRING => {
+            let units: Vec<u16> = value.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(value).into_owned(),
+    }
+}
+
+/// Parse the next `Time` in `validity`, consuming it
+fn next_time(validity: &mut Option<&[u8]>) -> Option<u64> {
+    let (tag, value, rest) = element((*validity)?)?;
+    *validity = Some(rest);
+    time(tag, std::str::from_utf8(value).ok()?)
+}
+
+/// A `UTCTime` (`YYMMDDHHMMSSZ`, years 1950-2049) or `GeneralizedTime`
+/// (`YYYYMMDDHHMMSSZ`) in Unix seconds
+fn time(tag: u8, value: &str) -> Option<u64> {
+    let digits = value.strip_suffix('Z')?;
+    let (year, rest) = match tag {
+        UTC_TIME if digits.len() == 12 => {
+            let year = parse_digits(&digits[..2], 2)?;
+            (if year < 50 { 2000 + year } else { 1900 + year }, &digits[2..])
+        }
+        GENERALIZED_TIME if digits.len() == 14 => (parse_digits(&digits[..4], 4)?, &digits[4..]),
+        _ => return None,
+    };
+    let field = |i: usize| parse_digits(rest.get(i * 2..i * 2 + 2)?, 2);
+    let (month, day, hour, minute, second) = (field(0)?, field(1)?, field(2)?, field(3)?, field(4)?);
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second;
+    u64::try_from(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    /// Self-signed P-256 certificate from `openssl req -x509`
+    const CERTIFICATE: &str = "MIIByDCCAW2gAwIBAgIUKf0qo/On58Hwyl5sHb/2TxdudbcwCgYIKoZIzj0EAwIwOTELMAkGA1UEBhMCVVMxEDAOBgNVBAoMB0V4YW1wbGUxGDAWBgNVBAMMD2lyYy5leGFtcGxlLmNvbTAeFw0yNTAxMDEwMDAwMDBaFw0zNTAxMDExMjAwMDBaMDkxCzAJBgNVBAYTAlVTMRAwDgYDVQQKDAdFeGFtcGxlMRgwFgYDVQQDDA9pcmMuZXhhbXBsZS5jb20wWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAATuUOUwERIqrhwh02rkzAOtYDG2CoF8WfsbZptI+k/fxyPMJegEZQN1TowSx4tVqn7L9PvhnLwZMD5ac0utYiXko1MwUTAdBgNVHQ4EFgQUNY1T6jgDjgy13nwFu3RoaszC8lcwHwYDVR0jBBgwFoAUNY1T6jgDjgy13nwFu3RoaszC8lcwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNJADBGAiEA/cnWDfpeQe49o6MQensU02e8/Tnm4SPn8Jk2fdA7cbECIQDObS/UGJNdSLSH9RBF0OzbciwJ/eJdv9SObtH4YvveWQ==";
+
+    #[test]
+    fn test_from_der() {
+        let der = STANDARD.decode(CERTIFICATE).unwrap();
+        assert_eq!(
+            CertificateInfo::from_der(&der),
+            CertificateInfo {
+                subject: Some("C=US, O=Example, CN=irc.example.com".to_string()),
+                issuer: Some("C=US, O=Example, CN=irc.example.com".to_string()),
+                not_before: Some(1_735_689_600),
+                not_after: Some(2_051_265_600),
+                sha256_fingerprint: "99:9B:A3:DB:1B:2C:7E:74:3E:CD:33:55:87:B9:F2:5B:68:D6:6D:A9:BA:1F:0B:BD:8E:C6:3A:A7:F8:29:2D:0D"
+                    .to_string(),
+            }
+        );
+
+        // Garbage still gets a fingerprint
+        let info = CertificateInfo::from_der(&der[..100]);
+        assert_eq!(info.subject, None);
+        assert_eq!(info.sha256_fingerprint.len(), 32 * 3 - 1);
+    }
+
+    #[test]
+    fn test_time() {
+        assert_eq!(time(UTC_TIME, "250101000000Z"), Some(1_735_689_600));
+        assert_eq!(time(UTC_TIME, "700101000000Z"), Some(0));
+        assert_eq!(time(GENERALIZED_TIME, "20350101120000Z"), Some(2_051_265_600));
+        assert_eq!(time(UTC_TIME, "250101000000"), None);
+        assert_eq!(time(GENERALIZED_TIME, "250101000000Z"), None);
+    }
+}
@@ -0,0 +1,161 @@
+use encoding_rs::Encoding;
+
+/// Character set a connection's lines are transcoded from and to
+///
+/// Incoming lines are always handed to the frontend as UTF-8, and outgoing
+/// text is encoded back before it hits the socket.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) enum Charset {
+    /// UTF-8, reading any invalid byte as Latin-1 instead of dropping it
+    #[default]
+    Utf8Fallback,
+    /// A fixed charset such as `iso-8859-1` or `koi8-r`
+    Encoding(&'static Encoding),
+    /// No transcoding at all: bytes pass through untouched
+    Binary,
+}
+
+impl Charset {
+    /// Look up a charset by name: `auto` (the default), `binary`, or any
+    /// WHATWG encoding label
+    pub(super) fn from_label(label: &str) -> Option<Self> {
+        match label.trim().to_ascii_lowercase().as_str() {
+            "" | "auto" => Some(Charset::Utf8Fallback),
+            "binary" | "raw" => Some(Charset::Binary),
+            label => Encoding::for_label(label.as_bytes()).map(Charset::Encoding),
+        }
+    }
+
+    /// Decode one incoming line to UTF-8
+    pub(super) fn decode(self, line: &[u8]) -> Vec<u8> {
+        match self {
+            Charset::Utf8Fallback => decode_utf8_latin1(line),
+            Charset::Encoding(encoding) => encoding.decode_without_bom_handling(line).0.into_owned().into_bytes(),
+            Charset::Binary => line.to_vec(),
+        }
+    }
+
+    /// Whether incoming bytes are read as UTF-8, whose characters a read
+    /// can cut in half
+    pub(super) fn is_utf8(self) -> bool {
+        match self {
+            Charset::Utf8Fallback => true,
+            Charset::Encoding(encoding) => encoding == encoding_rs::UTF_8,
+            Charset::Binary => false,
+        }
+    }
+
+    /// Encode outgoing text for the wire
+    pub(super) fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            Charset::Encoding(encoding) => encoding.encode(text).0.into_owned(),
+            Charset::Utf8Fallback | Charset::Binary => text.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Length of the UTF-8 sequence cut off at the end of `bytes`, if the
+/// last 1-3 bytes start one that the next read may complete; 0 otherwise
+pub(super) fn incomplete_utf8_tail(bytes: &[u8]) -> usize {
+    let tail_start = bytes.len().saturating_sub(3);
+    // Continuation bytes are 0b10xxxxxx; find the lead byte before them
+    let Some(lead) = bytes[tail_start..].iter().rposition(|&b| b & 0xc0 != 0x80) else {
+        return 0;
+    };
+    let lead = tail_start + lead;
+    match std::str::from_utf8(&bytes[lead..]) {
+        // Truncated rather than invalid: no error_len, and nothing valid
+        Err(e) if e.valid_up_to() == 0 && e.error_len().is_none() => bytes.len() - lead,
+        _ => 0,
+    }
+}
+
+/// Keep valid UTF-8 runs as they are and map every invalid byte to the
+/// Latin-1 character with the same value
+fn decode_utf8_latin1(mut bytes: &[u8]) -> Vec<u8> {
+    let mut decoded = String::with_capacity(bytes.len());
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                decoded.push_str(valid);
+                return decoded.into_bytes();
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                // Everything before `valid_up_to` is known to be valid
+                decoded.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                // A truncated sequence at the end has no error_len; take
+                // its bytes one at a time like any other invalid byte
+                let invalid = e.error_len().unwrap_or(1);
+                decoded.extend(rest[..invalid].iter().map(|&b| char::from(b)));
+                bytes = &rest[invalid..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_label() {
+        assert_eq!(Charset::from_label("auto"), Some(Charset::Utf8Fallback));
+        assert_eq!(Charset::from_label(""), Some(Charset::Utf8Fallback));
+        assert_eq!(Charset::from_label("Binary"), Some(Charset::Binary));
+        assert_eq!(Charset::from_label("KOI8-R"), Some(Charset::Encoding(encoding_rs::KOI8_R)));
+        // WHATWG maps latin1 to windows-1252
+        assert_eq!(Charset::from_label("latin1"), Some(Charset::Encoding(encoding_rs::WINDOWS_1252)));
+        assert_eq!(Charset::from_label("klingon"), None);
+    }
+
+    #[test]
+    fn test_is_utf8() {
+        assert!(Charset::Utf8Fallback.is_utf8());
+        assert!(Charset::from_label("utf-8").unwrap().is_utf8());
+        assert!(!Charset::from_label("koi8-r").unwrap().is_utf8());
+        assert!(!Charset::Binary.is_utf8());
+    }
+
+    #[test]
+    fn test_utf8_fallback() {
+        let charset = Charset::Utf8Fallback;
+        assert_eq!(charset.decode("héllo ☃\r\n".as_bytes()), "héllo ☃\r\n".as_bytes());
+        // Latin-1 "café" next to valid UTF-8
+        assert_eq!(charset.decode(b"caf\xe9 \xe2\x98\x83\r\n"), "café ☃\r\n".as_bytes());
+        // Truncated sequence at the end
+        assert_eq!(charset.decode(b"ok\xe2\x98"), "okâ\u{98}".as_bytes());
+        assert_eq!(charset.encode("café"), "café".as_bytes());
+    }
+
+    #[test]
+    fn test_incomplete_utf8_tail() {
+        let emoji = "😀".as_bytes();
+        assert_eq!(incomplete_utf8_tail(b"hi "), 0);
+        assert_eq!(incomplete_utf8_tail(&[b"hi ", &emoji[..1]].concat()), 1);
+        assert_eq!(incomplete_utf8_tail(&[b"hi ", &emoji[..3]].concat()), 3);
+        assert_eq!(incomplete_utf8_tail(&[b"hi ", emoji].concat()), 0);
+        assert_eq!(incomplete_utf8_tail(&emoji[..2]), 2);
+        // Invalid bytes are not held back, there is nothing to wait for
+        assert_eq!(incomplete_utf8_tail(b"\xff"), 0);
+        assert_eq!(incomplete_utf8_tail(b"\xe2\x28"), 0);
+        assert_eq!(incomplete_utf8_tail(b"\x80\x80\x80"), 0);
+        assert_eq!(incomplete_utf8_tail(b""), 0);
+    }
+
+    #[test]
+    fn test_fixed_encoding() {
+        let charset = Charset::from_label("koi8-r").unwrap();
+        // "Привет" in KOI8-R
+        let wire = b"\xf0\xd2\xc9\xd7\xc5\xd4";
+        assert_eq!(charset.decode(wire), "Привет".as_bytes());
+        assert_eq!(charset.encode("Привет"), wire);
+    }
+
+    #[test]
+    fn test_binary_passthrough() {
+        let charset = Charset::Binary;
+        assert_eq!(charset.decode(b"\x01DCC SEND \xff\x01\r\n"), b"\x01DCC SEND \xff\x01\r\n");
+        assert_eq!(charset.encode("PING :x"), b"PING :x");
+    }
+}
@@ -0,0 +1,208 @@
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Size of the compressed input buffer on the read side
+const INPUT_BUFFER_SIZE: usize = 8192;
+/// Spare room reserved for compressed output per `compress_vec` call
+const OUTPUT_CHUNK_SIZE: usize = 4096;
+
+/// Inflates a zlib stream read from `inner` as bytes arrive
+pub(super) struct ZlibReader<R> {
+    inner: R,
+    inflate: Decompress,
+    input: Box<[u8]>,
+    /// Unconsumed compressed bytes are `input[start..end]`
+    start: usize,
+    end: usize,
+    /// The socket hit EOF or the zlib stream ended
+    done: bool,
+}
+
+impl<R> ZlibReader<R> {
+    pub(super) fn new(inner: R) -> Self {
+        ZlibReader {
+            inner,
+            inflate: Decompress::new(true),
+            input: vec![0; INPUT_BUFFER_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            done: false,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ZlibReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.done || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.start == this.end {
+                let mut input = ReadBuf::new(&mut this.input);
+                ready!(Pin::new(&mut this.inner).poll_read(cx, &mut input))?;
+                if input.filled().is_empty() {
+                    this.done = true;
+                    return Poll::Ready(Ok(()));
+                }
+                this.start = 0;
+                this.end = input.filled().len();
+            }
+
+            let (in_before, out_before) = (this.inflate.total_in(), this.inflate.total_out());
+            let status = this
+                .inflate
+                .decompress(&this.input[this.start..this.end], buf.initialize_unfilled(), FlushDecompress::None)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            this.start += (this.inflate.total_in() - in_before) as usize;
+            let produced = (this.inflate.total_out() - out_before) as usize;
+            buf.advance(produced);
+
+            if status == Status::StreamEnd {
+                this.done = true;
+            }
+            // Otherwise all input was consumed without output yet: read more
+            if produced > 0 {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+/// Deflates everything written into a zlib stream on `inner`
+///
+/// Every `flush` ends with a zlib sync flush, so each IRC line reaches the
+/// server as soon as it's written rather than when the block fills up.
+pub(super) struct ZlibWriter<W> {
+    inner: W,
+    deflate: Compress,
+    /// Compressed bytes not yet written to `inner`, from `written` on
+    output: Vec<u8>,
+    written: usize,
+    /// Data went in since the last sync flush
+    needs_sync: bool,
+}
+
+impl<W> ZlibWriter<W> {
+    pub(super) fn new(inner: W) -> Self {
+        ZlibWriter {
+            inner,
+            deflate: Compress::new(Compression::default(), true),
+            output: Vec::new(),
+            written: 0,
+            needs_sync: false,
+        }
+    }
+
+    /// Run `input` through the compressor into `output`
+    /// Returns whether the stream is finished (only with `Finish`)
+    fn compress(&mut self, mut input: &[u8], flush: FlushCompress) -> io::Result<bool> {
+        loop {
+            self.output.reserve(OUTPUT_CHUNK_SIZE);
+            let in_before = self.deflate.total_in();
+            let status = self
+                .deflate
+                .compress_vec(input, &mut self.output, flush)
+                .map_err(io::Error::other)?;
+            input = &input[(self.deflate.total_in() - in_before) as usize..];
+            // A full output buffer may mean more is pending
+            let room_left = self.output.len() < self.output.capacity();
+            if status == Status::StreamEnd || (input.is_empty() && room_left) {
+                return Ok(status == Status::StreamEnd);
+            }
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> ZlibWriter<W> {
+    /// Write out all pending compressed bytes
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.output.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.output[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+        self.output.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for ZlibWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        this.compress(data, FlushCompress::None)?;
+        this.needs_sync = true;
+        Poll::Ready(Ok(data.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.needs_sync {
+            this.compress(&[], FlushCompress::Sync)?;
+            this.needs_sync = false;
+        }
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            ready!(this.poll_drain(cx))?;
+            if this.compress(&[], FlushCompress::Finish)? {
+                break;
+            }
+        }
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_loopback() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut writer = ZlibWriter::new(client);
+        let mut reader = ZlibReader::new(server);
+
+        // Each flushed line must be readable before the stream is finished
+        for line in ["NICK obsidian\r\n", "PRIVMSG #chan :héllo wörld\r\n"] {
+            writer.write_all(line.as_bytes()).await.unwrap();
+            writer.flush().await.unwrap();
+            let mut received = vec![0u8; line.len()];
+            reader.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, line.as_bytes());
+        }
+
+        // Larger than both the duplex and the output chunk
+        let burst: String = (0..2000).map(|i| format!(":server 353 me = #chan :nick{}\r\n", i)).collect();
+        let sender = tokio::spawn(async move {
+            writer.write_all(burst.as_bytes()).await.unwrap();
+            writer.shutdown().await.unwrap();
+            burst
+        });
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, sender.await.unwrap().as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_stream() {
+        let mut reader = ZlibReader::new(&b"PING :not compressed\r\n"[..]);
+        let mut received = Vec::new();
+        let error = reader.read_to_end(&mut received).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
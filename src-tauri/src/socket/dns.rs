@@ -0,0 +1,51 @@
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::TokioAsyncResolver;
+use std::net::{IpAddr, SocketAddr};
+
+use super::SocketError;
+
+/// Port used when the resolver is given as a bare IP
+const DNS_PORT: u16 = 53;
+
+/// Parse a nameserver given as `ip`, `ip:port` or `[ipv6]:port`
+fn parse_nameserver(resolver: &str) -> Result<SocketAddr, SocketError> {
+    let resolver = resolver.trim();
+    if let Ok(addr) = resolver.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    resolver
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, DNS_PORT))
+        .map_err(|_| SocketError::InvalidAddress(format!("Invalid DNS resolver: {}", resolver)))
+}
+
+/// Resolve `host` by querying the nameserver at `resolver` directly,
+/// bypassing the system resolver (UDP, falling back to TCP)
+pub(super) async fn resolve(host: &str, port: u16, resolver: &str) -> Result<Vec<SocketAddr>, SocketError> {
+    let nameserver = parse_nameserver(resolver)?;
+    let group = NameServerConfigGroup::from_ips_clear(&[nameserver.ip()], nameserver.port(), true);
+    let resolver = TokioAsyncResolver::tokio(ResolverConfig::from_parts(None, vec![], group), ResolverOpts::default());
+
+    let lookup = resolver.lookup_ip(host).await.map_err(|e| {
+        SocketError::ResolveFailed(format!("Failed to resolve {} via {}: {}", host, nameserver, e))
+    })?;
+    Ok(lookup.iter().map(|ip| SocketAddr::new(ip, port)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nameserver() {
+        assert_eq!(parse_nameserver("1.1.1.1").unwrap(), "1.1.1.1:53".parse().unwrap());
+        assert_eq!(parse_nameserver(" 9.9.9.9:5353 ").unwrap(), "9.9.9.9:5353".parse().unwrap());
+        assert_eq!(parse_nameserver("2606:4700::1111").unwrap(), "[2606:4700::1111]:53".parse().unwrap());
+        assert_eq!(parse_nameserver("[::1]").unwrap(), "[::1]:53".parse().unwrap());
+        assert_eq!(parse_nameserver("[::1]:5353").unwrap(), "[::1]:5353".parse().unwrap());
+        assert!(matches!(parse_nameserver("dns.example"), Err(SocketError::InvalidAddress(_))));
+        assert!(matches!(parse_nameserver(""), Err(SocketError::InvalidAddress(_))));
+    }
+}
@@ -0,0 +1,316 @@
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+use std::fmt;
+
+/// Errors returned by the socket commands
+///
+/// Serializes as `{ "kind": "tls_handshake", "message": "..." }` so the
+/// frontend can branch on `kind` instead of matching on message text. The
+/// message stays human-readable for logs and fallback display.
+/// `tls_certificate` errors also carry a `certificate` object with the
+/// details of what was wrong with it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SocketError {
+    /// The address could not be parsed
+    InvalidAddress(String),
+    /// A connect option has a value we don't understand
+    InvalidOption(String),
+    /// DNS resolution of the host failed or returned nothing
+    ResolveFailed(String),
+    /// The TCP (or Unix socket) connection could not be established, for a
+    /// reason none of the more specific variants cover
+    ConnectFailed(String),
+    /// The connection attempt timed out; worth retrying
+    ConnectTimeout(String),
+    /// The host actively refused the connection: the server is down or the
+    /// port is wrong, so retrying right away won't help
+    ConnectionRefused(String),
+    /// No route to the host or its network, usually because we are offline;
+    /// worth retrying once the network is back
+    Unreachable(String),
+    /// Creating the TLS connector or the TLS handshake failed, for a
+    /// reason none of the more specific variants cover
+    TlsHandshake(String),
+    /// The server's certificate was rejected; the details say why
+    TlsCertificate(String, CertificateProblem),
+    /// The server and we have no TLS version in common, e.g. a server
+    /// stuck on TLS 1.0 or one below `min_tls_version`
+    TlsProtocolVersion(String),
+    /// The proxy refused or failed to set up the tunnel
+    Proxy(String),
+    /// The WebSocket handshake failed
+    WebSocket(String),
+    /// The transport isn't available on this platform, or the command
+    /// doesn't support it
+    Unsupported(String),
+    /// No live connection exists for the given client_id
+    NotConnected(String),
+    /// `connect` was called for a client_id that already has a connection
+    AlreadyConnected(String),
+    /// The connection exists but the data could not be queued for writing
+    SendFailed(String),
+    /// The write queue is full because the socket isn't keeping up
+    QueueFull(String),
+    /// A line to send is longer than the server would accept
+    LineTooLong(String),
+    /// A local file or other I/O operation failed
+    Io(String),
+    /// The server did not answer in time (e.g. a latency `PING`)
+    Timeout(String),
+    /// Every address in a fallback list failed; the message lists each one
+    AllAddressesFailed(String),
+    /// Another connection would go over the limit `set_max_connections` sets
+    ConnectionLimit(String),
+}
+
+/// Why a server certificate was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertificateReason {
+    Expired,
+    NotYetValid,
+    /// Valid, but not for the host we connected to
+    HostnameMismatch,
+    /// Not signed by a trusted authority, self-signed certificates included
+    UnknownIssuer,
+}
+
+/// What was wrong with a rejected certificate, for the UI to offer the
+/// right remedy
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CertificateProblem {
+    pub reason: CertificateReason,
+    /// Name the certificate was checked against
+    pub host: String,
+    /// Validity period in Unix seconds, when the TLS library reports it
+    /// (rustls does for expired and not yet valid certificates)
+    pub not_before: Option<u64>,
+    pub not_after: Option<u64>,
+    /// Names the certificate is valid for, on a hostname mismatch, when
+    /// the TLS library reports them
+    pub presented_names: Vec<String>,
+}
+
+impl CertificateProblem {
+    pub(crate) fn new(reason: CertificateReason, host: &str) -> Self {
+        CertificateProblem {
+            reason,
+            host: host.to_string(),
+            not_before: None,
+            not_after: None,
+            presented_names: Vec::new(),
+        }
+    }
+
+    /// Short description for the error message
+    pub(crate) fn describe(&self) -> String {
+        match self.reason {
+            CertificateReason::Expired => format!("certificate for {} has expired", self.host),
+            CertificateReason::NotYetValid => format!("certificate for {} is not valid yet", self.host),
+            CertificateReason::HostnameMismatch => format!("certificate is not valid for {}", self.host),
+            CertificateReason::UnknownIssuer => {
+                format!("certificate for {} is not signed by a trusted authority", self.host)
+            }
+        }
+    }
+}
+
+impl SocketError {
+    /// Map an I/O error from a connection attempt to the matching variant
+    pub(crate) fn from_connect_io(target: &str, e: std::io::Error) -> Self {
+        let message = format!("Failed to connect to {}: {}", target, e);
+        match e.kind() {
+            std::io::ErrorKind::TimedOut => SocketError::ConnectTimeout(message),
+            std::io::ErrorKind::ConnectionRefused => SocketError::ConnectionRefused(message),
+            _ if is_unreachable(&e) => SocketError::Unreachable(message),
+            _ => SocketError::ConnectFailed(message),
+        }
+    }
+
+    /// Error for a client_id with no entry in `SocketState`
+    pub(crate) fn not_connected(client_id: &str) -> Self {
+        SocketError::NotConnected(format!("No connection found for client_id: {}", client_id))
+    }
+
+    /// Error for a `connect` to a client_id that is still connected
+    pub(crate) fn already_connected(client_id: &str) -> Self {
+        SocketError::AlreadyConnected(format!("client_id already connected: {}", client_id))
+    }
+
+    /// Error for a connection that would exceed `max` open connections
+    pub(crate) fn connection_limit(max: usize) -> Self {
+        SocketError::ConnectionLimit(format!("connection limit reached ({} connections)", max))
+    }
+
+    /// The `kind` the frontend sees
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SocketError::InvalidAddress(_) => "invalid_address",
+            SocketError::InvalidOption(_) => "invalid_option",
+            SocketError::ResolveFailed(_) => "resolve_failed",
+            SocketError::ConnectFailed(_) => "connect_failed",
+            SocketError::ConnectTimeout(_) => "connect_timeout",
+            SocketError::ConnectionRefused(_) => "connection_refused",
+            SocketError::Unreachable(_) => "unreachable",
+            SocketError::TlsHandshake(_) => "tls_handshake",
+            SocketError::TlsCertificate(..) => "tls_certificate",
+            SocketError::TlsProtocolVersion(_) => "tls_protocol_version",
+            SocketError::Proxy(_) => "proxy",
+            SocketError::WebSocket(_) => "web_socket",
+            SocketError::Unsupported(_) => "unsupported",
+            SocketError::NotConnected(_) => "not_connected",
+            SocketError::AlreadyConnected(_) => "already_connected",
+            SocketError::SendFailed(_) => "send_failed",
+            SocketError::QueueFull(_) => "queue_full",
+            SocketError::LineTooLong(_) => "line_too_long",
+            SocketError::Io(_) => "io",
+            SocketError::Timeout(_) => "timeout",
+            SocketError::AllAddressesFailed(_) => "all_addresses_failed",
+            SocketError::ConnectionLimit(_) => "connection_limit",
+        }
+    }
+
+    /// Human-readable description of the error
+    pub fn message(&self) -> &str {
+        match self {
+            SocketError::InvalidAddress(message)
+            | SocketError::InvalidOption(message)
+            | SocketError::ResolveFailed(message)
+            | SocketError::ConnectFailed(message)
+            | SocketError::ConnectTimeout(message)
+            | SocketError::ConnectionRefused(message)
+            | SocketError::Unreachable(message)
+            | SocketError::TlsHandshake(message)
+            | SocketError::TlsCertificate(message, _)
+            | SocketError::TlsProtocolVersion(message)
+            | SocketError::Proxy(message)
+            | SocketError::WebSocket(message)
+            | SocketError::Unsupported(message)
+            | SocketError::NotConnected(message)
+            | SocketError::AlreadyConnected(message)
+            | SocketError::SendFailed(message)
+            | SocketError::QueueFull(message)
+            | SocketError::LineTooLong(message)
+            | SocketError::Io(message)
+            | SocketError::Timeout(message)
+            | SocketError::AllAddressesFailed(message)
+            | SocketError::ConnectionLimit(message) => message,
+        }
+    }
+}
+
+/// `ENETUNREACH` and `EHOSTUNREACH` (`WSAENETUNREACH`/`WSAEHOSTUNREACH`)
+///
+/// Checked by OS error code, as the matching `ErrorKind`s are newer than
+/// the minimum supported Rust version.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const UNREACHABLE_ERRNOS: [i32; 2] = [101, 113];
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+const UNREACHABLE_ERRNOS: [i32; 2] = [51, 65];
+#[cfg(windows)]
+const UNREACHABLE_ERRNOS: [i32; 2] = [10051, 10065];
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    windows
+)))]
+const UNREACHABLE_ERRNOS: [i32; 0] = [];
+
+/// The network or host has no route to it
+fn is_unreachable(e: &std::io::Error) -> bool {
+    e.raw_os_error().is_some_and(|code| UNREACHABLE_ERRNOS.contains(&code))
+}
+
+impl Serialize for SocketError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let certificate = match self {
+            SocketError::TlsCertificate(_, certificate) => Some(certificate),
+            _ => None,
+        };
+        let mut error = serializer.serialize_struct("SocketError", 2 + usize::from(certificate.is_some()))?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", self.message())?;
+        if let Some(certificate) = certificate {
+            error.serialize_field("certificate", certificate)?;
+        }
+        error.end()
+    }
+}
+
+impl fmt::Display for SocketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for SocketError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_shape() {
+        let err = SocketError::TlsHandshake("TLS handshake failed: bad cert".to_string());
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({ "kind": "tls_handshake", "message": "TLS handshake failed: bad cert" })
+        );
+        let err = SocketError::AllAddressesFailed("a: refused".to_string());
+        assert_eq!(serde_json::to_value(&err).unwrap()["kind"], "all_addresses_failed");
+        assert_eq!(
+            serde_json::to_value(SocketError::connection_limit(64)).unwrap(),
+            serde_json::json!({ "kind": "connection_limit", "message": "connection limit reached (64 connections)" })
+        );
+
+        let mut problem = CertificateProblem::new(CertificateReason::Expired, "irc.example.com");
+        problem.not_after = Some(1_700_000_000);
+        let err = SocketError::TlsCertificate(problem.describe(), problem);
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({
+                "kind": "tls_certificate",
+                "message": "certificate for irc.example.com has expired",
+                "certificate": {
+                    "reason": "expired",
+                    "host": "irc.example.com",
+                    "not_before": null,
+                    "not_after": 1_700_000_000,
+                    "presented_names": [],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_connect_io() {
+        let err = SocketError::from_connect_io(
+            "irc.example.com:6697",
+            std::io::Error::from(std::io::ErrorKind::TimedOut),
+        );
+        assert!(matches!(err, SocketError::ConnectTimeout(_)));
+
+        let err = SocketError::from_connect_io(
+            "irc.example.com:6697",
+            std::io::Error::from(std::io::ErrorKind::ConnectionRefused),
+        );
+        assert!(matches!(err, SocketError::ConnectionRefused(_)));
+
+        for code in UNREACHABLE_ERRNOS {
+            let err = SocketError::from_connect_io("irc.example.com:6697", std::io::Error::from_raw_os_error(code));
+            assert!(matches!(err, SocketError::Unreachable(_)), "{:?}", err);
+        }
+
+        // Anything else keeps its message under the generic variant
+        let err = SocketError::from_connect_io(
+            "irc.example.com:6697",
+            std::io::Error::other("boom"),
+        );
+        assert_eq!(err, SocketError::ConnectFailed("Failed to connect to irc.example.com:6697: boom".to_string()));
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+
+use super::SocketError;
+
+/// Head start each attempt gets before the next address is tried in
+/// parallel (RFC 8305 "Connection Attempt Delay")
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connect to the first of `addrs` that answers, logging every attempt
+/// with its timing
+///
+/// With `sequential`, addresses are tried one at a time in resolver order.
+/// Otherwise the address families are interleaved and a new attempt starts
+/// whenever the previous one fails or has had `CONNECTION_ATTEMPT_DELAY`,
+/// so a dead IPv6 route costs a quarter second instead of a full timeout.
+pub(super) async fn connect(target: &str, addrs: Vec<SocketAddr>, sequential: bool) -> Result<TcpStream, SocketError> {
+    let result = if sequential {
+        connect_sequential(addrs).await
+    } else {
+        connect_racing(interleave_families(addrs)).await
+    };
+    result.map_err(|e| SocketError::from_connect_io(target, e))
+}
+
+async fn connect_sequential(addrs: Vec<SocketAddr>) -> std::io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in addrs {
+        match attempt(addr).await {
+            (_, Ok(stream)) => return Ok(stream),
+            (_, Err(e)) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(no_addresses))
+}
+
+async fn connect_racing(addrs: Vec<SocketAddr>) -> std::io::Result<TcpStream> {
+    let mut pending = VecDeque::from(addrs);
+    // Dropping the set aborts the attempts that lost
+    let mut attempts = JoinSet::new();
+    let mut last_error = None;
+    loop {
+        if attempts.is_empty() {
+            let Some(addr) = pending.pop_front() else {
+                return Err(last_error.unwrap_or_else(no_addresses));
+            };
+            attempts.spawn(attempt(addr));
+        }
+        tokio::select! {
+            Some(joined) = attempts.join_next() => match joined {
+                Ok((_, Ok(stream))) => return Ok(stream),
+                Ok((_, Err(e))) => {
+                    last_error = Some(e);
+                    // A failure hands over to the next address right away
+                    if let Some(addr) = pending.pop_front() {
+                        attempts.spawn(attempt(addr));
+                    }
+                }
+                Err(e) => last_error = Some(std::io::Error::other(e)),
+            },
+            _ = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY), if !pending.is_empty() => {
+                if let Some(addr) = pending.pop_front() {
+                    log::info!("No answer after {:?}, also trying {}", CONNECTION_ATTEMPT_DELAY, addr);
+                    attempts.spawn(attempt(addr));
+                }
+            }
+        }
+    }
+}
+
+/// One connection attempt, logged with how long it took
+async fn attempt(addr: SocketAddr) -> (SocketAddr, std::io::Result<TcpStream>) {
+    let started = Instant::now();
+    let result = TcpStream::connect(addr).await;
+    match &result {
+        Ok(_) => log::info!("Connected to {} in {:?}", addr, started.elapsed()),
+        Err(e) => log::info!("Connecting to {} failed after {:?}: {}", addr, started.elapsed(), e),
+    }
+    (addr, result)
+}
+
+fn no_addresses() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses to connect to")
+}
+
+/// Alternate between address families, starting with the family of the
+/// resolver's first answer and otherwise keeping its order
+fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_ipv6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|addr| addr.is_ipv6() == first_is_ipv6);
+    let mut other = other.into_iter();
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    for addr in preferred {
+        interleaved.push(addr);
+        interleaved.extend(other.next());
+    }
+    interleaved.extend(other);
+    interleaved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(list: &[&str]) -> Vec<SocketAddr> {
+        list.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_interleave_families() {
+        assert_eq!(
+            interleave_families(addrs(&["[::1]:1", "[::2]:1", "[::3]:1", "10.0.0.1:1"])),
+            addrs(&["[::1]:1", "10.0.0.1:1", "[::2]:1", "[::3]:1"])
+        );
+        assert_eq!(
+            interleave_families(addrs(&["10.0.0.1:1", "10.0.0.2:1", "[::1]:1", "[::2]:1", "[::3]:1"])),
+            addrs(&["10.0.0.1:1", "[::1]:1", "10.0.0.2:1", "[::2]:1", "[::3]:1"])
+        );
+        assert!(interleave_families(Vec::new()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connect_skips_refused_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live = listener.local_addr().unwrap();
+        // Bound and dropped, so nothing listens there any more
+        let dead = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+
+        for sequential in [true, false] {
+            let stream = connect("test", vec![dead, live], sequential).await.unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), live);
+        }
+        let err = connect("test", vec![dead], false).await.unwrap_err();
+        assert!(matches!(err, SocketError::ConnectionRefused(_)), "{:?}", err);
+        assert!(connect("test", Vec::new(), true).await.is_err());
+    }
+}
@@ -0,0 +1,757 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Minimal split of a raw line into its command and parameters
+pub(super) fn split_line(line: &str) -> (String, Vec<String>) {
+    let mut rest = line;
+    if rest.starts_with('@') {
+        rest = rest.split_once(' ').map(|(_, r)| r).unwrap_or_default();
+    }
+    if rest.starts_with(':') {
+        rest = rest.split_once(' ').map(|(_, r)| r).unwrap_or_default();
+    }
+
+    let (middle, trailing) = match rest.split_once(" :") {
+        Some((middle, trailing)) => (middle, Some(trailing)),
+        None => (rest, None),
+    };
+    let mut words = middle.split_whitespace();
+    let command = words.next().unwrap_or_default().to_ascii_uppercase();
+    let mut params: Vec<String> = words.map(str::to_string).collect();
+    params.extend(trailing.map(str::to_string));
+    (command, params)
+}
+
+/// Source of a line, from its `:nick!user@host` or `:server` prefix
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct Prefix {
+    /// Nick, or the server name for a server prefix
+    nick: String,
+    user: Option<String>,
+    host: Option<String>,
+    /// The prefix names a server rather than a user
+    is_server: bool,
+}
+
+/// A line broken into its parts, without tags
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct ParsedLine {
+    prefix: Option<Prefix>,
+    /// Uppercased command, or the three-digit numeric as sent
+    command: String,
+    /// Middle parameters, not including `trailing`
+    params: Vec<String>,
+    /// The final `:`-prefixed parameter, which may contain spaces or be empty
+    trailing: Option<String>,
+    /// `command` as a number, for three-digit numeric replies
+    numeric: Option<u16>,
+    /// For numerics, the nick the reply is addressed to, which is also the
+    /// first of `params` (or `trailing`, when there are no others)
+    client: Option<String>,
+}
+
+/// Parse a line into prefix, command and parameters
+/// Returns `None` when there is no command, e.g. for an empty line
+pub(super) fn parse_line(line: &[u8]) -> Option<ParsedLine> {
+    let line = String::from_utf8_lossy(line);
+    let mut rest = line.trim_end_matches(['\r', '\n']).trim_start_matches(' ');
+    if rest.starts_with('@') {
+        rest = rest.split_once(' ').map(|(_, r)| r).unwrap_or_default().trim_start_matches(' ');
+    }
+    let mut prefix = None;
+    if let Some(source) = rest.strip_prefix(':') {
+        let (source, r) = source.split_once(' ').unwrap_or((source, ""));
+        prefix = Some(parse_prefix(source));
+        rest = r;
+    }
+
+    let (middle, trailing) = match rest.split_once(" :") {
+        Some((middle, trailing)) => (middle, Some(trailing.to_string())),
+        None => (rest, None),
+    };
+    let mut words = middle.split_whitespace();
+    let command = words.next()?.to_ascii_uppercase();
+    let params: Vec<String> = words.map(str::to_string).collect();
+    let numeric = parse_numeric(&command);
+    let client = numeric.and(params.first().or(trailing.as_ref())).cloned();
+    Some(ParsedLine {
+        prefix,
+        command,
+        params,
+        trailing,
+        numeric,
+        client,
+    })
+}
+
+/// The reply code of a three-digit numeric command
+fn parse_numeric(command: &str) -> Option<u16> {
+    if command.len() != 3 || !command.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    command.parse().ok()
+}
+
+/// Split `nick!user@host`; nicks can't contain `.`, so a bare name with one
+/// is a server
+fn parse_prefix(source: &str) -> Prefix {
+    let (rest, host) = match source.split_once('@') {
+        Some((rest, host)) => (rest, Some(host.to_string())),
+        None => (source, None),
+    };
+    let (nick, user) = match rest.split_once('!') {
+        Some((nick, user)) => (nick, Some(user.to_string())),
+        None => (rest, None),
+    };
+    Prefix {
+        nick: nick.to_string(),
+        is_server: user.is_none() && host.is_none() && nick.contains('.'),
+        user,
+        host,
+    }
+}
+
+/// Build the `PONG` reply for a server `PING` line, if `line` is one
+pub(super) fn pong_reply(line: &[u8]) -> Option<String> {
+    let line = String::from_utf8_lossy(line);
+    let (command, params) = split_line(line.trim_end_matches(['\r', '\n']));
+    if command != "PING" {
+        return None;
+    }
+    Some(format!("PONG :{}", params.last().map(String::as_str).unwrap_or_default()))
+}
+
+/// Token of a server `PONG` line, if `line` is one
+pub(super) fn pong_token(line: &[u8]) -> Option<String> {
+    let line = String::from_utf8_lossy(line);
+    let (command, params) = split_line(line.trim_end_matches(['\r', '\n']));
+    if command != "PONG" {
+        return None;
+    }
+    params.last().cloned()
+}
+
+/// Merged `RPL_ISUPPORT` (005) tokens of a connection; a token without a
+/// value maps to `None`
+pub(super) type Isupport = HashMap<String, Option<String>>;
+
+/// One token of an `RPL_ISUPPORT` line
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum IsupportToken {
+    /// `TOKEN` or `TOKEN=value`
+    Set(String, Option<String>),
+    /// `-TOKEN`, withdrawing one sent earlier
+    Remove(String),
+}
+
+/// Tokens of an `RPL_ISUPPORT` line, if `line` is one
+/// The leading nick and the trailing "are supported" text are skipped
+pub(super) fn isupport_tokens(line: &[u8]) -> Option<Vec<IsupportToken>> {
+    let parsed = parse_line(line)?;
+    if parsed.numeric != Some(5) {
+        return None;
+    }
+    let tokens = parsed.params.iter().skip(1).filter_map(|token| {
+        if let Some(name) = token.strip_prefix('-') {
+            return (!name.is_empty()).then(|| IsupportToken::Remove(name.to_string()));
+        }
+        let (name, value) = match token.split_once('=') {
+            // An empty value means the same as none
+            Some((name, value)) => (name, Some(value).filter(|value| !value.is_empty())),
+            None => (token.as_str(), None),
+        };
+        (!name.is_empty()).then(|| IsupportToken::Set(name.to_string(), value.map(unescape_isupport_value)))
+    });
+    Some(tokens.collect())
+}
+
+/// Apply the tokens of one `RPL_ISUPPORT` line on top of earlier ones
+pub(super) fn merge_isupport(isupport: &mut Isupport, tokens: Vec<IsupportToken>) {
+    for token in tokens {
+        match token {
+            IsupportToken::Set(name, value) => {
+                isupport.insert(name, value);
+            }
+            IsupportToken::Remove(name) => {
+                isupport.remove(&name);
+            }
+        }
+    }
+}
+
+/// Undo `\xHH` escaping in an ISUPPORT value; malformed escapes stay as
+/// they are
+fn unescape_isupport_value(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i..i + 4)
+            .filter(|escape| escape.starts_with(b"\\x"))
+            .and_then(|escape| std::str::from_utf8(&escape[2..]).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// Most bytes the IRCv3 tag block may take, including the `@` and the
+/// trailing space
+pub(super) const MAX_TAGS_LENGTH: usize = 8191;
+
+/// Byte length of the tag block at the start of `line`, including the `@`
+/// and the trailing space; 0 for untagged lines
+pub(super) fn tags_length(line: &str) -> usize {
+    if !line.starts_with('@') {
+        return 0;
+    }
+    line.find(' ').map_or(line.len(), |space| space + 1)
+}
+
+/// Parse the IRCv3 message tags at the start of `line`
+///
+/// Keys keep their client-only `+` and vendor prefixes; missing values are
+/// empty strings and a repeated key keeps its last value. Lines without
+/// tags, or whose tag block exceeds the 8191-byte limit, give `None`.
+pub(super) fn parse_tags(line: &[u8]) -> Option<HashMap<String, String>> {
+    let line = line.strip_prefix(b"@")?;
+    let end = line.iter().position(|&b| b == b' ').unwrap_or(line.len());
+    // +2 for the `@` and the space
+    if end + 2 > MAX_TAGS_LENGTH {
+        return None;
+    }
+    let block = String::from_utf8_lossy(&line[..end]);
+
+    let tags = block
+        .split(';')
+        .filter(|tag| !tag.is_empty())
+        .filter_map(|tag| {
+            let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+            (!key.is_empty()).then(|| (key.to_string(), unescape_tag_value(value)))
+        })
+        .collect();
+    Some(tags)
+}
+
+/// Undo IRCv3 tag value escaping (`\:` `\s` `\\` `\r` `\n`)
+/// Any other escaped character stands for itself and a trailing lone
+/// backslash is dropped
+fn unescape_tag_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Parse a `server-time` tag value (`2024-01-02T03:04:05.678Z`) into Unix
+/// milliseconds
+/// The fraction is optional and may have any number of digits; the time
+/// must be UTC
+pub(super) fn parse_server_time(value: &str) -> Option<u64> {
+    let value = value.strip_suffix('Z')?;
+    let (date, time) = value.split_once('T')?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = parse_digits(date_parts.next()?, 4)?;
+    let month: i64 = parse_digits(date_parts.next()?, 2)?;
+    let day: i64 = parse_digits(date_parts.next()?, 2)?;
+
+    let (clock, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut clock_parts = clock.splitn(3, ':');
+    let hour: i64 = parse_digits(clock_parts.next()?, 2)?;
+    let minute: i64 = parse_digits(clock_parts.next()?, 2)?;
+    // Allow a leap second (60), as server clocks may report one
+    let second: i64 = parse_digits(clock_parts.next()?, 2)?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let millis = if fraction.is_empty() {
+        0
+    } else {
+        if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        // Milliseconds from the first three digits, padding short fractions
+        format!("{:0<3}", &fraction[..fraction.len().min(3)]).parse::<i64>().ok()?
+    };
+
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second;
+    u64::try_from(seconds * 1000 + millis).ok()
+}
+
+/// Parse exactly `len` ASCII digits
+pub(super) fn parse_digits(digits: &str, len: usize) -> Option<i64> {
+    if digits.len() != len || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's
+/// `days_from_civil`)
+pub(super) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// CTCP content of a `PRIVMSG` or `NOTICE`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct CtcpMessage {
+    /// True for a `NOTICE`, which carries CTCP replies
+    reply: bool,
+    /// Every `\x01`-framed part, in order
+    commands: Vec<Ctcp>,
+    /// Text outside the framing, if the message mixed in plain text
+    text: Option<String>,
+}
+
+/// A single CTCP, e.g. `ACTION waves`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(super) struct Ctcp {
+    /// Uppercased command, e.g. `ACTION` or `VERSION`
+    command: String,
+    params: Option<String>,
+}
+
+/// Parse the CTCP parts of a `PRIVMSG`/`NOTICE` line, if it has any
+///
+/// A missing closing `\x01` on the last part is tolerated, as many clients
+/// send `\x01ACTION waves` unterminated.
+pub(super) fn parse_ctcp(line: &[u8]) -> Option<CtcpMessage> {
+    let line = String::from_utf8_lossy(line);
+    let (command, params) = split_line(line.trim_end_matches(['\r', '\n']));
+    let reply = match command.as_str() {
+        "PRIVMSG" => false,
+        "NOTICE" => true,
+        _ => return None,
+    };
+    let payload = params.get(1)?;
+    if !payload.contains('\x01') {
+        return None;
+    }
+
+    let mut commands = Vec::new();
+    let mut text = String::new();
+    // Pieces alternate plain text and CTCP bodies
+    for (index, piece) in payload.split('\x01').enumerate() {
+        if index % 2 == 0 {
+            text.push_str(piece);
+            continue;
+        }
+        let (command, params) = match piece.split_once(' ') {
+            Some((command, params)) => (command, Some(params.to_string()).filter(|p| !p.is_empty())),
+            None => (piece, None),
+        };
+        if !command.is_empty() {
+            commands.push(Ctcp {
+                command: command.to_ascii_uppercase(),
+                params,
+            });
+        }
+    }
+
+    Some(CtcpMessage {
+        reply,
+        commands,
+        text: Some(text).filter(|text| !text.is_empty()),
+    })
+}
+
+/// Maximum length of an IRC line including the trailing `\r\n`
+pub(super) const MAX_LINE_LENGTH: usize = 512;
+
+/// Room left for the `:nick!user@host ` the server puts in front of a
+/// message it relays: typical NICKLEN, USERLEN and HOSTLEN limits (30, 10
+/// and 63) plus the separators
+pub(super) const RELAY_PREFIX_LEN: usize = 1 + 30 + 1 + 10 + 1 + 63 + 1;
+
+/// Split `text` into as many `PRIVMSG` lines as needed to stay under
+/// `max_line_length` (including `\r\n`) once relayed to the recipients
+///
+/// Each line's budget accounts for `PRIVMSG <target> :`, the `\r\n` and
+/// `RELAY_PREFIX_LEN`. Splits never break a UTF-8 character and prefer the
+/// last whitespace within the budget. Embedded `\n`, `\r` and `\r\n` start
+/// a new message, and blank lines are dropped.
+pub(super) fn split_privmsg(target: &str, text: &str, max_line_length: usize) -> Vec<String> {
+    let prefix = format!("PRIVMSG {} :", target);
+    let budget = max_line_length
+        .saturating_sub(prefix.len() + 2 + RELAY_PREFIX_LEN)
+        .max(1);
+
+    let mut lines = Vec::new();
+    // Servers refuse an empty PRIVMSG with ERR_NOTEXTTOSEND
+    for paragraph in text.split(['\r', '\n']).filter(|paragraph| !paragraph.trim().is_empty()) {
+        let mut rest = paragraph;
+        while !rest.is_empty() {
+            let chunk_len = split_point(rest, budget);
+            let (chunk, remainder) = rest.split_at(chunk_len);
+            lines.push(format!("{}{}", prefix, chunk.trim_end()));
+            rest = remainder.trim_start();
+        }
+    }
+    lines
+}
+
+/// Byte length of the first chunk of `text` fitting in `budget` bytes
+fn split_point(text: &str, budget: usize) -> usize {
+    if text.len() <= budget {
+        return text.len();
+    }
+
+    // Largest char boundary within the budget (always at least one char)
+    let mut end = budget;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    if end == 0 {
+        end = text.chars().next().map(char::len_utf8).unwrap_or(text.len());
+    }
+
+    // Prefer breaking after the last whitespace, if there is one
+    match text[..end].rfind(char::is_whitespace) {
+        Some(space) if space > 0 => space,
+        _ => end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_line() {
+        assert_eq!(
+            split_line("@time=x :nick!u@h PRIVMSG #chan :hello there"),
+            (
+                "PRIVMSG".to_string(),
+                vec!["#chan".to_string(), "hello there".to_string()]
+            )
+        );
+        assert_eq!(split_line("ping abc"), ("PING".to_string(), vec!["abc".to_string()]));
+    }
+
+    fn prefix(nick: &str, user: Option<&str>, host: Option<&str>, is_server: bool) -> Option<Prefix> {
+        Some(Prefix {
+            nick: nick.to_string(),
+            user: user.map(str::to_string),
+            host: host.map(str::to_string),
+            is_server,
+        })
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line(b"@time=x :nick!user@host.example PRIVMSG #chan :hello: there\r\n"),
+            Some(ParsedLine {
+                prefix: prefix("nick", Some("user"), Some("host.example"), false),
+                command: "PRIVMSG".to_string(),
+                params: vec!["#chan".to_string()],
+                trailing: Some("hello: there".to_string()),
+                numeric: None,
+                client: None,
+            })
+        );
+        // Server prefix and numeric
+        assert_eq!(
+            parse_line(b":irc.example.net 001 me :Welcome to IRC\r\n"),
+            Some(ParsedLine {
+                prefix: prefix("irc.example.net", None, None, true),
+                command: "001".to_string(),
+                params: vec!["me".to_string()],
+                trailing: Some("Welcome to IRC".to_string()),
+                numeric: Some(1),
+                client: Some("me".to_string()),
+            })
+        );
+        // No prefix, no trailing, and an empty trailing
+        assert_eq!(
+            parse_line(b"ping abc"),
+            Some(ParsedLine {
+                prefix: None,
+                command: "PING".to_string(),
+                params: vec!["abc".to_string()],
+                trailing: None,
+                numeric: None,
+                client: None,
+            })
+        );
+        assert_eq!(
+            parse_line(b":nick MODE nick :"),
+            Some(ParsedLine {
+                prefix: prefix("nick", None, None, false),
+                command: "MODE".to_string(),
+                params: vec!["nick".to_string()],
+                trailing: Some(String::new()),
+                numeric: None,
+                client: None,
+            })
+        );
+        assert_eq!(parse_line(b"\r\n"), None);
+        assert_eq!(parse_line(b":server.only"), None);
+    }
+
+    #[test]
+    fn test_isupport() {
+        let mut isupport = Isupport::new();
+        let lines: [&[u8]; 3] = [
+            b":irc.example.net 005 me CHANTYPES=# PREFIX=(ov)@+ EXCEPTS :are supported by this server\r\n",
+            b":irc.example.net 005 me NETWORK=Example\\x20Net SAFELIST MODES= :are supported by this server\r\n",
+            b":irc.example.net 005 me -EXCEPTS -UNKNOWN CHANTYPES=#& :are supported by this server\r\n",
+        ];
+        for line in lines {
+            merge_isupport(&mut isupport, isupport_tokens(line).unwrap());
+        }
+        let expected: Isupport = [
+            ("CHANTYPES", Some("#&")),
+            ("PREFIX", Some("(ov)@+")),
+            ("NETWORK", Some("Example Net")),
+            ("SAFELIST", None),
+            ("MODES", None),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.map(str::to_string)))
+        .collect();
+        assert_eq!(isupport, expected);
+
+        assert_eq!(isupport_tokens(b":irc.example.net 001 me :Welcome"), None);
+        assert_eq!(isupport_tokens(b":irc.example.net 005 me :are supported"), Some(Vec::new()));
+        assert_eq!(unescape_isupport_value("a\\x3Db\\xZZ\\x4"), "a=b\\xZZ\\x4");
+    }
+
+    #[test]
+    fn test_parse_numeric() {
+        let welcome = parse_line(b":server 001 nick :Welcome").unwrap();
+        assert_eq!(welcome.numeric, Some(1));
+        assert_eq!(welcome.client.as_deref(), Some("nick"));
+        assert_eq!(welcome.trailing.as_deref(), Some("Welcome"));
+
+        let isupport = parse_line(
+            b":irc.example.net 005 me CHANTYPES=# NETWORK=Example PREFIX=(ov)@+ :are supported by this server\r\n",
+        )
+        .unwrap();
+        assert_eq!(isupport.numeric, Some(5));
+        assert_eq!(isupport.client.as_deref(), Some("me"));
+        assert_eq!(isupport.params, vec!["me", "CHANTYPES=#", "NETWORK=Example", "PREFIX=(ov)@+"]);
+
+        // Error numerics too; a client-less reply falls back to trailing
+        assert_eq!(parse_line(b":s 433 * taken :Nickname is already in use").unwrap().numeric, Some(433));
+        assert_eq!(parse_line(b":s 001 :me").unwrap().client.as_deref(), Some("me"));
+        // Only exactly three digits count
+        assert_eq!(parse_line(b":s 0001 me").unwrap().numeric, None);
+        assert_eq!(parse_line(b":s 1a1 me").unwrap().numeric, None);
+        assert_eq!(parse_line(b"PRIVMSG #c :001").unwrap().client, None);
+    }
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            parse_tags(b"@time=2024-01-01T00:00:00.000Z;msgid=abc :n!u@h PRIVMSG #c :hi\r\n"),
+            Some(tags(&[("time", "2024-01-01T00:00:00.000Z"), ("msgid", "abc")]))
+        );
+        // Client-only and vendor tags, empty and missing values
+        assert_eq!(
+            parse_tags(b"@+draft/reply=42;+typing=;example.com/flag;account= :n PRIVMSG #c :x"),
+            Some(tags(&[
+                ("+draft/reply", "42"),
+                ("+typing", ""),
+                ("example.com/flag", ""),
+                ("account", ""),
+            ]))
+        );
+        // A repeated key keeps the last value
+        assert_eq!(parse_tags(b"@a=1;a=2 PING x"), Some(tags(&[("a", "2")])));
+        assert_eq!(parse_tags(b":n!u@h PRIVMSG #c :no tags\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_tags_unescaping() {
+        assert_eq!(
+            parse_tags(br"@a=semi\:colon;b=sp\sace;c=back\\slash;d=cr\rlf\n PING x"),
+            Some(tags(&[
+                ("a", "semi;colon"),
+                ("b", "sp ace"),
+                ("c", "back\\slash"),
+                ("d", "cr\rlf\n"),
+            ]))
+        );
+        // Unknown escapes drop the backslash; a trailing one vanishes
+        assert_eq!(
+            parse_tags(br"@a=\b\x;b=end\ PING x"),
+            Some(tags(&[("a", "bx"), ("b", "end")]))
+        );
+        assert_eq!(unescape_tag_value(r"\\\:"), "\\;");
+    }
+
+    #[test]
+    fn test_parse_tags_length_limit() {
+        let value = "v".repeat(MAX_TAGS_LENGTH - 4);
+        // `@a=` + value + ` ` is exactly the limit
+        let line = format!("@a={} PING x", value);
+        assert_eq!(parse_tags(line.as_bytes()), Some(tags(&[("a", &value)])));
+        let line = format!("@a={}v PING x", value);
+        assert_eq!(parse_tags(line.as_bytes()), None);
+    }
+
+    #[test]
+    fn test_parse_server_time() {
+        assert_eq!(parse_server_time("1970-01-01T00:00:00.000Z"), Some(0));
+        assert_eq!(parse_server_time("2024-01-02T03:04:05.678Z"), Some(1_704_164_645_678));
+        // Leap day, no fraction, and a longer fraction
+        assert_eq!(parse_server_time("2024-02-29T12:00:00Z"), Some(1_709_208_000_000));
+        assert_eq!(parse_server_time("2024-02-29T12:00:00.5Z"), Some(1_709_208_000_500));
+        assert_eq!(parse_server_time("2024-02-29T12:00:00.123456Z"), Some(1_709_208_000_123));
+
+        assert_eq!(parse_server_time("2024-01-02T03:04:05.678"), None);
+        assert_eq!(parse_server_time("2024-01-02T03:04:05+01:00"), None);
+        assert_eq!(parse_server_time("2024-13-02T03:04:05Z"), None);
+        assert_eq!(parse_server_time("2024-01-02 03:04:05Z"), None);
+        assert_eq!(parse_server_time("1969-12-31T23:59:59Z"), None);
+        assert_eq!(parse_server_time("not a time"), None);
+    }
+
+    fn ctcp(command: &str, params: Option<&str>) -> Ctcp {
+        Ctcp {
+            command: command.to_string(),
+            params: params.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_ctcp() {
+        assert_eq!(
+            parse_ctcp(b":nick!u@h PRIVMSG #chan :\x01ACTION waves hello\x01\r\n"),
+            Some(CtcpMessage {
+                reply: false,
+                commands: vec![ctcp("ACTION", Some("waves hello"))],
+                text: None,
+            })
+        );
+        // Unterminated, and a reply without params
+        assert_eq!(
+            parse_ctcp(b":nick!u@h NOTICE me :\x01version"),
+            Some(CtcpMessage {
+                reply: true,
+                commands: vec![ctcp("VERSION", None)],
+                text: None,
+            })
+        );
+        // Several CTCPs mixed with plain text
+        assert_eq!(
+            parse_ctcp(b":n!u@h PRIVMSG me :hi \x01PING 123\x01 and \x01TIME\x01\r\n"),
+            Some(CtcpMessage {
+                reply: false,
+                commands: vec![ctcp("PING", Some("123")), ctcp("TIME", None)],
+                text: Some("hi  and ".to_string()),
+            })
+        );
+        assert_eq!(parse_ctcp(b":n!u@h PRIVMSG #chan :plain text\r\n"), None);
+        assert_eq!(parse_ctcp(b":n!u@h TOPIC #chan :\x01ACTION x\x01\r\n"), None);
+    }
+
+    #[test]
+    fn test_tags_length() {
+        assert_eq!(tags_length("PRIVMSG #c :hi"), 0);
+        assert_eq!(tags_length("@a=b;c PRIVMSG #c :hi"), 7);
+        assert_eq!(tags_length("@a=b"), 4);
+    }
+
+    #[test]
+    fn test_split_privmsg_short() {
+        assert_eq!(split_privmsg("#c", "hello", MAX_LINE_LENGTH), vec!["PRIVMSG #c :hello"]);
+        assert_eq!(
+            split_privmsg("#c", "one\r\ntwo", MAX_LINE_LENGTH),
+            vec!["PRIVMSG #c :one", "PRIVMSG #c :two"]
+        );
+        // A bare `\r` is a line break too, never sent as is
+        assert_eq!(
+            split_privmsg("#c", "one\rtwo\n\nthree", MAX_LINE_LENGTH),
+            vec!["PRIVMSG #c :one", "PRIVMSG #c :two", "PRIVMSG #c :three"]
+        );
+        assert_eq!(
+            split_privmsg("#c", "a\n \nb", MAX_LINE_LENGTH),
+            vec!["PRIVMSG #c :a", "PRIVMSG #c :b"]
+        );
+        assert!(split_privmsg("#c", "", MAX_LINE_LENGTH).is_empty());
+    }
+
+    #[test]
+    fn test_split_privmsg_long() {
+        let word = "abcdefghi ";
+        let text = word.repeat(100);
+        let lines = split_privmsg("#chan", text.trim_end(), MAX_LINE_LENGTH);
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            // Still within the limit with the relay prefix in front
+            assert!(RELAY_PREFIX_LEN + line.len() + 2 <= MAX_LINE_LENGTH);
+            // Whitespace break, so no word is cut in half
+            assert!(line.ends_with("abcdefghi"));
+        }
+        let rejoined: Vec<&str> = lines.iter().map(|l| &l["PRIVMSG #chan :".len()..]).collect();
+        assert_eq!(rejoined.join(" "), text.trim_end());
+    }
+
+    #[test]
+    fn test_split_privmsg_utf8_boundary() {
+        // No whitespace at all: must split on a character boundary
+        let text = "é".repeat(400);
+        let lines = split_privmsg("#c", &text, MAX_LINE_LENGTH);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|l| RELAY_PREFIX_LEN + l.len() + 2 <= MAX_LINE_LENGTH));
+        let rejoined: String = lines.iter().map(|l| &l["PRIVMSG #c :".len()..]).collect();
+        assert_eq!(rejoined, text);
+
+        // A connection with a larger limit gets longer lines
+        assert_eq!(split_privmsg("#c", &text, 4096).len(), 1);
+    }
+
+    #[test]
+    fn test_pong_reply() {
+        assert_eq!(pong_reply(b"PING :abc\r\n"), Some("PONG :abc".to_string()));
+        assert_eq!(pong_reply(b":irc.example.com PING abc\r\n"), Some("PONG :abc".to_string()));
+        assert_eq!(pong_reply(b":nick PRIVMSG #c :PING :abc\r\n"), None);
+    }
+
+    #[test]
+    fn test_pong_token() {
+        assert_eq!(
+            pong_token(b":irc.example.com PONG irc.example.com :lat-1\r\n"),
+            Some("lat-1".to_string())
+        );
+        assert_eq!(pong_token(b"PONG lat-2\r\n"), Some("lat-2".to_string()));
+        assert_eq!(pong_token(b"PING :lat-1\r\n"), None);
+    }
+}
@@ -0,0 +1,343 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::irc::split_line;
+use super::SocketError;
+
+/// How long the whole CAP/AUTHENTICATE exchange may take
+const SASL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Maximum payload size of a single AUTHENTICATE line
+const AUTHENTICATE_CHUNK_SIZE: usize = 400;
+
+/// SASL mechanism to authenticate with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SaslMechanism {
+    /// Username and password
+    #[default]
+    Plain,
+    /// Credentials the server already has from the transport, e.g. the
+    /// client certificate fingerprint (CertFP)
+    External,
+}
+
+impl SaslMechanism {
+    fn name(self) -> &'static str {
+        match self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::External => "EXTERNAL",
+        }
+    }
+}
+
+/// Credentials for backend-driven SASL
+///
+/// `username` and `password` are only used by PLAIN; EXTERNAL needs neither.
+#[derive(Clone, Deserialize)]
+pub struct SaslCredentials {
+    #[serde(default)]
+    pub mechanism: SaslMechanism,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+impl SaslCredentials {
+    /// Fail early on PLAIN without a username, which no server accepts
+    pub(super) fn validate(&self) -> Result<(), SocketError> {
+        if self.mechanism == SaslMechanism::Plain && self.username.is_empty() {
+            return Err(SocketError::InvalidOption("SASL PLAIN needs a username".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Base64 payload to answer the server's `AUTHENTICATE +` with; empty
+    /// for EXTERNAL, which goes out as a bare `+`
+    fn response(&self) -> String {
+        match self.mechanism {
+            SaslMechanism::Plain => STANDARD.encode(format!("\0{}\0{}", self.username, self.password)),
+            SaslMechanism::External => String::new(),
+        }
+    }
+}
+
+// Keep the password out of any debug output
+impl std::fmt::Debug for SaslCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SaslCredentials")
+            .field("mechanism", &self.mechanism)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Result of the SASL exchange, reported to the frontend as `sasl-result`
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct SaslOutcome {
+    pub success: bool,
+    pub message: String,
+}
+
+impl SaslOutcome {
+    fn failure(message: impl Into<String>) -> Self {
+        SaslOutcome {
+            success: false,
+            message: message.into(),
+        }
+    }
+}
+
+/// Run `CAP REQ :sasl` + `AUTHENTICATE <mechanism>` on a freshly opened
+/// stream
+///
+/// Returns the outcome and any bytes that were read but aren't part of the
+/// exchange (server notices, the start of the next line...), which the
+/// caller must feed to the regular read path so nothing is lost. That
+/// includes whatever was still unread when the exchange timed out.
+pub(super) async fn authenticate<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    credentials: &SaslCredentials,
+) -> (SaslOutcome, Vec<u8>)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut passthrough = Vec::new();
+    let mut line_buffer = Vec::new();
+    let exchange = run_exchange(reader, writer, credentials, &mut passthrough, &mut line_buffer);
+    let outcome = match tokio::time::timeout(SASL_TIMEOUT, exchange).await {
+        Ok(outcome) => outcome,
+        Err(_) => SaslOutcome::failure("SASL authentication timed out"),
+    };
+    // Hand the unread remainder back along with skipped lines
+    passthrough.extend_from_slice(&line_buffer);
+    (outcome, passthrough)
+}
+
+async fn run_exchange<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    credentials: &SaslCredentials,
+    passthrough: &mut Vec<u8>,
+    line_buffer: &mut Vec<u8>,
+) -> SaslOutcome
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    if let Err(e) = write_line(writer, "CAP REQ :sasl").await {
+        return SaslOutcome::failure(format!("Failed to request SASL: {}", e));
+    }
+
+    let mut read_buf = [0u8; 1024];
+
+    loop {
+        while let Some(pos) = line_buffer.windows(2).position(|w| w == b"\r\n") {
+            let line_data: Vec<u8> = line_buffer.drain(..pos + 2).collect();
+            let line = String::from_utf8_lossy(&line_data[..pos]).into_owned();
+
+            match handle_line(&line, writer, credentials).await {
+                Ok(Step::Continue) => {}
+                Ok(Step::Done(outcome)) => return outcome,
+                Ok(Step::Passthrough) => passthrough.extend_from_slice(&line_data),
+                Err(e) => return SaslOutcome::failure(format!("SASL write failed: {}", e)),
+            }
+        }
+
+        match reader.read(&mut read_buf).await {
+            Ok(0) => return SaslOutcome::failure("Connection closed during SASL"),
+            Ok(n) => line_buffer.extend_from_slice(&read_buf[..n]),
+            Err(e) => return SaslOutcome::failure(format!("SASL read failed: {}", e)),
+        }
+    }
+}
+
+enum Step {
+    Continue,
+    Passthrough,
+    Done(SaslOutcome),
+}
+
+/// React to a single line from the server during the exchange
+async fn handle_line<W>(
+    line: &str,
+    writer: &mut W,
+    credentials: &SaslCredentials,
+) -> std::io::Result<Step>
+where
+    W: AsyncWrite + Unpin,
+{
+    let (command, params) = split_line(line);
+    let param = |i: usize| params.get(i).map(String::as_str).unwrap_or_default();
+
+    match command.as_str() {
+        "PING" => {
+            write_line(writer, &format!("PONG :{}", param(0))).await?;
+            Ok(Step::Continue)
+        }
+        "CAP" if param(1).eq_ignore_ascii_case("ACK") => {
+            if !param(2).split_whitespace().any(|cap| cap == "sasl") {
+                return Ok(Step::Done(SaslOutcome::failure("Server did not acknowledge sasl")));
+            }
+            write_line(writer, &format!("AUTHENTICATE {}", credentials.mechanism.name())).await?;
+            Ok(Step::Continue)
+        }
+        "CAP" if param(1).eq_ignore_ascii_case("NAK") => Ok(Step::Done(SaslOutcome::failure(
+            "Server does not support SASL",
+        ))),
+        "AUTHENTICATE" if param(0) == "+" => {
+            let payload = credentials.response();
+            for chunk in authenticate_chunks(&payload) {
+                write_line(writer, &format!("AUTHENTICATE {}", chunk)).await?;
+            }
+            Ok(Step::Continue)
+        }
+        // RPL_SASLSUCCESS
+        "903" => Ok(Step::Done(SaslOutcome {
+            success: true,
+            message: params.last().cloned().unwrap_or_default(),
+        })),
+        // ERR_NICKLOCKED, ERR_SASLFAIL, ERR_SASLTOOLONG, ERR_SASLABORTED, ERR_SASLALREADY
+        "902" | "904" | "905" | "906" | "907" => Ok(Step::Done(SaslOutcome::failure(
+            params.last().cloned().unwrap_or_else(|| "SASL authentication failed".to_string()),
+        ))),
+        // RPL_LOGGEDIN / RPL_SASLMECHS are informational, the result follows
+        "900" | "908" => Ok(Step::Continue),
+        _ => Ok(Step::Passthrough),
+    }
+}
+
+/// Split an AUTHENTICATE payload into 400-byte lines, ending with `+` when
+/// the last chunk is exactly 400 bytes (or the payload is empty)
+fn authenticate_chunks(payload: &str) -> Vec<&str> {
+    let mut chunks: Vec<&str> = payload
+        .as_bytes()
+        .chunks(AUTHENTICATE_CHUNK_SIZE)
+        // base64 is ASCII, so byte chunks are always valid UTF-8
+        .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+        .collect();
+    if payload.len() % AUTHENTICATE_CHUNK_SIZE == 0 {
+        chunks.push("+");
+    }
+    chunks
+}
+
+async fn write_line<W>(writer: &mut W, line: &str) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(format!("{}\r\n", line).as_bytes()).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authenticate_chunks() {
+        assert_eq!(authenticate_chunks("abc"), vec!["abc"]);
+        let exact = "a".repeat(400);
+        assert_eq!(authenticate_chunks(&exact), vec![exact.as_str(), "+"]);
+        assert_eq!(authenticate_chunks(""), vec!["+"]);
+    }
+
+    #[tokio::test]
+    async fn test_plain_exchange() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let (mut reader, mut writer) = tokio::io::split(client);
+        let credentials = SaslCredentials {
+            mechanism: SaslMechanism::Plain,
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        };
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 1024];
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"CAP REQ :sasl\r\n");
+            server
+                .write_all(b":irc NOTICE * :hello\r\n:irc CAP * ACK :sasl\r\n")
+                .await
+                .unwrap();
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"AUTHENTICATE PLAIN\r\n");
+            server.write_all(b"AUTHENTICATE +\r\n").await.unwrap();
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"AUTHENTICATE AHVzZXIAcGFzcw==\r\n");
+            server
+                .write_all(b":irc 903 * :SASL authentication successful\r\n:irc NOTICE")
+                .await
+                .unwrap();
+        });
+
+        let (outcome, passthrough) = authenticate(&mut reader, &mut writer, &credentials).await;
+        server_task.await.unwrap();
+        assert!(outcome.success);
+        assert_eq!(passthrough, b":irc NOTICE * :hello\r\n:irc NOTICE");
+    }
+
+    #[tokio::test]
+    async fn test_external_exchange() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let (mut reader, mut writer) = tokio::io::split(client);
+        let credentials: SaslCredentials = serde_json::from_str(r#"{ "mechanism": "EXTERNAL" }"#).unwrap();
+        assert!(credentials.validate().is_ok());
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 1024];
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"CAP REQ :sasl\r\n");
+            server.write_all(b":irc CAP * ACK :sasl\r\n").await.unwrap();
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"AUTHENTICATE EXTERNAL\r\n");
+            server.write_all(b"AUTHENTICATE +\r\n").await.unwrap();
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"AUTHENTICATE +\r\n");
+            server
+                .write_all(b":irc 900 * me!u@h me :You are now logged in as me\r\n:irc 903 * :SASL authentication successful\r\n")
+                .await
+                .unwrap();
+        });
+
+        let (outcome, passthrough) = authenticate(&mut reader, &mut writer, &credentials).await;
+        server_task.await.unwrap();
+        assert!(outcome.success);
+        assert!(passthrough.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ack_without_sasl_fails() {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let (mut reader, mut writer) = tokio::io::split(client);
+        let credentials: SaslCredentials = serde_json::from_str(r#"{ "mechanism": "EXTERNAL" }"#).unwrap();
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 1024];
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"CAP REQ :sasl\r\n");
+            server.write_all(b":irc CAP * ACK :multi-prefix\r\n").await.unwrap();
+            server
+        });
+
+        let (outcome, passthrough) = authenticate(&mut reader, &mut writer, &credentials).await;
+        let _server = server_task.await.unwrap();
+        assert!(!outcome.success);
+        assert_eq!(outcome.message, "Server did not acknowledge sasl");
+        assert!(passthrough.is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let plain: SaslCredentials = serde_json::from_str(r#"{ "password": "pass" }"#).unwrap();
+        assert_eq!(plain.mechanism, SaslMechanism::Plain);
+        assert!(matches!(plain.validate(), Err(SocketError::InvalidOption(_))));
+        assert!(serde_json::from_str::<SaslCredentials>(r#"{ "mechanism": "SCRAM" }"#).is_err());
+    }
+}
@@ -0,0 +1,567 @@
+use serde::Serialize;
+use tokio::net::TcpStream;
+
+use super::certificate::CertificateInfo;
+use super::error::{CertificateProblem, CertificateReason};
+use super::{ConnectOptions, OpenedStream, SocketError};
+
+// Platform-specific TLS imports
+#[cfg(not(target_os = "android"))]
+use tokio_native_tls::TlsConnector;
+#[cfg(not(target_os = "android"))]
+use native_tls::TlsConnector as NativeTlsConnector;
+
+#[cfg(target_os = "android")]
+use tokio_rustls::TlsConnector;
+#[cfg(target_os = "android")]
+use rustls::pki_types::ServerName;
+#[cfg(target_os = "android")]
+use std::sync::Arc as StdArc;
+#[cfg(target_os = "android")]
+use webpki_roots;
+
+/// Negotiated TLS parameters reported to the frontend
+///
+/// Fields are `None` when the platform TLS library can't report them
+/// (native-tls exposes neither the protocol version nor the cipher).
+#[derive(Debug, Clone, Default, Serialize)]
+pub(super) struct TlsInfo {
+    /// e.g. "TLS 1.3"
+    pub protocol_version: Option<String>,
+    /// e.g. "TLS13_AES_256_GCM_SHA384"
+    pub cipher_suite: Option<String>,
+    /// ALPN protocol selected by the server, if any was offered and chosen
+    pub alpn_protocol: Option<String>,
+    /// The server's leaf certificate
+    pub certificate: Option<CertificateInfo>,
+}
+
+/// Names `cipher_suites` accepts: what rustls implements, spelled as
+/// `tls_info` reports them
+const KNOWN_CIPHER_SUITES: &[&str] = &[
+    "TLS13_AES_256_GCM_SHA384",
+    "TLS13_AES_128_GCM_SHA256",
+    "TLS13_CHACHA20_POLY1305_SHA256",
+    "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+    "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+    "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+    "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+    "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+    "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+];
+
+/// Whether `handshake` honors `cipher_suites`; native-tls has no way to
+/// choose them
+pub(super) const CIPHER_SUITES_CONFIGURABLE: bool = cfg!(target_os = "android");
+
+/// Warning for the `connected` event when `cipher_suites` couldn't be applied
+pub(super) const CIPHER_SUITES_IGNORED: &str =
+    "cipher_suites is not supported by this platform's TLS library and was ignored";
+
+/// Reject an empty `cipher_suites` list or one naming a suite we don't know
+pub(super) fn validate_cipher_suites(names: &[String]) -> Result<(), SocketError> {
+    if names.is_empty() {
+        return Err(SocketError::InvalidOption("cipher_suites must name at least one suite".to_string()));
+    }
+    match names.iter().find(|name| !KNOWN_CIPHER_SUITES.iter().any(|known| known.eq_ignore_ascii_case(name))) {
+        Some(name) => Err(SocketError::InvalidOption(format!("Unknown cipher suite: {}", name))),
+        None => Ok(()),
+    }
+}
+
+/// Oldest TLS version a connection may negotiate
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) enum MinTlsVersion {
+    #[default]
+    Tls12,
+    Tls13,
+}
+
+impl MinTlsVersion {
+    /// Parse `min_tls_version`: `"1.2"` or `"1.3"`, optionally `TLS`-prefixed
+    pub(super) fn from_label(label: &str) -> Option<Self> {
+        let label = label.trim().to_ascii_lowercase();
+        let version = label.strip_prefix("tls").unwrap_or(&label).trim_start_matches([' ', 'v']);
+        match version {
+            "1.2" => Some(MinTlsVersion::Tls12),
+            "1.3" => Some(MinTlsVersion::Tls13),
+            _ => None,
+        }
+    }
+
+    /// Policy description for handshake errors
+    fn describe(self) -> &'static str {
+        match self {
+            MinTlsVersion::Tls12 => "TLS 1.2 or newer",
+            MinTlsVersion::Tls13 => "TLS 1.3",
+        }
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn native_protocol(self) -> native_tls::Protocol {
+        match self {
+            MinTlsVersion::Tls12 => native_tls::Protocol::Tlsv12,
+            MinTlsVersion::Tls13 => native_tls::Protocol::Tlsv13,
+        }
+    }
+
+    #[cfg(target_os = "android")]
+    fn rustls_versions(self) -> &'static [&'static rustls::SupportedProtocolVersion] {
+        static TLS13_ONLY: &[&rustls::SupportedProtocolVersion] = &[&rustls::version::TLS13];
+        match self {
+            MinTlsVersion::Tls12 => rustls::ALL_VERSIONS,
+            MinTlsVersion::Tls13 => TLS13_ONLY,
+        }
+    }
+}
+
+/// What a failed handshake was refused for, when the TLS library's error
+/// tells us
+#[derive(Debug, Clone, PartialEq)]
+enum FailureCause {
+    Certificate(CertificateProblem),
+    ProtocolVersion,
+}
+
+/// Handshake error for `e`, classified by `cause` when known. Unknown
+/// causes name the version policy, the likely culprit when the server only
+/// offers older protocols.
+fn handshake_error(e: impl std::fmt::Display, cause: Option<FailureCause>, min_version: MinTlsVersion) -> SocketError {
+    match cause {
+        Some(FailureCause::Certificate(problem)) => {
+            SocketError::TlsCertificate(format!("TLS handshake failed: {} ({})", problem.describe(), e), problem)
+        }
+        Some(FailureCause::ProtocolVersion) => SocketError::TlsProtocolVersion(format!(
+            "TLS handshake failed: no TLS version in common: {} (policy requires {})",
+            e,
+            min_version.describe()
+        )),
+        None => SocketError::TlsHandshake(format!(
+            "TLS handshake failed: {} (policy requires {})",
+            e,
+            min_version.describe()
+        )),
+    }
+}
+
+/// Schannel error codes (`SEC_E_*`, `CERT_E_*`), which Windows reports as
+/// `(os error <code>)` after a message in the user's language
+const SCHANNEL_CAUSES: &[(i32, Option<CertificateReason>)] = &[
+    // SEC_E_CERT_EXPIRED, CERT_E_EXPIRED
+    (-2146893016, Some(CertificateReason::Expired)),
+    (-2146762495, Some(CertificateReason::Expired)),
+    // SEC_E_WRONG_PRINCIPAL, CERT_E_CN_NO_MATCH
+    (-2146893022, Some(CertificateReason::HostnameMismatch)),
+    (-2146762481, Some(CertificateReason::HostnameMismatch)),
+    // SEC_E_UNTRUSTED_ROOT, CERT_E_UNTRUSTEDROOT
+    (-2146893019, Some(CertificateReason::UnknownIssuer)),
+    (-2146762487, Some(CertificateReason::UnknownIssuer)),
+    // SEC_E_ALGORITHM_MISMATCH, SEC_E_UNSUPPORTED_FUNCTION: no protocol in common
+    (-2146893007, None),
+    (-2146893054, None),
+];
+
+/// Phrases OpenSSL (its verify results), Security.framework and Schannel
+/// use for each cause, lowercased
+const CAUSE_PHRASES: &[(&str, Option<CertificateReason>)] = &[
+    ("not yet valid", Some(CertificateReason::NotYetValid)),
+    ("not valid yet", Some(CertificateReason::NotYetValid)),
+    ("expired", Some(CertificateReason::Expired)),
+    ("hostname mismatch", Some(CertificateReason::HostnameMismatch)),
+    ("host name mismatch", Some(CertificateReason::HostnameMismatch)),
+    ("name does not match", Some(CertificateReason::HostnameMismatch)),
+    ("principal name is incorrect", Some(CertificateReason::HostnameMismatch)),
+    ("unable to get local issuer", Some(CertificateReason::UnknownIssuer)),
+    ("unable to get issuer", Some(CertificateReason::UnknownIssuer)),
+    ("self signed", Some(CertificateReason::UnknownIssuer)),
+    ("self-signed", Some(CertificateReason::UnknownIssuer)),
+    ("not trusted", Some(CertificateReason::UnknownIssuer)),
+    ("unknown ca", Some(CertificateReason::UnknownIssuer)),
+    ("unsupported protocol", None),
+    ("wrong version number", None),
+    ("protocol version", None),
+    ("no protocols available", None),
+];
+
+/// Classify a native-tls handshake error by its message, the only thing
+/// it exposes; `None` entries in the tables mean a protocol mismatch
+#[cfg_attr(target_os = "android", allow(dead_code))]
+fn native_cause(message: &str, host: &str) -> Option<FailureCause> {
+    let lowercase = message.to_lowercase();
+    let by_code = SCHANNEL_CAUSES
+        .iter()
+        .find(|(code, _)| lowercase.contains(&format!("(os error {})", code)))
+        .map(|(_, reason)| reason);
+    let by_phrase = || {
+        CAUSE_PHRASES
+            .iter()
+            .find(|(phrase, _)| lowercase.contains(phrase))
+            .map(|(_, reason)| reason)
+    };
+    let reason = by_code.or_else(by_phrase)?;
+    Some(match reason {
+        Some(reason) => FailureCause::Certificate(CertificateProblem::new(*reason, host)),
+        None => FailureCause::ProtocolVersion,
+    })
+}
+
+/// Classify a rustls handshake error, which tokio-rustls wraps in the
+/// `io::Error`, with the dates and names rustls reports
+#[cfg(target_os = "android")]
+fn rustls_cause(e: &std::io::Error, host: &str) -> Option<FailureCause> {
+    use rustls::{AlertDescription, CertificateError, Error, PeerIncompatible};
+
+    let problem = |reason| CertificateProblem::new(reason, host);
+    let cause = match e.get_ref()?.downcast_ref::<Error>()? {
+        Error::InvalidCertificate(certificate_error) => FailureCause::Certificate(match certificate_error {
+            CertificateError::Expired => problem(CertificateReason::Expired),
+            CertificateError::ExpiredContext { not_after, .. } => CertificateProblem {
+                not_after: Some(not_after.as_secs()),
+                ..problem(CertificateReason::Expired)
+            },
+            CertificateError::NotValidYet => problem(CertificateReason::NotYetValid),
+            CertificateError::NotValidYetContext { not_before, .. } => CertificateProblem {
+                not_before: Some(not_before.as_secs()),
+                ..problem(CertificateReason::NotYetValid)
+            },
+            CertificateError::NotValidForName => problem(CertificateReason::HostnameMismatch),
+            CertificateError::NotValidForNameContext { presented, .. } => CertificateProblem {
+                presented_names: presented.clone(),
+                ..problem(CertificateReason::HostnameMismatch)
+            },
+            CertificateError::UnknownIssuer => problem(CertificateReason::UnknownIssuer),
+            _ => return None,
+        }),
+        Error::PeerIncompatible(
+            PeerIncompatible::ServerDoesNotSupportTls12Or13
+            | PeerIncompatible::ServerTlsVersionIsDisabledByOurConfig
+            | PeerIncompatible::SupportedVersionsExtensionRequired
+            | PeerIncompatible::Tls12NotOffered
+            | PeerIncompatible::Tls12NotOfferedOrEnabled,
+        )
+        | Error::AlertReceived(AlertDescription::ProtocolVersion) => FailureCause::ProtocolVersion,
+        _ => return None,
+    };
+    Some(cause)
+}
+
+/// Host name to send as SNI and validate the certificate against
+///
+/// Defaults to the connection host; `sni_host` overrides it, e.g. when
+/// connecting by IP or through a reverse proxy.
+fn server_name<'a>(host: &'a str, options: &'a ConnectOptions) -> Result<&'a str, SocketError> {
+    match options.sni_host.as_deref() {
+        Some(sni_host) if is_valid_dns_name(sni_host) => Ok(sni_host),
+        Some(sni_host) => Err(SocketError::InvalidAddress(format!(
+            "Invalid SNI host name: {}",
+            sni_host
+        ))),
+        None => Ok(host),
+    }
+}
+
+/// Check that `name` is a syntactically valid DNS host name
+fn is_valid_dns_name(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.is_empty() || name.len() > 253 || name.parse::<std::net::IpAddr>().is_ok() {
+        return false;
+    }
+    name.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    })
+}
+
+/// Perform the TLS handshake on an established TCP stream
+#[cfg(not(target_os = "android"))]
+pub(super) async fn handshake(
+    host: &str,
+    tcp_stream: TcpStream,
+    options: &ConnectOptions,
+) -> Result<OpenedStream, SocketError> {
+    let min_version = options.min_tls_version()?;
+    if options.cipher_suites.is_some() {
+        log::warn!("Ignoring cipher_suites for {}: native-tls uses the system's choice", host);
+    }
+    let mut builder = NativeTlsConnector::builder();
+    builder.min_protocol_version(Some(min_version.native_protocol()));
+    if options.danger_skip_verification {
+        log::warn!("TLS certificate and hostname verification DISABLED for {}", host);
+        builder
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+    if let Some(alpn) = &options.alpn {
+        let protocols: Vec<&str> = alpn.iter().map(String::as_str).collect();
+        builder.request_alpns(&protocols);
+    }
+
+    let connector = TlsConnector::from(
+        builder
+            .build()
+            .map_err(|e| SocketError::TlsHandshake(format!("Failed to create TLS connector: {}", e)))?
+    );
+
+    let sni_host = server_name(host, options)?;
+    let tls_stream = connector.connect(sni_host, tcp_stream).await.map_err(|e| {
+        let cause = native_cause(&e.to_string(), sni_host);
+        handshake_error(e, cause, min_version)
+    })?;
+
+    // native-tls can only tell us the ALPN result and the certificate
+    let inner = tls_stream.get_ref();
+    let tls_info = TlsInfo {
+        alpn_protocol: inner
+            .negotiated_alpn()
+            .ok()
+            .flatten()
+            .map(|protocol| String::from_utf8_lossy(&protocol).into_owned()),
+        certificate: inner
+            .peer_certificate()
+            .ok()
+            .flatten()
+            .and_then(|certificate| certificate.to_der().ok())
+            .map(|der| CertificateInfo::from_der(&der)),
+        ..Default::default()
+    };
+
+    // Split the TLS stream using tokio::io::split
+    let (reader, writer) = tokio::io::split(tls_stream);
+    let mut stream = OpenedStream::new(reader, writer);
+    stream.tls_info = Some(tls_info);
+    Ok(stream)
+}
+
+/// Perform the TLS handshake on an established TCP stream
+#[cfg(target_os = "android")]
+pub(super) async fn handshake(
+    host: &str,
+    tcp_stream: TcpStream,
+    options: &ConnectOptions,
+) -> Result<OpenedStream, SocketError> {
+    // Create rustls config with webpki roots
+    let root_store = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+
+    let min_version = options.min_tls_version()?;
+    let mut provider = rustls::crypto::ring::default_provider();
+    if let Some(names) = &options.cipher_suites {
+        provider.cipher_suites.retain(|suite| {
+            suite
+                .suite()
+                .as_str()
+                .is_some_and(|suite| names.iter().any(|name| name.eq_ignore_ascii_case(suite)))
+        });
+    }
+    // Fails when no allowed suite fits the allowed versions, e.g. only
+    // TLS 1.2 suites with a TLS 1.3 minimum
+    let builder = rustls::ClientConfig::builder_with_provider(StdArc::new(provider))
+        .with_protocol_versions(min_version.rustls_versions())
+        .map_err(|e| {
+            SocketError::InvalidOption(format!("No allowed cipher suite works with {}: {}", min_version.describe(), e))
+        })?;
+    let mut config = if options.danger_skip_verification {
+        log::warn!("TLS certificate and hostname verification DISABLED for {}", host);
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(StdArc::new(NoVerification))
+            .with_no_client_auth()
+    } else {
+        builder
+            .with_root_certificates(root_store)
+            .with_no_client_auth()
+    };
+    if let Some(alpn) = &options.alpn {
+        config.alpn_protocols = alpn.iter().map(|protocol| protocol.as_bytes().to_vec()).collect();
+    }
+
+    let connector = TlsConnector::from(StdArc::new(config));
+
+    let sni_host = server_name(host, options)?;
+    let server_name = ServerName::try_from(sni_host.to_string())
+        .map_err(|_| SocketError::InvalidAddress(format!("Invalid DNS name: {}", sni_host)))?;
+
+    let tls_stream = connector.connect(server_name, tcp_stream).await.map_err(|e| {
+        let cause = rustls_cause(&e, sni_host);
+        handshake_error(e, cause, min_version)
+    })?;
+
+    let (_, connection) = tls_stream.get_ref();
+    let tls_info = TlsInfo {
+        protocol_version: connection.protocol_version().map(|version| match version {
+            rustls::ProtocolVersion::TLSv1_2 => "TLS 1.2".to_string(),
+            rustls::ProtocolVersion::TLSv1_3 => "TLS 1.3".to_string(),
+            other => format!("{:?}", other),
+        }),
+        cipher_suite: connection
+            .negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite())),
+        alpn_protocol: connection
+            .alpn_protocol()
+            .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+        certificate: connection
+            .peer_certificates()
+            .and_then(|certificates| certificates.first())
+            .map(|certificate| CertificateInfo::from_der(certificate)),
+    };
+
+    // Split the TLS stream using tokio::io::split
+    let (reader, writer) = tokio::io::split(tls_stream);
+    let mut stream = OpenedStream::new(reader, writer);
+    stream.tls_info = Some(tls_info);
+    Ok(stream)
+}
+
+/// Certificate verifier that accepts anything, for `danger_skip_verification`
+#[cfg(target_os = "android")]
+#[derive(Debug)]
+struct NoVerification;
+
+#[cfg(target_os = "android")]
+impl rustls::client::danger::ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::pki_types::CertificateDer<'_>,
+        _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &rustls::pki_types::CertificateDer<'_>,
+        _dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &rustls::pki_types::CertificateDer<'_>,
+        _dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_dns_name() {
+        assert!(is_valid_dns_name("irc.libera.chat"));
+        assert!(is_valid_dns_name("irc.libera.chat."));
+        assert!(is_valid_dns_name("localhost"));
+        assert!(!is_valid_dns_name(""));
+        assert!(!is_valid_dns_name("192.0.2.1"));
+        assert!(!is_valid_dns_name("bad..name"));
+        assert!(!is_valid_dns_name("-bad.example.com"));
+        assert!(!is_valid_dns_name("spaces are.bad"));
+    }
+
+    #[test]
+    fn test_validate_cipher_suites() {
+        let names = |list: &[&str]| list.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert!(validate_cipher_suites(&names(&["TLS13_AES_128_GCM_SHA256", "tls_ecdhe_rsa_with_aes_256_gcm_sha384"])).is_ok());
+        assert!(validate_cipher_suites(&names(&["TLS_RSA_WITH_RC4_128_SHA"])).is_err());
+        assert!(validate_cipher_suites(&[]).is_err());
+    }
+
+    #[test]
+    fn test_min_tls_version() {
+        assert_eq!(MinTlsVersion::from_label("1.2"), Some(MinTlsVersion::Tls12));
+        assert_eq!(MinTlsVersion::from_label("TLS 1.3"), Some(MinTlsVersion::Tls13));
+        assert_eq!(MinTlsVersion::from_label("tlsv1.3"), Some(MinTlsVersion::Tls13));
+        assert_eq!(MinTlsVersion::from_label("1.1"), None);
+        assert_eq!(MinTlsVersion::from_label("1.0"), None);
+
+        let mut options = ConnectOptions::default();
+        assert_eq!(options.min_tls_version().unwrap(), MinTlsVersion::Tls12);
+        options.min_tls_version = Some("1.3".to_string());
+        assert_eq!(options.min_tls_version().unwrap(), MinTlsVersion::Tls13);
+        options.min_tls_version = Some("1.0".to_string());
+        assert!(matches!(options.min_tls_version(), Err(SocketError::InvalidOption(_))));
+    }
+
+    #[cfg(not(target_os = "android"))]
+    #[test]
+    fn test_native_min_protocol() {
+        assert!(matches!(MinTlsVersion::Tls12.native_protocol(), native_tls::Protocol::Tlsv12));
+        assert!(matches!(MinTlsVersion::Tls13.native_protocol(), native_tls::Protocol::Tlsv13));
+        // The connector builds with either floor
+        for version in [MinTlsVersion::Tls12, MinTlsVersion::Tls13] {
+            let mut builder = NativeTlsConnector::builder();
+            builder.min_protocol_version(Some(version.native_protocol()));
+            assert!(builder.build().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_handshake_error_names_policy() {
+        let err = handshake_error("unsupported protocol", None, MinTlsVersion::Tls13);
+        assert_eq!(
+            err,
+            SocketError::TlsHandshake("TLS handshake failed: unsupported protocol (policy requires TLS 1.3)".to_string())
+        );
+        let err = handshake_error("unsupported protocol", Some(FailureCause::ProtocolVersion), MinTlsVersion::Tls13);
+        assert!(matches!(err, SocketError::TlsProtocolVersion(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_native_cause() {
+        let certificate = |reason| Some(FailureCause::Certificate(CertificateProblem::new(reason, "irc.example.com")));
+        let cases = [
+            // OpenSSL
+            (
+                "error:0A000086:SSL routines:tls_post_process_server_certificate:certificate verify failed:../ssl/statem/statem_clnt.c:1889: (certificate has expired)",
+                certificate(CertificateReason::Expired),
+            ),
+            ("certificate verify failed: (certificate is not yet valid)", certificate(CertificateReason::NotYetValid)),
+            ("certificate verify failed: (Hostname mismatch)", certificate(CertificateReason::HostnameMismatch)),
+            ("certificate verify failed: (self-signed certificate)", certificate(CertificateReason::UnknownIssuer)),
+            (
+                "certificate verify failed: (unable to get local issuer certificate)",
+                certificate(CertificateReason::UnknownIssuer),
+            ),
+            ("error:0A00010B:SSL routines:ssl3_get_record:wrong version number", Some(FailureCause::ProtocolVersion)),
+            // Schannel, matched by code whatever the language
+            ("Le nom principal cible est incorrect. (os error -2146893022)", certificate(CertificateReason::HostnameMismatch)),
+            ("(os error -2146893007)", Some(FailureCause::ProtocolVersion)),
+            ("connection reset by peer", None),
+        ];
+        for (message, cause) in cases {
+            assert_eq!(native_cause(message, "irc.example.com"), cause, "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_server_name_override() {
+        let mut options = ConnectOptions::default();
+        assert_eq!(server_name("192.0.2.1", &options).unwrap(), "192.0.2.1");
+
+        options.sni_host = Some("irc.example.com".to_string());
+        assert_eq!(server_name("192.0.2.1", &options).unwrap(), "irc.example.com");
+
+        options.sni_host = Some("not a host".to_string());
+        assert!(matches!(
+            server_name("192.0.2.1", &options),
+            Err(SocketError::InvalidAddress(_))
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::Mutex;
+
+/// Traffic log shared between a connection's read and write tasks
+pub(super) type SharedTrafficLog = Arc<Mutex<Option<TrafficLog>>>;
+
+/// Which way a logged line travelled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+impl Direction {
+    fn marker(self) -> &'static str {
+        match self {
+            Direction::Incoming => "<<",
+            Direction::Outgoing => ">>",
+        }
+    }
+}
+
+/// Raw IRC traffic log file for a single connection
+#[derive(Debug)]
+pub(super) struct TrafficLog {
+    writer: BufWriter<File>,
+    redact_auth: bool,
+}
+
+impl TrafficLog {
+    /// Open (or create) `path` for appending
+    pub(super) async fn open(path: &str, redact_auth: bool) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path).await?;
+        Ok(TrafficLog {
+            writer: BufWriter::new(file),
+            redact_auth,
+        })
+    }
+
+    /// Append one line (or raw chunk) with a timestamp and direction marker,
+    /// one entry per line when it holds several
+    async fn write(&mut self, direction: Direction, data: &[u8]) -> std::io::Result<()> {
+        let text = String::from_utf8_lossy(data);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        for line in entry_lines(&text, self.redact_auth) {
+            let entry = format!(
+                "{}.{:03} {} {}\n",
+                timestamp.as_secs(),
+                timestamp.subsec_millis(),
+                direction.marker(),
+                line
+            );
+            self.writer.write_all(entry.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// Flush buffered entries to disk
+    pub(super) async fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush().await
+    }
+}
+
+/// Record `data` in the connection's traffic log, if logging is enabled
+pub(super) async fn record(log: &SharedTrafficLog, direction: Direction, data: &[u8]) {
+    let mut log = log.lock().await;
+    if let Some(traffic_log) = log.as_mut() {
+        if let Err(e) = traffic_log.write(direction, data).await {
+            log::warn!("Failed to write traffic log, disabling it: {}", e);
+            *log = None;
+        }
+    }
+}
+
+/// Flush the connection's traffic log, if logging is enabled
+pub(super) async fn flush(log: &SharedTrafficLog) {
+    if let Some(traffic_log) = log.lock().await.as_mut() {
+        let _ = traffic_log.flush().await;
+    }
+}
+
+/// SASL mechanism names logged as they are, next to the `SCRAM-*` family
+const SASL_MECHANISMS: &[&str] = &["PLAIN", "EXTERNAL"];
+
+/// The lines of a logged write or read, without their line endings and
+/// redacted if asked; a pre-framed send can hold several
+fn entry_lines(text: &str, redact_auth: bool) -> impl Iterator<Item = Cow<'_, str>> {
+    text.lines().map(move |line| {
+        if redact_auth {
+            redact_authenticate(line)
+        } else {
+            Cow::Borrowed(line)
+        }
+    })
+}
+
+/// Mask the payload of `AUTHENTICATE` lines, which carry SASL credentials
+///
+/// Mechanism names (`AUTHENTICATE PLAIN`), the empty `+` and the abort `*`
+/// are kept since they're useful when debugging and contain no secrets.
+fn redact_authenticate(line: &str) -> Cow<'_, str> {
+    // The command follows any `@tags` block and `:source`
+    let mut rest = line;
+    for marker in ['@', ':'] {
+        if rest.starts_with(marker) {
+            rest = rest.split_once(' ').map_or("", |(_, after)| after.trim_start_matches(' '));
+        }
+    }
+    let Some((command, payload)) = rest.split_once(' ') else {
+        return Cow::Borrowed(line);
+    };
+
+    let is_mechanism = SASL_MECHANISMS.contains(&payload) || payload.starts_with("SCRAM-");
+    if !command.eq_ignore_ascii_case("AUTHENTICATE") || payload == "+" || payload == "*" || is_mechanism {
+        return Cow::Borrowed(line);
+    }
+
+    let head = &line[..line.len() - rest.len()];
+    Cow::Owned(format!("{}{} <redacted>", head, command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_authenticate() {
+        assert_eq!(
+            redact_authenticate("AUTHENTICATE dXNlcgB1c2VyAHBhc3M="),
+            "AUTHENTICATE <redacted>"
+        );
+        assert_eq!(redact_authenticate("AUTHENTICATE PLAIN"), "AUTHENTICATE PLAIN");
+        assert_eq!(redact_authenticate("AUTHENTICATE +"), "AUTHENTICATE +");
+        assert_eq!(
+            redact_authenticate("PRIVMSG #chan :AUTHENTICATE abc"),
+            "PRIVMSG #chan :AUTHENTICATE abc"
+        );
+        // An all-caps chunk is still base64, not a mechanism
+        assert_eq!(redact_authenticate("AUTHENTICATE QUJDREVG"), "AUTHENTICATE <redacted>");
+        assert_eq!(redact_authenticate("AUTHENTICATE SCRAM-SHA-256"), "AUTHENTICATE SCRAM-SHA-256");
+        assert_eq!(
+            redact_authenticate("@label=x AUTHENTICATE dXNlcgB1c2VyAHBhc3M="),
+            "@label=x AUTHENTICATE <redacted>"
+        );
+        assert_eq!(redact_authenticate("@label=x :irc AUTHENTICATE +"), "@label=x :irc AUTHENTICATE +");
+    }
+
+    #[test]
+    fn test_entry_lines() {
+        let lines: Vec<_> = entry_lines("CAP END\r\nAUTHENTICATE dXNlcgB1c2VyAHBhc3M=\r\n", true).collect();
+        assert_eq!(lines, vec!["CAP END", "AUTHENTICATE <redacted>"]);
+        let lines: Vec<_> = entry_lines("AUTHENTICATE dXNlcgB1c2VyAHBhc3M=", false).collect();
+        assert_eq!(lines, vec!["AUTHENTICATE dXNlcgB1c2VyAHBhc3M="]);
+    }
+}
@@ -0,0 +1,83 @@
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::task;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// Size of the in-memory pipe between the WebSocket and the line tasks
+const BRIDGE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Open a WebSocket to `url` and expose it as a plain byte stream
+///
+/// Incoming text/binary frames are written to the returned stream with a
+/// trailing `\r\n` so the regular line buffering in `read_task` applies, and
+/// every line written to it is sent as its own text frame (without `\r\n`),
+/// as the IRCv3 WebSocket spec expects. Lines that aren't UTF-8 (another
+/// `encoding`, or raw bytes) go out as binary frames instead.
+pub(super) async fn connect(url: &str) -> Result<DuplexStream, String> {
+    let (ws_stream, _response) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(|e| format!("WebSocket handshake failed: {}", e))?;
+
+    let (local, remote) = tokio::io::duplex(BRIDGE_BUFFER_SIZE);
+    task::spawn(bridge(ws_stream, remote));
+    Ok(local)
+}
+
+/// Pump frames between the WebSocket and the in-memory pipe until either side closes
+async fn bridge<S>(ws_stream: WebSocketStream<S>, pipe: DuplexStream)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut ws_tx, mut ws_rx) = ws_stream.split();
+    let (pipe_rx, mut pipe_tx) = tokio::io::split(pipe);
+
+    let inbound = async {
+        while let Some(frame) = ws_rx.next().await {
+            let payload = match frame {
+                Ok(Message::Text(text)) => text.as_bytes().to_vec(),
+                Ok(Message::Binary(data)) => data.to_vec(),
+                Ok(Message::Close(_)) | Err(_) => break,
+                // Pings are answered by tungstenite itself
+                Ok(_) => continue,
+            };
+
+            if pipe_tx.write_all(&payload).await.is_err() {
+                break;
+            }
+            if !payload.ends_with(b"\r\n") && pipe_tx.write_all(b"\r\n").await.is_err() {
+                break;
+            }
+        }
+        // Signal EOF so read_task reports the disconnect
+        let _ = pipe_tx.shutdown().await;
+    };
+
+    let outbound = async {
+        let mut pipe_rx = BufReader::new(pipe_rx);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match pipe_rx.read_until(b'\n', &mut line).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            let payload = line.strip_suffix(b"\n").unwrap_or(&line);
+            let payload = payload.strip_suffix(b"\r").unwrap_or(payload).to_vec();
+            let message = match String::from_utf8(payload) {
+                Ok(text) => Message::text(text),
+                Err(e) => Message::binary(e.into_bytes()),
+            };
+            if ws_tx.send(message).await.is_err() {
+                break;
+            }
+        }
+        // write_task shut its side down, close the WebSocket cleanly
+        let _ = ws_tx.close().await;
+    };
+
+    tokio::select! {
+        _ = inbound => {}
+        _ = outbound => {}
+    }
+}
@@ -0,0 +1,214 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio::sync::oneshot;
+
+use super::irc::split_line;
+
+/// Everything a server said about a nick in its reply to `WHOIS`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WhoisReply {
+    /// The nick as the server spells it
+    nick: String,
+    /// False when the server answered `ERR_NOSUCHNICK`
+    exists: bool,
+    user: Option<String>,
+    host: Option<String>,
+    realname: Option<String>,
+    /// Server the nick is connected to, and its description
+    server: Option<String>,
+    server_info: Option<String>,
+    /// Services account the nick is logged in as
+    account: Option<String>,
+    /// Away message, when away
+    away: Option<String>,
+    operator: bool,
+    /// Connected over TLS
+    secure: bool,
+    idle_seconds: Option<u64>,
+    /// Connection time in Unix seconds
+    signon: Option<u64>,
+    /// Channels with their membership prefixes (`@#ops`), as sent
+    channels: Vec<String>,
+}
+
+/// A `WHOIS` sent by `whois` that is still collecting its reply
+#[derive(Debug)]
+pub(super) struct WhoisRequest {
+    reply: WhoisReply,
+    /// Every `whois` call waiting on this nick; one `WHOIS` serves them all
+    pub(super) waiters: Vec<oneshot::Sender<WhoisReply>>,
+}
+
+impl WhoisRequest {
+    pub(super) fn new(nick: &str, waiter: oneshot::Sender<WhoisReply>) -> Self {
+        WhoisRequest {
+            reply: WhoisReply {
+                nick: nick.to_string(),
+                exists: true,
+                ..Default::default()
+            },
+            waiters: vec![waiter],
+        }
+    }
+}
+
+/// Requests in flight on a connection, by `nick_key`
+pub(super) type PendingWhois = HashMap<String, WhoisRequest>;
+
+/// Key to match a nick by; nicks compare case-insensitively
+pub(super) fn nick_key(nick: &str) -> String {
+    nick.to_ascii_lowercase()
+}
+
+/// A `WHOIS` reply numeric, with the nick it is about
+#[derive(Debug, PartialEq)]
+pub(super) struct WhoisLine {
+    numeric: String,
+    key: String,
+    /// Parameters after `<client> <nick>`, `trailing` included
+    params: Vec<String>,
+}
+
+/// Numerics that make up a `WHOIS` reply
+const WHOIS_NUMERICS: &[&str] = &[
+    "301", "307", "311", "312", "313", "317", "318", "319", "320", "330", "338", "378", "379", "401", "671",
+];
+
+/// Pick out lines that belong to a `WHOIS` reply
+pub(super) fn parse_reply(line: &[u8]) -> Option<WhoisLine> {
+    let line = String::from_utf8_lossy(line);
+    let (numeric, mut params) = split_line(line.trim_end_matches(['\r', '\n']));
+    if !WHOIS_NUMERICS.contains(&numeric.as_str()) || params.len() < 2 {
+        return None;
+    }
+    let rest = params.split_off(2);
+    Some(WhoisLine {
+        numeric,
+        key: nick_key(&params[1]),
+        params: rest,
+    })
+}
+
+/// Feed `line` to the request waiting on its nick
+///
+/// Returns false if no request wants it, in which case the line is the
+/// frontend's. On the end of the reply the waiters get the result.
+pub(super) fn route(pending: &mut PendingWhois, line: WhoisLine) -> bool {
+    let Some(request) = pending.get_mut(&line.key) else {
+        return false;
+    };
+    let reply = &mut request.reply;
+    let param = |i: usize| line.params.get(i).cloned();
+    match line.numeric.as_str() {
+        // RPL_WHOISUSER: <user> <host> * :<realname>
+        "311" => {
+            reply.user = param(0);
+            reply.host = param(1);
+            reply.realname = param(3);
+        }
+        // RPL_WHOISSERVER: <server> :<server info>
+        "312" => {
+            reply.server = param(0);
+            reply.server_info = param(1);
+        }
+        // RPL_WHOISOPERATOR
+        "313" => reply.operator = true,
+        // RPL_WHOISIDLE: <secs> <signon> :seconds idle, signon time
+        "317" => {
+            reply.idle_seconds = param(0).and_then(|secs| secs.parse().ok());
+            reply.signon = param(1).and_then(|signon| signon.parse().ok());
+        }
+        // RPL_WHOISCHANNELS, which may repeat
+        "319" => reply
+            .channels
+            .extend(line.params.last().into_iter().flat_map(|channels| channels.split_whitespace().map(str::to_string))),
+        // RPL_WHOISACCOUNT: <account> :is logged in as
+        "330" => reply.account = param(0),
+        // RPL_AWAY
+        "301" => reply.away = param(0),
+        // RPL_WHOISSECURE
+        "671" => reply.secure = true,
+        // ERR_NOSUCHNICK; the end of the reply still follows
+        "401" => reply.exists = false,
+        // RPL_ENDOFWHOIS
+        "318" => {
+            if let Some(request) = pending.remove(&line.key) {
+                for waiter in request.waiters {
+                    let _ = waiter.send(request.reply.clone());
+                }
+            }
+        }
+        // Registered-nick, host and mode lines carry nothing we keep
+        _ => {}
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(pending: &mut PendingWhois, line: &str) -> bool {
+        route(pending, parse_reply(line.as_bytes()).expect(line))
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let line = parse_reply(b":irc.example.net 312 me Alice irc.example.net :Example server\r\n").unwrap();
+        assert_eq!(line.key, "alice");
+        assert_eq!(line.params, vec!["irc.example.net", "Example server"]);
+        assert_eq!(parse_reply(b":irc.example.net 001 me :Welcome"), None);
+        assert_eq!(parse_reply(b":irc.example.net 318 me"), None);
+    }
+
+    #[test]
+    fn test_collects_reply_until_end() {
+        let (tx, mut rx) = oneshot::channel();
+        let mut pending = PendingWhois::new();
+        pending.insert(nick_key("Alice"), WhoisRequest::new("Alice", tx));
+
+        assert!(feed(&mut pending, ":s 311 me Alice ~alice host.example * :Alice Liddell"));
+        assert!(feed(&mut pending, ":s 319 me Alice :@#ops +#chat"));
+        assert!(feed(&mut pending, ":s 319 me Alice :#more"));
+        assert!(feed(&mut pending, ":s 312 me Alice irc.example.net :Example server"));
+        assert!(feed(&mut pending, ":s 671 me Alice :is using a secure connection"));
+        assert!(feed(&mut pending, ":s 317 me Alice 42 1700000000 :seconds idle, signon time"));
+        assert!(feed(&mut pending, ":s 330 me Alice alice :is logged in as"));
+        // Replies about other nicks are not ours
+        assert!(!feed(&mut pending, ":s 311 me Bob ~bob host * :Bob"));
+        assert!(rx.try_recv().is_err());
+
+        assert!(feed(&mut pending, ":s 318 me alice :End of /WHOIS list."));
+        assert!(pending.is_empty());
+        let reply = rx.try_recv().unwrap();
+        assert_eq!(
+            reply,
+            WhoisReply {
+                nick: "Alice".to_string(),
+                exists: true,
+                user: Some("~alice".to_string()),
+                host: Some("host.example".to_string()),
+                realname: Some("Alice Liddell".to_string()),
+                server: Some("irc.example.net".to_string()),
+                server_info: Some("Example server".to_string()),
+                account: Some("alice".to_string()),
+                away: None,
+                operator: false,
+                secure: true,
+                idle_seconds: Some(42),
+                signon: Some(1700000000),
+                channels: vec!["@#ops".to_string(), "+#chat".to_string(), "#more".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_no_such_nick() {
+        let (tx, mut rx) = oneshot::channel();
+        let mut pending = PendingWhois::new();
+        pending.insert(nick_key("ghost"), WhoisRequest::new("ghost", tx));
+        assert!(feed(&mut pending, ":s 401 me ghost :No such nick/channel"));
+        assert!(feed(&mut pending, ":s 318 me ghost :End of /WHOIS list."));
+        assert!(!rx.try_recv().unwrap().exists);
+    }
+}
@@ -0,0 +1,145 @@
+use serde::Deserialize;
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+/// Id of the one tray icon the app creates
+#[cfg(desktop)]
+const TRAY_ID: &str = "main";
+
+/// Tooltip shown when there is nothing unread
+#[cfg(desktop)]
+const TOOLTIP: &str = "ObsidianIRC";
+
+/// What closing the main window does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloseBehavior {
+    /// Hide the window and keep connections running in the tray
+    Tray,
+    /// Exit the app
+    #[default]
+    Quit,
+}
+
+/// Current close behavior, set from the frontend's settings
+#[derive(Default)]
+pub struct CloseBehaviorState(Mutex<CloseBehavior>);
+
+/// Choose whether closing the window hides it to the tray or quits
+#[tauri::command]
+pub fn set_close_behavior(state: State<'_, CloseBehaviorState>, behavior: CloseBehavior) {
+    *state.0.lock().unwrap_or_else(|e| e.into_inner()) = behavior;
+}
+
+/// Hide the main window instead of closing it when set to `tray`
+/// Hiding leaves the read tasks running, so messages keep arriving
+#[cfg(desktop)]
+pub fn handle_window_event(window: &tauri::Window, event: &tauri::WindowEvent) {
+    use tauri::Manager;
+
+    let tauri::WindowEvent::CloseRequested { api, .. } = event else {
+        return;
+    };
+    if window.label() != "main" {
+        return;
+    }
+    let state = window.state::<CloseBehaviorState>();
+    let behavior = *state.0.lock().unwrap_or_else(|e| e.into_inner());
+    if behavior == CloseBehavior::Tray && window.app_handle().tray_by_id(TRAY_ID).is_some() {
+        api.prevent_close();
+        let _ = window.hide();
+    }
+}
+
+/// Create the tray icon with its Show/Hide and Quit menu
+/// Menu picks are emitted as `tray-action` (payload: the item id) before
+/// the default action runs; left-click toggles the main window
+#[cfg(desktop)]
+pub fn setup(app: &tauri::App) -> tauri::Result<()> {
+    use tauri::menu::{Menu, MenuItem};
+    use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+    use tauri::Emitter;
+
+    let show_hide = MenuItem::with_id(app, "show_hide", "Show/Hide", true, None::<&str>)?;
+    let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&show_hide, &quit_item])?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(TOOLTIP)
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(|app, event| {
+            let action = event.id().as_ref();
+            if let Err(e) = app.emit("tray-action", action) {
+                log::error!("Failed to emit tray-action: {}", e);
+            }
+            match action {
+                "show_hide" => toggle_main_window(app),
+                // The only way out once closing hides to the tray; the
+                // exit hook sends QUIT on every connection first
+                "quit" => app.exit(0),
+                _ => {}
+            }
+        })
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                toggle_main_window(tray.app_handle());
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+    Ok(())
+}
+
+/// Hide the main window if it is showing, otherwise bring it back
+#[cfg(desktop)]
+fn toggle_main_window(app: &AppHandle) {
+    use tauri::Manager;
+
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    if window.is_visible().unwrap_or(false) && !window.is_minimized().unwrap_or(false) {
+        let _ = window.hide();
+    } else {
+        crate::deep_link::focus_main_window(app);
+    }
+}
+
+/// Tooltip (and macOS menu bar title) text for `count` unread highlights
+#[cfg(desktop)]
+fn badge_text(count: u32) -> String {
+    match count {
+        0 => TOOLTIP.to_string(),
+        1 => format!("{} - 1 unread highlight", TOOLTIP),
+        n => format!("{} - {} unread highlights", TOOLTIP, n),
+    }
+}
+
+/// Reflect the total unread highlight count on the tray icon
+/// A no-op on mobile, which has no tray
+#[tauri::command]
+pub fn set_tray_badge(app: AppHandle, count: u32) -> Result<(), String> {
+    #[cfg(desktop)]
+    {
+        let Some(tray) = app.tray_by_id(TRAY_ID) else {
+            return Ok(());
+        };
+        tray.set_tooltip(Some(badge_text(count)))
+            .map_err(|e| format!("Failed to update tray: {}", e))?;
+        // Only macOS draws a title next to the icon
+        #[cfg(target_os = "macos")]
+        tray.set_title((count > 0).then(|| count.to_string()))
+            .map_err(|e| format!("Failed to update tray: {}", e))?;
+    }
+    #[cfg(mobile)]
+    let _ = (app, count);
+    Ok(())
+}
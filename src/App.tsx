@@ -1,4 +1,5 @@
-import { onOpenUrl } from "@tauri-apps/plugin-deep-link";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import {
   isPermissionGranted,
   requestPermission,
@@ -22,9 +23,8 @@ import UserSettings from "./components/ui/UserSettings";
 import { useKeyboardResize } from "./hooks/useKeyboardResize";
 import { useUpdateCheck } from "./hooks/useUpdateCheck";
 import ircClient from "./lib/ircClient";
-import { parseIrcUrl } from "./lib/ircUrlParser";
 import useStore, { loadSavedServers } from "./store";
-import type { ConnectionDetails } from "./store/types";
+import type { ConnectionDetails, IrcUrl } from "./store/types";
 
 const askPermissions = async () => {
   // Do you have permission to send a notification?
@@ -271,44 +271,46 @@ const App: React.FC = () => {
     connectToSavedServers,
   ]); // Removed connectToSavedServers from dependencies
 
-  // Handle deeplinks
+  // Handle deeplinks, parsed by the backend
   useEffect(() => {
-    const setupDeepLinkHandler = async () => {
-      if (typeof window === "undefined" || !window.__TAURI__) {
-        return;
-      }
+    if (typeof window === "undefined" || !window.__TAURI__) {
+      return;
+    }
 
-      try {
-        // Register handler for when app is already running
-        await onOpenUrl((urls) => {
-          console.log("Deep link received:", urls);
-
-          for (const url of urls) {
-            if (url.startsWith("irc://") || url.startsWith("ircs://")) {
-              try {
-                // Parse the IRC URL
-                const parsed = parseIrcUrl(url);
-
-                // Open the connect modal with pre-filled details
-                toggleAddServerModal(true, {
-                  name: parsed.host || "IRC Server",
-                  host: parsed.host,
-                  port: parsed.port.toString(),
-                  nickname: parsed.nick || "user",
-                  useIrcProtocol: true,
-                });
-              } catch (error) {
-                console.error("Failed to parse IRC URL:", error);
-              }
-            }
-          }
+    const handleUrls = (urls: IrcUrl[]) => {
+      console.log("Deep link received:", urls);
+
+      for (const url of urls) {
+        // Open the connect modal with pre-filled details
+        toggleAddServerModal(true, {
+          name: url.host || "IRC Server",
+          host: url.host,
+          port: url.port.toString(),
+          nickname: "user",
+          useIrcProtocol: true,
         });
-      } catch (error) {
-        console.error("Failed to setup deep link handler:", error);
       }
     };
 
-    setupDeepLinkHandler();
+    // Register handler for when app is already running
+    const unlisten = listen<IrcUrl>("open-irc-url", (event) => {
+      handleUrls([event.payload]);
+    });
+    // Links that arrived before this handler existed (cold start)
+    unlisten
+      .then(() => invoke<IrcUrl[]>("take_pending_deep_links"))
+      .then((pending) => {
+        if (pending.length > 0) {
+          handleUrls(pending);
+        }
+      })
+      .catch((error) => {
+        console.error("Failed to setup deep link handler:", error);
+      });
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
   }, [toggleAddServerModal]);
 
   // Global keyboard shortcut for Quick Actions (Cmd+K / Ctrl+K)
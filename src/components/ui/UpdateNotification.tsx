@@ -35,6 +35,16 @@ export const UpdateNotification: React.FC = () => {
               <p className="text-sm font-medium truncate">
                 v{updateInfo.version} available
               </p>
+              {updateInfo.updateMethod === "package_manager" && (
+                <p className="text-xs text-blue-100 truncate">
+                  Update through your package manager
+                </p>
+              )}
+              {updateInfo.updateMethod === "store" && (
+                <p className="text-xs text-blue-100 truncate">
+                  Update through your app store
+                </p>
+              )}
             </div>
           </div>
 
@@ -1,8 +1,12 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { useCallback, useEffect, useRef } from "react";
 import useStore from "../store";
 import type { UpdateInfo } from "../store/types";
 
+// Interval for background update checks while auto-check is enabled
+const UPDATE_POLL_INTERVAL_SECS = 6 * 60 * 60;
+
 // LocalStorage key for persisting update info
 const UPDATE_STORAGE_KEY = "obsidianirc-update-info";
 
@@ -52,12 +56,12 @@ function persistUpdateInfo(updateInfo: UpdateInfo | null): void {
 }
 
 /**
- * Open a URL in the system browser
+ * Open a release or download URL in the system browser
+ * On Tauri the backend checks it's an https URL on the release host first
  */
 async function openUrl(url: string): Promise<void> {
   if (window.__TAURI__) {
-    const { openUrl: tauriOpenUrl } = await import("@tauri-apps/plugin-opener");
-    await tauriOpenUrl(url);
+    await invoke("open_update_url", { url });
   } else {
     window.open(url, "_blank", "noopener,noreferrer");
   }
@@ -207,6 +211,41 @@ export function useUpdateCheck() {
     return () => clearTimeout(timer);
   }, [servers, checkForUpdates, updateState.updateAvailable]);
 
+  // Keep checking in the background while auto-check is enabled
+  useEffect(() => {
+    if (typeof window === "undefined" || !window.__TAURI__) {
+      return;
+    }
+    if (!autoCheckUpdates) {
+      invoke("stop_update_polling").catch(() => {});
+      return;
+    }
+
+    const unlisten = listen<UpdateInfo>("update-available", (event) => {
+      console.log("[UpdateCheck] Background check found:", event.payload);
+      setState((state) => ({
+        updateState: {
+          ...state.updateState,
+          updateAvailable: true,
+          updateInfo: event.payload,
+          lastChecked: new Date().toISOString(),
+          error: null,
+        },
+      }));
+      persistUpdateInfo(event.payload);
+    });
+    invoke("start_update_polling", {
+      intervalSecs: UPDATE_POLL_INTERVAL_SECS,
+    }).catch((error) =>
+      console.error("[UpdateCheck] Failed to start polling:", error),
+    );
+
+    return () => {
+      unlisten.then((fn) => fn());
+      invoke("stop_update_polling").catch(() => {});
+    };
+  }, [autoCheckUpdates, setState]);
+
   const downloadUpdate = useCallback(async () => {
     if (!updateState.updateInfo) {
       return;
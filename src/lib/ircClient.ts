@@ -596,7 +596,7 @@ export class IRCClient {
         // Don't start ping timer here - wait for 001 welcome message
         // to ensure connection is fully established before sending PINGs
 
-        socket.onclose = () => {
+        socket.onclose = (event) => {
           if (!this.servers.has(server.id)) {
             return;
           }
@@ -611,7 +611,8 @@ export class IRCClient {
             connectionState: "disconnected",
           });
           this.pendingConnections.delete(connectionKey);
-          if (!wasReconnecting) {
+          // A deliberate disconnect must not be undone by auto-reconnect
+          if (!wasReconnecting && event?.reason !== "user") {
             this.startReconnection(
               server.id,
               name,
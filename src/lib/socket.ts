@@ -3,17 +3,38 @@
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 
+// Why a connection closed: our own disconnect, the server, or a failure
+export type CloseReason = "user" | "server" | "error";
+
 export interface ISocket {
   onopen: (() => void) | null;
   onmessage: ((event: { data: string }) => void) | null;
   onerror: ((error: Error) => void) | null;
-  onclose: (() => void) | null;
+  onclose: ((event?: { reason?: CloseReason }) => void) | null;
 
   send(data: string): void;
   close(): void;
   readyState: number;
 }
 
+// Connection progress reported by the backend while connecting and closing
+export type ConnectionPhase =
+  | "resolving"
+  | "tcp_connected"
+  | "tls_handshaking"
+  | "tls_established"
+  | "ready"
+  | "closing"
+  | "closed";
+
+// Backend socket commands reject with { kind, message }
+function describeError(error: unknown): string {
+  if (error && typeof error === "object" && "message" in error) {
+    return String((error as { message: unknown }).message);
+  }
+  return String(error);
+}
+
 export class TCPSocket implements ISocket {
   private clientId: string;
   private isConnected = false;
@@ -23,7 +44,8 @@ export class TCPSocket implements ISocket {
   public onopen: (() => void) | null = null;
   public onmessage: ((event: { data: string }) => void) | null = null;
   public onerror: ((error: Error) => void) | null = null;
-  public onclose: (() => void) | null = null;
+  public onclose: ((event?: { reason?: CloseReason }) => void) | null = null;
+  public onphase: ((phase: ConnectionPhase) => void) | null = null;
 
   constructor(address: string) {
     this.clientId = Math.random().toString(36).substring(2, 15);
@@ -37,12 +59,18 @@ export class TCPSocket implements ISocket {
           message?: { data: number[] };
           error?: string;
           connected?: boolean;
+          phase?: ConnectionPhase;
+          reason?: CloseReason;
         };
       };
 
       // Only handle messages for this client
       if (payload.id !== this.clientId) return;
 
+      if (payload.event.phase) {
+        this.onphase?.(payload.event.phase);
+      }
+
       if (payload.event.message) {
         // Convert byte array to string
         const data = new TextDecoder().decode(
@@ -58,7 +86,7 @@ export class TCPSocket implements ISocket {
       if (payload.event.connected === false) {
         this.isConnected = false;
         this._readyState = 3; // CLOSED
-        this.onclose?.();
+        this.onclose?.({ reason: payload.event.reason });
         this.unlisten?.();
         this.unlisten = undefined;
       }
@@ -83,7 +111,9 @@ export class TCPSocket implements ISocket {
       })
       .catch((error: unknown) => {
         this._readyState = 3; // CLOSED
-        this.onerror?.(new Error(`Failed to connect: ${error}`));
+        this.onerror?.(
+          new Error(`Failed to connect: ${describeError(error)}`),
+        );
       });
   }
 
@@ -98,7 +128,9 @@ export class TCPSocket implements ISocket {
 
     invoke("send", { clientId: this.clientId, data }).catch(
       (error: unknown) => {
-        this.onerror?.(new Error(`Failed to send data: ${error}`));
+        this.onerror?.(
+          new Error(`Failed to send data: ${describeError(error)}`),
+        );
       },
     );
   }
@@ -110,12 +142,14 @@ export class TCPSocket implements ISocket {
         .then(() => {
           this.isConnected = false;
           this._readyState = 3; // CLOSED
-          this.onclose?.();
+          this.onclose?.({ reason: "user" });
           this.unlisten?.();
           this.unlisten = undefined;
         })
         .catch((error: unknown) => {
-          this.onerror?.(new Error(`Failed to disconnect: ${error}`));
+          this.onerror?.(
+            new Error(`Failed to disconnect: ${describeError(error)}`),
+          );
         });
     }
   }
@@ -127,7 +161,8 @@ export class WebSocketWrapper implements ISocket {
   public onopen: (() => void) | null = null;
   public onmessage: ((event: { data: string }) => void) | null = null;
   public onerror: ((error: Error) => void) | null = null;
-  public onclose: (() => void) | null = null;
+  public onclose: ((event?: { reason?: CloseReason }) => void) | null = null;
+  public onphase: ((phase: ConnectionPhase) => void) | null = null;
 
   constructor(url: string) {
     this.socket = new WebSocket(url);
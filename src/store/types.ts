@@ -66,6 +66,18 @@ export interface UpdateInfo {
   downloadUrl: string;
   releaseNotes: string;
   publishedAt: string;
+  sha256?: string | null;
+  combinedNotes: string;
+  updateMethod: "in_app" | "package_manager" | "store";
+}
+
+// An irc:// or ircs:// link parsed by the backend (deep links)
+export interface IrcUrl {
+  address: string;
+  host: string;
+  port: number;
+  tls: boolean;
+  channels: Array<{ name: string; key: string | null }>;
 }
 
 export interface UpdateState {